@@ -1,18 +1,90 @@
-import { useEffect, useState } from 'react';
+import { useEffect, useState, type KeyboardEvent } from 'react';
+import type { CrashReport } from '@/types/events';
 import styles from './DebugPanel.module.css';
 
 interface DebugPanelProps {
   location: string;
   gitHash: string;
   wasmChecksum: string;
+  crashReport?: CrashReport | null;
+  onTraceChange?: (capacity: number) => void;
+  getTraceText?: () => string;
+  debugOutput?: string;
+  onDebugCommand?: (command: string) => void;
 }
 
-export function DebugPanel({ location, gitHash, wasmChecksum }: DebugPanelProps) {
+// Instructions kept while tracing
+const TRACE_SIZE = 256;
+
+export function DebugPanel({
+  location,
+  gitHash,
+  wasmChecksum,
+  crashReport,
+  onTraceChange,
+  getTraceText,
+  debugOutput,
+  onDebugCommand,
+}: DebugPanelProps) {
+  const [tracing, setTracing] = useState(false);
+  const [command, setCommand] = useState('');
+
+  const handleDebugKey = (e: KeyboardEvent<HTMLInputElement>) => {
+    if (e.key !== 'Enter') return;
+    e.preventDefault();
+    onDebugCommand?.(command || 'help');
+    setCommand('');
+  };
+
+  const toggleTrace = () => {
+    onTraceChange?.(tracing ? 0 : TRACE_SIZE);
+    setTracing(!tracing);
+  };
+
+  const copyTrace = () => {
+    navigator.clipboard?.writeText(getTraceText?.() ?? '');
+  };
+
+  // Everything a bug report needs, including which build hit the fault
+  const copyCrashReport = () => {
+    const report = { ...crashReport, gitHash, wasmChecksum };
+    navigator.clipboard?.writeText(JSON.stringify(report, null, 2));
+  };
+
   return (
     <div className={styles.debugPanel}>
       <span className={styles.debugItem}>Location: {location || '—'}</span>
       <span className={styles.debugItem}>Git: {gitHash}</span>
       <span className={styles.debugItem}>WASM: {wasmChecksum}</span>
+      {onTraceChange && (
+        <button type="button" className={styles.panelButton} onClick={toggleTrace} aria-pressed={tracing}>
+          Trace: {tracing ? 'on' : 'off'}
+        </button>
+      )}
+      {tracing && getTraceText && (
+        <button type="button" className={styles.panelButton} onClick={copyTrace}>
+          Copy trace
+        </button>
+      )}
+      {onDebugCommand && (
+        <input
+          type="text"
+          className={styles.debugInput}
+          value={command}
+          onChange={(e) => setCommand(e.target.value)}
+          onKeyDown={handleDebugKey}
+          placeholder="(debug)"
+          aria-label="Debugger command"
+          spellCheck={false}
+          autoComplete="off"
+        />
+      )}
+      {crashReport && (
+        <button type="button" className={styles.panelButton} onClick={copyCrashReport}>
+          Copy crash report
+        </button>
+      )}
+      {debugOutput && <pre className={styles.debugOutput}>{debugOutput}</pre>}
     </div>
   );
 }
@@ -6,15 +6,36 @@ import { TerminalControls } from './TerminalControls';
 import { SaveLoadDialog } from './SaveLoadDialog';
 import { HintModal } from './HintModal';
 import { DebugPanel } from './DebugPanel';
+import type { CrashReport, DebugStop } from '@/types/events';
 import styles from './Terminal.module.css';
 
+// Instructions per animation frame, small enough to keep a frame short
+const RUN_BUDGET = 50_000;
+
+// Debugger commands that let the story run again
+const RESUME_COMMANDS = new Set(['step', 's', 'next', 'n', 'finish', 'f', 'continue', 'c']);
+
+function describeStop(stop: DebugStop): string {
+  const hex = (value: number) => `0x${value.toString(16)}`;
+  const words = (values: number[]) => values.map((value) => value.toString(16).padStart(4, '0')).join(' ');
+
+  switch (stop.reason) {
+    case 'breakpoint':
+      return `Breakpoint ${stop.id} at ${hex(stop.pc)}`;
+    case 'watchpoint':
+      return `Watchpoint ${stop.id}: [${words(stop.old)}] -> [${words(stop.new)}] by ${hex(stop.changed_by)}`;
+    case 'step':
+      return `Stepped to ${hex(stop.pc)}`;
+  }
+}
+
 export function Terminal() {
   const {
     isLoading,
     error,
     isInitialized,
     feed,
-    step,
+    run,
     getUpdates,
     getLocation,
     getHintsForLocation,
@@ -23,6 +44,10 @@ export function Terminal() {
     redo,
     save,
     restore,
+    setTrace,
+    getTraceText,
+    debugCommand,
+    debugLocation,
   } = useWasm();
 
   const {
@@ -46,6 +71,10 @@ export function Terminal() {
   const [hintModalOpen, setHintModalOpen] = useState(false);
   const [currentLocation, setCurrentLocation] = useState('');
   const [totalHintsShown, setTotalHintsShown] = useState(0);
+  const [isBusy, setIsBusy] = useState(false);
+  const [crashReport, setCrashReport] = useState<CrashReport | null>(null);
+  const [debugOutput, setDebugOutput] = useState('');
+  const [isStopped, setIsStopped] = useState(false);
   const [showDebug, setShowDebug] = useState(() => {
     if (typeof window !== 'undefined') {
       return new URLSearchParams(window.location.search).get('debug') === '1';
@@ -96,21 +125,34 @@ export function Terminal() {
   }, []);
 
   // Process game updates
-  const processUpdates = useCallback(() => {
+  const processUpdates = useCallback(async () => {
     if (!isInitialized) return;
 
-    // Step the game
-    let hasMore = true;
-    let iterations = 0;
-    const maxIterations = 100;
+    // Run the game in slices, one per animation frame, so long computations
+    // show the busy indicator instead of freezing the tab
+    let result = run(RUN_BUDGET);
+    while (result.outcome === 'yielded') {
+      setIsBusy(true);
+      await new Promise((resolve) => requestAnimationFrame(resolve));
+      result = run(RUN_BUDGET);
+    }
+    setIsBusy(false);
+
+    if (result.outcome === 'error') {
+      addLine(`[Interpreter error: ${result.message}]`);
+    }
 
-    while (hasMore && iterations < maxIterations) {
-      hasMore = step();
-      iterations++;
+    // Stopped by the debugger, the DebugPanel lets it go on
+    setIsStopped(result.outcome === 'break');
+    if (result.outcome === 'break') {
+      setDebugOutput(`[${describeStop(result.message)}]\n${debugLocation()}`);
     }
 
     // Get and display updates
     const updates = getUpdates();
+    if (updates?.crash) {
+      setCrashReport(updates.crash);
+    }
     if (updates) {
       const filterLine = (line: string) => {
         if (!line) return false;
@@ -165,7 +207,7 @@ export function Terminal() {
         addLine(updates.message);
       }
     }
-  }, [isInitialized, step, getUpdates, addLine, addLines]);
+  }, [isInitialized, run, getUpdates, addLine, addLines]);
 
   // Initial game setup
   useEffect(() => {
@@ -178,10 +220,10 @@ export function Terminal() {
       addLine('Type commands and press ENTER to interact with the game.');
       addLine('Press Ctrl+L to clear screen. Hover top of screen for controls.');
       addLine('');
-      processUpdates();
-      // Fetch initial location from game state
-      const location = getLocation();
-      setCurrentLocation(location);
+      processUpdates().then(() => {
+        // Fetch initial location from game state
+        setCurrentLocation(getLocation());
+      });
     }
   }, [isInitialized, addLine, processUpdates, getLocation]);
 
@@ -204,7 +246,7 @@ export function Terminal() {
   }, [isInitialized, hintModalOpen]);
 
   // Handle command submission
-  const handleSubmit = useCallback(() => {
+  const handleSubmit = useCallback(async () => {
     const trimmedInput = input.trim().toLowerCase();
 
     // Check for hint/help commands
@@ -216,7 +258,7 @@ export function Terminal() {
       if (isInitialized) {
         // Feed the command to keep game state consistent, then get location for hint modal
         feed(trimmedInput);
-        processUpdates();
+        await processUpdates();
         const location = getLocation();
         setCurrentLocation(location);
         setHintModalOpen(true);
@@ -233,7 +275,7 @@ export function Terminal() {
     if (isInitialized) {
       // Always feed input to the game, even if empty (some games require just Enter)
       feed(input.trim());
-      processUpdates();
+      await processUpdates();
       // Update current location after each command
       const location = getLocation();
       setCurrentLocation(location);
@@ -244,6 +286,7 @@ export function Terminal() {
   const handleKeyDown = useCallback((e: KeyboardEvent<HTMLInputElement>) => {
     if (e.key === 'Enter') {
       e.preventDefault();
+      if (isBusy || isStopped) return;
       handleSubmit();
       scrollToBottom();
     } else if (e.key === 'ArrowUp') {
@@ -264,13 +307,26 @@ export function Terminal() {
       e.preventDefault();
       clearScreen();
     }
-  }, [handleSubmit, navigateHistory, clearScreen, scrollToBottom]);
+  }, [isBusy, isStopped, handleSubmit, navigateHistory, clearScreen, scrollToBottom]);
+
+  const handleDebugCommand = useCallback(async (command: string) => {
+    setDebugOutput(debugCommand(command));
+
+    const [name] = command.trim().split(/\s+/);
+    if (RESUME_COMMANDS.has(name)) {
+      await processUpdates();
+    } else {
+      // going back leaves the story in the middle of a turn, an empty run
+      // only yields when it isn't waiting for input
+      setIsStopped(run(0).outcome === 'yielded');
+    }
+  }, [debugCommand, processUpdates, run]);
 
   // Control handlers
-  const handleUndo = useCallback(() => {
+  const handleUndo = useCallback(async () => {
     if (undo()) {
       addLine('[UNDO]');
-      processUpdates();
+      await processUpdates();
       const location = getLocation();
       setCurrentLocation(location);
     } else {
@@ -278,10 +334,10 @@ export function Terminal() {
     }
   }, [undo, addLine, processUpdates, getLocation]);
 
-  const handleRedo = useCallback(() => {
+  const handleRedo = useCallback(async () => {
     if (redo()) {
       addLine('[REDO]');
-      processUpdates();
+      await processUpdates();
       const location = getLocation();
       setCurrentLocation(location);
     } else {
@@ -299,17 +355,17 @@ export function Terminal() {
     setSaveDialogOpen(false);
   }, [save, saveToSlot, addLine]);
 
-  const handleLoad = useCallback((slotName: string) => {
+  const handleLoad = useCallback(async (slotName: string) => {
     const saveData = loadFromSlot(slotName);
     if (saveData && restore(saveData)) {
       addLine(`[Game loaded from slot: ${slotName}]`);
-      processUpdates();
+      await processUpdates();
       // Replay the last command to show context
       const lastCommand = getLastCommand();
       if (lastCommand) {
         addLine(`> ${lastCommand}`, true);
         feed(lastCommand);
-        processUpdates();
+        await processUpdates();
       }
       // Update location after load
       const location = getLocation();
@@ -368,6 +424,11 @@ export function Terminal() {
         </label>
         <div className={styles.inputContainer}>
           <span className={styles.inputPrompt} aria-hidden="true">&gt;</span>
+          {isBusy && (
+            <span className={styles.busy} role="status">
+              Thinking…
+            </span>
+          )}
           <input
             id="game-input"
             ref={inputRef}
@@ -422,6 +483,11 @@ export function Terminal() {
           location={currentLocation}
           gitHash={typeof __GIT_HASH__ !== 'undefined' ? __GIT_HASH__ : 'unknown'}
           wasmChecksum={wasmChecksum}
+          crashReport={crashReport}
+          onTraceChange={setTrace}
+          getTraceText={getTraceText}
+          debugOutput={debugOutput}
+          onDebugCommand={handleDebugCommand}
         />
       )}
     </div>
@@ -1,11 +1,12 @@
 import { useState, useEffect, useCallback, useRef } from 'react';
-import { setWasmModule } from '../env-shim';
+import { PROTOCOL_VERSION, type CrashReport, type QueuedEvent, type StepOutcome, type TraceRecord } from '../types/events';
 
 interface WasmExports {
   memory: WebAssembly.Memory;
   create: () => void;
   feed: (input: string) => void;
   step: () => boolean;
+  run: (budget: number) => string;
   get_updates: () => void;
   get_room_ascii_art: () => string | undefined;
   get_location: () => string;
@@ -16,8 +17,13 @@ interface WasmExports {
   save: () => string | undefined;
   restore: (data: string) => void;
   load_savestate: (data: string) => void;
-  get_messages: () => string;
-  clear_messages: () => void;
+  poll_events: () => string;
+  protocol_version: () => number;
+  set_output_format: (format: 'html' | 'tokens') => boolean;
+  set_trace: (capacity: number) => void;
+  get_trace: (format: 'text' | 'json') => string;
+  debug: (command: string) => string;
+  debug_location: () => string;
 }
 
 interface GameUpdate {
@@ -25,6 +31,7 @@ interface GameUpdate {
   lines?: string[];
   output?: string;
   message?: string;
+  crash?: CrashReport;
 }
 
 export function useWasm() {
@@ -51,15 +58,14 @@ export function useWasm() {
         console.log('WASM module initialized successfully');
         console.log('[useWasm] WASM instance exports:', Object.keys(wasmInstance));
 
-        // Create a wrapper with both the module and instance for env-shim
-        const wasmWithMemory = {
-          ...wasmModule,
-          memory: wasmInstance.memory,
-        };
-        setWasmModule(wasmWithMemory);
-
         wasmRef.current = wasmModule as unknown as WasmExports;
 
+        // A stale wasm build would send events these types don't describe
+        const version = wasmRef.current.protocol_version();
+        if (version !== PROTOCOL_VERSION) {
+          console.warn(`[useWasm] Event protocol ${version} doesn't match ${PROTOCOL_VERSION}, rebuild the WASM module`);
+        }
+
         // Initialize the game
         try {
           console.log('Calling create()');
@@ -103,6 +109,12 @@ export function useWasm() {
     return wasmRef.current.step();
   }, []);
 
+  // Run at most `budget` instructions, 'yielded' means call again
+  const run = useCallback((budget: number): StepOutcome => {
+    if (!wasmRef.current) return { outcome: 'error', message: 'WASM module not loaded' };
+    return JSON.parse(wasmRef.current.run(budget));
+  }, []);
+
   // Get updates from the game
   const getUpdates = useCallback((): GameUpdate | null => {
     if (!wasmRef.current) return null;
@@ -110,26 +122,23 @@ export function useWasm() {
     // Call get_updates to trigger message processing
     wasmRef.current.get_updates();
 
-    // Get messages from WASM message store
+    // Drain the event queue, every print since the last poll is kept
     try {
-      const messagesJson = wasmRef.current.get_messages();
-      const messages = JSON.parse(messagesJson);
-
-      // Clear the store for next time
-      wasmRef.current.clear_messages();
-
-      // Return the messages
-      if (messages.print) {
-        return { output: messages.print };
-      }
-      if (messages.map) {
-        return { output: messages.map };
+      const events: QueuedEvent[] = JSON.parse(wasmRef.current.poll_events());
+      const prints = events.flatMap((event) => (event.type === 'print' ? [event.data] : []));
+      const crashes = events.flatMap((event) => (event.type === 'crash' ? [event.data] : []));
+
+      // The engine already printed what happened, the report is for bug reports
+      const crash = crashes[crashes.length - 1];
+      if (crash) {
+        console.error('[useWasm] Interpreter fault:', crash);
       }
-      if (Object.keys(messages).length > 0) {
-        return { output: JSON.stringify(messages) };
+
+      if (prints.length > 0 || crash) {
+        return { output: prints.length > 0 ? prints.join('<br>') : undefined, crash };
       }
     } catch (e) {
-      console.error('[useWasm] Error processing messages:', e);
+      console.error('[useWasm] Error processing events:', e);
     }
 
     return null;
@@ -190,12 +199,43 @@ export function useWasm() {
     return wasmRef.current.get_hint_answer(questionIdx, level);
   }, []);
 
+  // Trace the last `capacity` instructions, 0 turns tracing off
+  const setTrace = useCallback((capacity: number): void => {
+    if (!wasmRef.current) return;
+    wasmRef.current.set_trace(capacity);
+  }, []);
+
+  // Get the instruction trace, oldest first
+  const getTrace = useCallback((): TraceRecord[] => {
+    if (!wasmRef.current) return [];
+    return JSON.parse(wasmRef.current.get_trace('json'));
+  }, []);
+
+  // Same trace as one disassembled line per instruction
+  const getTraceText = useCallback((): string => {
+    if (!wasmRef.current) return '';
+    return wasmRef.current.get_trace('text');
+  }, []);
+
+  // Run a debugger command, after step/next/finish/continue run the story again
+  const debugCommand = useCallback((command: string): string => {
+    if (!wasmRef.current) return '';
+    return wasmRef.current.debug(command);
+  }, []);
+
+  // Next instruction and the current frame
+  const debugLocation = useCallback((): string => {
+    if (!wasmRef.current) return '';
+    return wasmRef.current.debug_location();
+  }, []);
+
   return {
     isLoading,
     error,
     isInitialized,
     feed,
     step,
+    run,
     getUpdates,
     getRoomAsciiArt,
     getLocation,
@@ -205,5 +245,10 @@ export function useWasm() {
     redo,
     save,
     restore,
+    setTrace,
+    getTrace,
+    getTraceText,
+    debugCommand,
+    debugLocation,
   };
 }
@@ -0,0 +1,40 @@
+// Generated from encrusted/src/rust/events.rs, do not edit.
+// Regenerate with: UPDATE_TYPES=1 cargo test --test typescript
+
+export const PROTOCOL_VERSION = 2;
+
+export type QueuedEvent = { seq: number, } & ({ "type": "print", "data": string } | { "type": "tokens", "data": Array<TextRun> } | { "type": "header", "data": [string, string] } | { "type": "map", "data": [number, string] } | { "type": "tree", "data": GameObject } | { "type": "save", "data": [string, string] } | { "type": "savestate", "data": [string, string] } | { "type": "restore" } | { "type": "crash", "data": CrashReport });
+
+export type HostEvent = { "type": "print", "data": string } | { "type": "tokens", "data": Array<TextRun> } | { "type": "header", "data": [string, string] } | { "type": "map", "data": [number, string] } | { "type": "tree", "data": GameObject } | { "type": "save", "data": [string, string] } | { "type": "savestate", "data": [string, string] } | { "type": "restore" } | { "type": "crash", "data": CrashReport };
+
+export type TextRun = { kind: RunKind, text: string, style: RunStyle, };
+
+export type RunKind = "text" | "newline" | "object" | "debug" | "ascii_art";
+
+export type RunStyle = "plain" | "room" | "object" | "debug" | "preformatted";
+
+export type GameObject = { number: number, name: string, children: Array<GameObject>, };
+
+export type StepOutcome = { "outcome": "needs_line" } | { "outcome": "needs_char" } | { "outcome": "needs_restore_data" } | { "outcome": "yielded" } | { "outcome": "quit" } | { "outcome": "error", "message": string } | { "outcome": "break", "message": DebugStop };
+
+export type CrashReport = { message: string, pc: number, 
+/**
+ * Name of the instruction at the pc, if it was decoded
+ */
+opcode: string | null, 
+/**
+ * The last instructions before the fault, oldest first
+ */
+recent: Array<string>, last_command: string | null, 
+/**
+ * False when there was no earlier state to go back to
+ */
+recovered: boolean, };
+
+export type TraceRecord = { addr: number, opcode: string, text: string, args: Array<number>, result: number | null, };
+
+export type DebugStop = { "reason": "breakpoint", id: number, pc: number, } | { "reason": "watchpoint", id: number, pc: number, 
+/**
+ * Address of the instruction that made the change
+ */
+changed_by: number, old: Array<number>, new: Array<number>, } | { "reason": "step", pc: number, };
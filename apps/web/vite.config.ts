@@ -22,20 +22,9 @@ export default defineConfig(({ mode }) => ({
   resolve: {
     alias: {
       "@": path.resolve(__dirname, "./src"),
-      "env": path.resolve(__dirname, "./src/env-shim.js"),
     },
   },
   define: {
     __GIT_HASH__: JSON.stringify(gitHash),
   },
-  build: {
-    rollupOptions: {
-      external: ["env"],
-      output: {
-        globals: {
-          env: "globalThis.env",
-        },
-      },
-    },
-  },
 }));
@@ -0,0 +1,228 @@
+//! Headless benchmarks: fixed command scripts run against the bundled h2g2
+//! with a capturing `WebUI`, doing per turn what the web app does (run to
+//! the next prompt, rebuild the object tree and status bar, flush the
+//! output). Reports instructions per second, allocations per turn, the time
+//! spent in `run`, `get_object_tree` and `flush`, and the peak memory held
+//! by the undo history.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::events::HostEvent;
+use crate::game::Game;
+use crate::options::Options;
+use crate::ui_web::{VecSink, WebUI};
+use crate::zmachine::{StepOutcome, Zmachine};
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts allocations for the reports. Binaries opt in with
+/// `#[global_allocator] static ALLOC: CountingAllocator = CountingAllocator;`,
+/// without it allocations are reported as 0
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Allocations (and reallocations) since the program started
+pub fn allocation_count() -> usize {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Script {
+    pub name: &'static str,
+    pub commands: &'static [&'static str],
+}
+
+pub const SCRIPTS: [Script; 3] = [
+    // the first part of the game, up to Ford and the bulldozer
+    Script {
+        name: "opening",
+        commands: &[
+            "turn on light", "get up", "get gown", "wear gown", "open pocket", "take pill", "eat pill",
+            "get screwdriver", "get toothbrush", "s", "get mail", "read mail", "s",
+            "lie down in front of bulldozer", "wait", "wait", "wait", "inventory", "look",
+        ],
+    },
+    // the cheapest turns, mostly the story's clock
+    Script {
+        name: "wait",
+        commands: &["wait"; 30],
+    },
+    // the parser: separators, unknown words, several objects at once
+    Script {
+        name: "parser",
+        commands: &[
+            "turn on light", "get up", "examine the thing, then look", "xyzzy", "take all",
+            "drop all", "take all. inventory", "examine gown. examine pocket", "frobnicate the gown",
+            "look", "look", "look",
+        ],
+    },
+];
+
+impl Script {
+    pub fn find(name: &str) -> Option<Script> {
+        SCRIPTS.iter().find(|script| script.name == name).cloned()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub script: String,
+    /// How many times the script was run, each on a fresh story
+    pub runs: usize,
+    /// The opening (up to the first prompt) and each command, for all runs
+    pub turns: usize,
+    pub instructions: u64,
+    /// Instructions over the time spent in `run`
+    pub instructions_per_second: f64,
+    pub allocations_per_turn: f64,
+    pub step_ms: f64,
+    pub object_tree_ms: f64,
+    pub flush_ms: f64,
+    pub total_ms: f64,
+    pub peak_history_bytes: usize,
+}
+
+#[derive(Default)]
+struct Totals {
+    turns: usize,
+    instructions: u64,
+    allocations: usize,
+    step: Duration,
+    object_tree: Duration,
+    flush: Duration,
+    total: Duration,
+    peak_history: usize,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// Runs to the next prompt and does the web app's updates (see push_updates
+// in the wasm crate)
+fn turn(zvm: &mut Zmachine, sink: &VecSink, totals: &mut Totals) {
+    let allocations = allocation_count();
+    let instructions = zvm.instruction_count();
+    let started = Instant::now();
+
+    let outcome = zvm.run(usize::MAX);
+    let ran = Instant::now();
+    assert!(
+        !matches!(outcome, StepOutcome::Error(_) | StepOutcome::Quit),
+        "Benchmark script stopped the story: {:?}",
+        outcome
+    );
+
+    let (number, name) = zvm.get_current_room();
+    let tree_started = Instant::now();
+    let tree = zvm.get_object_tree();
+    let tree_done = Instant::now();
+
+    zvm.update_status_bar();
+    zvm.ui.event(HostEvent::Map(number, name));
+    zvm.ui.event(HostEvent::Tree(tree));
+
+    let flush_started = Instant::now();
+    zvm.ui.flush();
+    let done = Instant::now();
+
+    // the host would have taken the messages by now
+    sink.take();
+
+    totals.turns += 1;
+    totals.instructions += zvm.instruction_count() - instructions;
+    totals.allocations += allocation_count() - allocations;
+    totals.step += ran - started;
+    totals.object_tree += tree_done - tree_started;
+    totals.flush += done - flush_started;
+    totals.total += done - started;
+    totals.peak_history = totals.peak_history.max(zvm.history_size());
+}
+
+/// Runs `script` `runs` times, each on a freshly loaded story
+pub fn run_script(script: &Script, runs: usize) -> BenchReport {
+    let mut totals = Totals::default();
+
+    for _ in 0..runs {
+        let sink = VecSink::new();
+        let ui = WebUI::with_sink(Box::new(sink.clone()));
+        let mut zvm = Game::load_story(Game::story_data().to_vec(), ui, Options::default());
+
+        turn(&mut zvm, &sink, &mut totals);
+
+        for command in script.commands {
+            zvm.handle_input(command.to_string());
+            turn(&mut zvm, &sink, &mut totals);
+        }
+    }
+
+    let step_seconds = totals.step.as_secs_f64();
+
+    BenchReport {
+        script: script.name.to_string(),
+        runs,
+        turns: totals.turns,
+        instructions: totals.instructions,
+        instructions_per_second: if step_seconds > 0.0 { totals.instructions as f64 / step_seconds } else { 0.0 },
+        allocations_per_turn: totals.allocations as f64 / totals.turns.max(1) as f64,
+        step_ms: millis(totals.step),
+        object_tree_ms: millis(totals.object_tree),
+        flush_ms: millis(totals.flush),
+        total_ms: millis(totals.total),
+        peak_history_bytes: totals.peak_history,
+    }
+}
+
+impl BenchReport {
+    /// One row per report
+    pub fn table(reports: &[BenchReport]) -> String {
+        let mut text = String::new();
+
+        writeln!(
+            text,
+            "{:10} {:>6} {:>10} {:>12} {:>11} {:>10} {:>10} {:>10} {:>10} {:>12}",
+            "script", "turns", "instrs", "instrs/sec", "allocs/turn", "run ms", "tree ms", "flush ms", "total ms", "peak history"
+        )
+        .unwrap();
+
+        for report in reports {
+            writeln!(
+                text,
+                "{:10} {:>6} {:>10} {:>12.0} {:>11.1} {:>10.2} {:>10.2} {:>10.2} {:>10.2} {:>12}",
+                report.script,
+                report.turns,
+                report.instructions,
+                report.instructions_per_second,
+                report.allocations_per_turn,
+                report.step_ms,
+                report.object_tree_ms,
+                report.flush_ms,
+                report.total_ms,
+                report.peak_history_bytes
+            )
+            .unwrap();
+        }
+
+        text
+    }
+}
@@ -0,0 +1,64 @@
+extern crate clap;
+extern crate encrusted;
+
+use std::process;
+
+use clap::{value_parser, Arg, ArgAction, Command};
+
+use encrusted::bench::{run_script, BenchReport, CountingAllocator, Script, SCRIPTS};
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn main() {
+    let names: Vec<&str> = SCRIPTS.iter().map(|script| script.name).collect();
+
+    let matches = Command::new("encrusted-bench")
+        .version(VERSION)
+        .about("Runs scripted h2g2 turns headlessly and reports interpreter performance")
+        .arg(
+            Arg::new("script")
+                .long("script")
+                .value_name("NAME")
+                .action(ArgAction::Append)
+                .help(format!("Script to run, repeatable (defaults to all: {})", names.join(", "))),
+        )
+        .arg(
+            Arg::new("runs")
+                .long("runs")
+                .value_name("N")
+                .value_parser(value_parser!(usize))
+                .default_value("5")
+                .help("Times to run each script, on a fresh story each time"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Prints the reports as JSON"),
+        )
+        .get_matches();
+
+    let scripts: Vec<Script> = match matches.get_many::<String>("script") {
+        Some(names) => names
+            .map(|name| {
+                Script::find(name).unwrap_or_else(|| {
+                    eprintln!("\nUnknown script: {}\n", name);
+                    process::exit(1);
+                })
+            })
+            .collect(),
+        None => SCRIPTS.to_vec(),
+    };
+
+    let runs = *matches.get_one::<usize>("runs").unwrap();
+    let reports: Vec<BenchReport> = scripts.iter().map(|script| run_script(script, runs)).collect();
+
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string(&reports).unwrap());
+    } else {
+        print!("{}", BenchReport::table(&reports));
+    }
+}
@@ -0,0 +1,82 @@
+extern crate clap;
+extern crate encrusted;
+
+use std::fs;
+use std::process;
+
+use clap::{Arg, ArgAction, Command};
+
+use encrusted::coverage::story_id;
+use encrusted::{disassemble, Coverage, CoverageReport, Game, Options, StoryHeader, VecSink, WebUI};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn main() {
+    let matches = Command::new("encrusted-disasm")
+        .version(VERSION)
+        .about("Disassembles a story file's routines and strings (defaults to the bundled h2g2)")
+        .arg(Arg::new("FILE").help("Story file to disassemble"))
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Prints the disassembly as JSON"),
+        )
+        .arg(
+            Arg::new("coverage")
+                .long("coverage")
+                .value_name("COVERAGE")
+                .action(ArgAction::Append)
+                .help("Prints what files from encrusted-cli --coverage reached instead (merged if repeated)"),
+        )
+        .get_matches();
+
+    let story = match matches.get_one::<String>("FILE") {
+        Some(path) => fs::read(path).unwrap_or_else(|err| {
+            eprintln!("\nCouldn't read story file {}: {}\n", path, err);
+            process::exit(1);
+        }),
+        None => Game::story_data().to_vec(),
+    };
+
+    let header = StoryHeader::parse(&story).unwrap_or_else(|err| {
+        eprintln!("\n{}\nIs this a z-code story file?\n", err);
+        process::exit(1);
+    });
+
+    let ui = WebUI::with_sink(Box::new(VecSink::new()));
+    let zvm = Game::load_story(story, ui, Options::default());
+    let disassembly = disassemble(&zvm);
+
+    if let Some(paths) = matches.get_many::<String>("coverage") {
+        let mut coverage = Coverage::new(&header, false);
+
+        for path in paths {
+            let merged = fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|json| Coverage::from_json(&json))
+                .and_then(|saved| coverage.merge(&saved));
+
+            if let Err(err) = merged {
+                eprintln!("\nCouldn't read coverage {} for story {}: {}\n", path, story_id(&header), err);
+                process::exit(1);
+            }
+        }
+
+        let report = CoverageReport::new(&disassembly, &coverage);
+
+        if matches.get_flag("json") {
+            println!("{}", report.to_json());
+        } else {
+            print!("{}", report.to_text());
+        }
+
+        return;
+    }
+
+    if matches.get_flag("json") {
+        println!("{}", disassembly.to_json());
+    } else {
+        print!("{}", disassembly.to_text());
+    }
+}
@@ -0,0 +1,81 @@
+extern crate clap;
+extern crate encrusted;
+
+use std::fs;
+use std::process;
+
+use clap::{Arg, ArgAction, Command};
+
+use encrusted::inspector::Section;
+use encrusted::{inspect, Game, Options, StoryHeader, VecSink, WebUI};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn main() {
+    let matches = Command::new("encrusted-inspect")
+        .version(VERSION)
+        .about("Dumps a story file's header, dictionary, objects, abbreviations and strings (defaults to the bundled h2g2)")
+        .arg(Arg::new("FILE").help("Story file to inspect"))
+        .arg(
+            Arg::new("section")
+                .long("section")
+                .value_name("NAME")
+                .action(ArgAction::Append)
+                .value_parser(["header", "dictionary", "objects", "abbreviations", "strings"])
+                .help("Prints only this section, can be given more than once"),
+        )
+        .arg(
+            Arg::new("find")
+                .long("find")
+                .value_name("NAME")
+                .conflicts_with_all(["section", "json"])
+                .help("Lists the objects whose name contains NAME, with their numbers"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("section")
+                .help("Prints everything as JSON"),
+        )
+        .get_matches();
+
+    let story = match matches.get_one::<String>("FILE") {
+        Some(path) => fs::read(path).unwrap_or_else(|err| {
+            eprintln!("\nCouldn't read story file {}: {}\n", path, err);
+            process::exit(1);
+        }),
+        None => Game::story_data().to_vec(),
+    };
+
+    if let Err(err) = StoryHeader::parse(&story) {
+        eprintln!("\n{}\nIs this a z-code story file?\n", err);
+        process::exit(1);
+    }
+
+    let ui = WebUI::with_sink(Box::new(VecSink::new()));
+    let zvm = Game::load_story(story, ui, Options::default());
+    let info = inspect(&zvm).unwrap_or_else(|err| {
+        eprintln!("\nCouldn't read the story: {}\n", err);
+        process::exit(1);
+    });
+
+    if let Some(name) = matches.get_one::<String>("find") {
+        for object in info.find_objects(name) {
+            println!("{:3}. \"{}\" (parent {})", object.number, object.name, object.parent);
+        }
+        return;
+    }
+
+    if matches.get_flag("json") {
+        println!("{}", info.to_json());
+        return;
+    }
+
+    let sections: Vec<Section> = match matches.get_many::<String>("section") {
+        Some(names) => names.filter_map(|name| Section::parse(name)).collect(),
+        None => Section::ALL.to_vec(),
+    };
+
+    print!("{}", info.to_text(&sections));
+}
@@ -0,0 +1,74 @@
+extern crate clap;
+extern crate encrusted;
+
+use std::fs;
+use std::process;
+
+use clap::{Arg, ArgAction, Command};
+
+use encrusted::{read_save, Game, SaveInfo, StoryHeader};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn open(story: &[u8], path: &str) -> SaveInfo {
+    let text = fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("\nCouldn't read save {}: {}\n", path, err);
+        process::exit(1);
+    });
+
+    read_save(story, &text).unwrap_or_else(|err| {
+        eprintln!("\n{}: {}\n", path, err);
+        process::exit(1);
+    })
+}
+
+fn main() {
+    let matches = Command::new("encrusted-save")
+        .version(VERSION)
+        .about("Checks a base64 save from the web app and shows its chunks and frames, or what changed between two saves")
+        .arg(Arg::new("SAVE").required(true).help("File with a base64 save"))
+        .arg(Arg::new("LATER").help("A later save to diff against"))
+        .arg(
+            Arg::new("story")
+                .long("story")
+                .value_name("FILE")
+                .help("Story the saves are from (defaults to the bundled h2g2)"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Prints the save or the diff as JSON"),
+        )
+        .get_matches();
+
+    let story = match matches.get_one::<String>("story") {
+        Some(path) => fs::read(path).unwrap_or_else(|err| {
+            eprintln!("\nCouldn't read story file {}: {}\n", path, err);
+            process::exit(1);
+        }),
+        None => Game::story_data().to_vec(),
+    };
+
+    if let Err(err) = StoryHeader::parse(&story) {
+        eprintln!("\n{}\nIs this a z-code story file?\n", err);
+        process::exit(1);
+    }
+
+    let save = open(&story, matches.get_one::<String>("SAVE").unwrap());
+    let json = matches.get_flag("json");
+
+    match matches.get_one::<String>("LATER") {
+        Some(path) => {
+            let diff = save.diff(&open(&story, path));
+
+            if json {
+                println!("{}", serde_json::to_string(&diff).unwrap());
+            } else {
+                print!("{}", diff.to_text());
+            }
+        }
+        None if json => println!("{}", serde_json::to_string(&save).unwrap()),
+        None => print!("{}", save.to_text()),
+    }
+}
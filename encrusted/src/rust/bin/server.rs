@@ -0,0 +1,100 @@
+extern crate clap;
+extern crate encrusted;
+extern crate tiny_http;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use clap::{value_parser, Arg, Command};
+
+use encrusted::server::{self, ServerConfig};
+use encrusted::{Game, StoryHeader};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn main() {
+    let matches = Command::new("encrusted-server")
+        .version(VERSION)
+        .about("Hosts game sessions over HTTP and WebSockets (defaults to the bundled h2g2)")
+        .arg(Arg::new("FILE").help("Story file to serve"))
+        .arg(
+            Arg::new("addr")
+                .long("addr")
+                .value_name("HOST:PORT")
+                .default_value("127.0.0.1:8080")
+                .help("Address to listen on"),
+        )
+        .arg(
+            Arg::new("autosave")
+                .long("autosave")
+                .value_name("DIR")
+                .help("Saves sessions to this directory so they survive eviction and restarts"),
+        )
+        .arg(
+            Arg::new("idle-timeout")
+                .long("idle-timeout")
+                .value_name("SECS")
+                .value_parser(value_parser!(u64))
+                .default_value("1800")
+                .help("Evicts sessions that have been idle this long"),
+        )
+        .arg(
+            Arg::new("max-sessions")
+                .long("max-sessions")
+                .value_name("N")
+                .value_parser(value_parser!(usize))
+                .default_value("256")
+                .help("Refuses new sessions while this many are running"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("N")
+                .value_parser(value_parser!(u32))
+                .help("Seeds every session's random number generator"),
+        )
+        .arg(
+            Arg::new("allow-origin")
+                .long("allow-origin")
+                .value_name("ORIGIN")
+                .help("Lets pages from this origin call the server (sent as Access-Control-Allow-Origin)"),
+        )
+        .after_help("See PROTOCOL.md for the routes and the JSON protocol.")
+        .get_matches();
+
+    let story = match matches.get_one::<String>("FILE") {
+        Some(path) => fs::read(path).unwrap_or_else(|err| {
+            eprintln!("\nCouldn't read story file {}: {}\n", path, err);
+            process::exit(1);
+        }),
+        None => Game::story_data().to_vec(),
+    };
+
+    if let Err(err) = StoryHeader::parse(&story) {
+        eprintln!("\n{}\nIs this a z-code story file?\n", err);
+        process::exit(1);
+    }
+
+    let config = ServerConfig {
+        story,
+        autosave_dir: matches.get_one::<String>("autosave").map(PathBuf::from),
+        idle_timeout: Duration::from_secs(*matches.get_one::<u64>("idle-timeout").unwrap()),
+        seed: matches.get_one::<u32>("seed").cloned(),
+        allowed_origin: matches.get_one::<String>("allow-origin").cloned(),
+        max_sessions: *matches.get_one::<usize>("max-sessions").unwrap(),
+    };
+
+    let addr = matches.get_one::<String>("addr").unwrap();
+    let http = match tiny_http::Server::http(addr) {
+        Ok(http) => http,
+        Err(err) => {
+            eprintln!("\nCouldn't listen on {}: {}\n", addr, err);
+            process::exit(1);
+        }
+    };
+
+    println!("Listening on http://{}", http.server_addr());
+    server::serve(http, config);
+}
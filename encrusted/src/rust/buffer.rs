@@ -1,3 +1,60 @@
+use std::fmt;
+
+// Header bytes a game may write: Flags 2 (standard section 11.1)
+const GAME_WRITABLE_HEADER: [usize; 2] = [0x10, 0x11];
+const HEADER_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Header,
+    Dynamic,
+    Static,
+    High,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Region::Header => "header",
+            Region::Dynamic => "dynamic memory",
+            Region::Static => "static memory",
+            Region::High => "high memory",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryError {
+    /// A read or write of `length` bytes at `addr` runs past the end
+    OutOfBounds { addr: usize, length: usize, size: usize },
+    /// A game write to a header byte only the interpreter may change
+    ReadOnlyHeader(usize),
+    /// A game write above the end of dynamic memory
+    ReadOnly { addr: usize, region: Region },
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MemoryError::OutOfBounds { addr, length, size } => write!(
+                f,
+                "Memory access of {} bytes at {:#x} is past the end of memory ({:#x})",
+                length, addr, size
+            ),
+            MemoryError::ReadOnlyHeader(addr) => {
+                write!(f, "Illegal write to header byte {:#x}", addr)
+            }
+            MemoryError::ReadOnly { addr, region } => {
+                write!(f, "Illegal write to {} at {:#x}", region, addr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MemoryError {}
+
 #[derive(Debug)]
 pub struct Reader<'a> {
     buffer: &'a Buffer,
@@ -5,6 +62,19 @@ pub struct Reader<'a> {
 }
 
 impl<'a> Reader<'a> {
+    /// For reads at addresses that come from the story
+    pub fn try_byte(&mut self) -> Result<u8, MemoryError> {
+        let byte = self.buffer.try_read_byte(self.cursor)?;
+        self.cursor += 1;
+        Ok(byte)
+    }
+
+    pub fn try_word(&mut self) -> Result<u16, MemoryError> {
+        let word = self.buffer.try_read_word(self.cursor)?;
+        self.cursor += 2;
+        Ok(word)
+    }
+
     pub fn byte(&mut self) -> u8 {
         let byte = self.buffer.read_byte(self.cursor);
         self.cursor += 1;
@@ -55,26 +125,142 @@ impl<'a> Writer<'a> {
 #[derive(Debug)]
 pub struct Buffer {
     buf: Vec<u8>,
+    // old values of written bytes, while journaling
+    journal: Option<Vec<(usize, u8)>>,
+    // where dynamic memory ends and high memory starts, everything is
+    // dynamic until set_regions is called
+    static_start: usize,
+    high_start: usize,
 }
 
 impl Buffer {
     pub fn new(buf: Vec<u8>) -> Buffer {
-        Buffer { buf }
+        let len = buf.len();
+
+        Buffer {
+            buf,
+            journal: None,
+            static_start: len,
+            high_start: len,
+        }
+    }
+
+    /// Sets the region boundaries from the header's static and high memory
+    /// addresses
+    pub fn set_regions(&mut self, static_start: usize, high_start: usize) {
+        self.static_start = static_start.min(self.buf.len());
+        self.high_start = high_start.max(self.static_start).min(self.buf.len());
+    }
+
+    pub fn static_start(&self) -> usize {
+        self.static_start
+    }
+
+    pub fn high_start(&self) -> usize {
+        self.high_start
     }
 
     pub fn len(&self) -> usize {
         self.buf.len()
     }
 
+    /// Which region `location` is in, high memory wins where the header
+    /// puts it below the end of static memory
+    pub fn region(&self, location: usize) -> Region {
+        if location < HEADER_LENGTH.min(self.static_start) {
+            Region::Header
+        } else if location < self.static_start {
+            Region::Dynamic
+        } else if location < self.high_start {
+            Region::Static
+        } else {
+            Region::High
+        }
+    }
+
+    fn check_bounds(&self, location: usize, length: usize) -> Result<(), MemoryError> {
+        match location.checked_add(length) {
+            Some(end) if end <= self.buf.len() => Ok(()),
+            _ => Err(MemoryError::OutOfBounds {
+                addr: location,
+                length,
+                size: self.buf.len(),
+            }),
+        }
+    }
+
+    /// Checks that a game may write `length` bytes at `location`: inside
+    /// dynamic memory, and only the header bytes games are allowed to change
+    pub fn check_write(&self, location: usize, length: usize) -> Result<(), MemoryError> {
+        self.check_bounds(location, length)?;
+
+        for addr in location..location + length {
+            match self.region(addr) {
+                Region::Dynamic => (),
+                Region::Header if GAME_WRITABLE_HEADER.contains(&addr) => (),
+                Region::Header => return Err(MemoryError::ReadOnlyHeader(addr)),
+                region => return Err(MemoryError::ReadOnly { addr, region }),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn try_read_byte(&self, location: usize) -> Result<u8, MemoryError> {
+        self.check_bounds(location, 1)?;
+        Ok(self.buf[location])
+    }
+
+    pub fn try_read_word(&self, location: usize) -> Result<u16, MemoryError> {
+        self.check_bounds(location, 2)?;
+        Ok((u16::from(self.buf[location]) << 8) + u16::from(self.buf[location + 1]))
+    }
+
+    pub fn try_read(&self, location: usize, length: usize) -> Result<&[u8], MemoryError> {
+        self.check_bounds(location, length)?;
+        Ok(&self.buf[location..location + length])
+    }
+
+    // The unchecked reads are for addresses the interpreter worked out
+    // itself, they panic with the MemoryError rather than a slice index
     pub fn read_byte(&self, location: usize) -> u8 {
-        self.buf[location]
+        self.try_read_byte(location).unwrap_or_else(|err| panic!("{}", err))
     }
 
     pub fn read_word(&self, location: usize) -> u16 {
-        (u16::from(self.buf[location]) << 8) + u16::from(self.buf[location + 1])
+        self.try_read_word(location).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// A game write, checked against the regions
+    pub fn store_byte(&mut self, location: usize, value: u8) -> Result<(), MemoryError> {
+        self.check_write(location, 1)?;
+        self.write_byte(location, value);
+        Ok(())
+    }
+
+    pub fn store_word(&mut self, location: usize, value: u16) -> Result<(), MemoryError> {
+        self.check_write(location, 2)?;
+        self.write_word(location, value);
+        Ok(())
+    }
+
+    pub fn store(&mut self, location: usize, buf: &[u8]) -> Result<(), MemoryError> {
+        self.check_write(location, buf.len())?;
+        self.write(location, buf);
+        Ok(())
     }
 
+    // Interpreter writes (restores, header setup, undo) skip the region
+    // checks but not the bounds
     pub fn write_byte(&mut self, location: usize, value: u8) {
+        if let Err(err) = self.check_bounds(location, 1) {
+            panic!("{}", err);
+        }
+
+        if let Some(ref mut journal) = self.journal {
+            journal.push((location, self.buf[location]));
+        }
+
         self.buf[location] = value;
     }
 
@@ -82,12 +268,22 @@ impl Buffer {
         let top = ((value & 0xFF00) >> 8) as u8;
         let bottom = (value & 0x00FF) as u8;
 
-        self.buf[location] = top;
-        self.buf[location + 1] = bottom;
+        self.write_byte(location, top);
+        self.write_byte(location + 1, bottom);
+    }
+
+    /// Starts noting the old value of every byte written
+    pub fn start_journal(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    /// Stops journaling, returns the old values in the order they were written
+    pub fn take_journal(&mut self) -> Vec<(usize, u8)> {
+        self.journal.take().unwrap_or_default()
     }
 
     pub fn read(&self, location: usize, length: usize) -> &[u8] {
-        &self.buf[location..location + length]
+        self.try_read(location, length).unwrap_or_else(|err| panic!("{}", err))
     }
 
     pub fn write(&mut self, location: usize, buf: &[u8]) {
@@ -97,7 +293,7 @@ impl Buffer {
     }
 
     pub fn slice(&self, start: usize, end: usize) -> &[u8] {
-        &self.buf[start..end]
+        self.read(start, end.saturating_sub(start))
     }
 
     pub fn get_reader(&self, cursor: usize) -> Reader<'_> {
@@ -0,0 +1,289 @@
+//! Story code coverage: the routines, instructions and branch directions a
+//! play session reached, and the strings print_addr/print_paddr printed.
+//! Sessions of the same story merge, and the report lines them up with the
+//! disassembly so prints that never ran (endings, rooms, hints) show up by
+//! routine address with their text.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::disassembler::{Disassembly, StoryString};
+use crate::header::StoryHeader;
+
+/// Counts nothing until it's enabled
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Coverage {
+    #[serde(skip)]
+    enabled: bool,
+    // release, serial and checksum, only the same story's coverage merges
+    story: String,
+    // routines called (the main routine never is)
+    routines: BTreeSet<usize>,
+    instructions: BTreeSet<usize>,
+    // branch instructions with the way they went: true if they branched
+    branches: BTreeSet<(usize, bool)>,
+    // strings printed by print_addr and print_paddr
+    strings: BTreeSet<usize>,
+}
+
+pub fn story_id(header: &StoryHeader) -> String {
+    format!(
+        "{}.{}.{:04x}",
+        header.release,
+        String::from_utf8_lossy(&header.serial),
+        header.checksum
+    )
+}
+
+impl Coverage {
+    pub fn new(header: &StoryHeader, enabled: bool) -> Coverage {
+        Coverage {
+            enabled,
+            story: story_id(header),
+            ..Coverage::default()
+        }
+    }
+
+    /// Coverage saved by `to_json`, to merge into
+    pub fn from_json(json: &str) -> Result<Coverage, String> {
+        serde_json::from_str(json).map_err(|err| format!("Invalid coverage file: {}", err))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turning it off keeps what was reached so far
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn story(&self) -> &str {
+        &self.story
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Adds another session's coverage, which has to be of the same story
+    pub fn merge(&mut self, other: &Coverage) -> Result<(), String> {
+        if self.story != other.story {
+            return Err(format!(
+                "Coverage is of story {}, not {}",
+                other.story, self.story
+            ));
+        }
+
+        self.routines.extend(&other.routines);
+        self.instructions.extend(&other.instructions);
+        self.branches.extend(&other.branches);
+        self.strings.extend(&other.strings);
+
+        Ok(())
+    }
+
+    pub fn record_instruction(&mut self, addr: usize) {
+        if self.enabled {
+            self.instructions.insert(addr);
+        }
+    }
+
+    pub fn record_call(&mut self, routine: usize) {
+        if self.enabled {
+            self.routines.insert(routine);
+        }
+    }
+
+    pub fn record_branch(&mut self, addr: usize, branched: bool) {
+        if self.enabled {
+            self.branches.insert((addr, branched));
+        }
+    }
+
+    pub fn record_string(&mut self, addr: usize) {
+        if self.enabled {
+            self.strings.insert(addr);
+        }
+    }
+
+    pub fn reached_instruction(&self, addr: usize) -> bool {
+        self.instructions.contains(&addr)
+    }
+
+    /// Whether the branch at `addr` ever went the way `branched` says
+    pub fn reached_branch(&self, addr: usize, branched: bool) -> bool {
+        self.branches.contains(&(addr, branched))
+    }
+
+    pub fn printed_string(&self, addr: usize) -> bool {
+        self.strings.contains(&addr)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutineCoverage {
+    pub addr: usize,
+    pub main: bool,
+    pub reached: bool,
+    pub instructions: usize,
+    pub instructions_reached: usize,
+    /// Two for each branch instruction, one for each way it can go
+    pub branches: usize,
+    pub branches_reached: usize,
+    /// print, print_ret and print_addr/print_paddr of a constant
+    pub prints: usize,
+    pub prints_reached: usize,
+    /// The disassembly of the prints that never ran
+    pub unreached_prints: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub story: String,
+    /// By address
+    pub routines: Vec<RoutineCoverage>,
+    /// The strings in high memory, printed from constants or from tables
+    pub strings: usize,
+    pub strings_reached: usize,
+    pub unreached_strings: Vec<StoryString>,
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    count as f64 * 100.0 / total.max(1) as f64
+}
+
+fn is_print(name: &str) -> bool {
+    name == "print" || name == "print_ret"
+}
+
+impl CoverageReport {
+    /// Lines `coverage` up with the disassembly of its story
+    pub fn new(disassembly: &Disassembly, coverage: &Coverage) -> CoverageReport {
+        let routines = disassembly
+            .routines
+            .iter()
+            .map(|routine| {
+                let mut report = RoutineCoverage {
+                    addr: routine.addr,
+                    main: routine.main,
+                    reached: coverage.routines.contains(&routine.addr),
+                    instructions: routine.instructions.len(),
+                    instructions_reached: 0,
+                    branches: 0,
+                    branches_reached: 0,
+                    prints: 0,
+                    prints_reached: 0,
+                    unreached_prints: Vec::new(),
+                };
+
+                for instr in &routine.instructions {
+                    let reached = coverage.reached_instruction(instr.addr);
+
+                    if reached {
+                        report.reached = true;
+                        report.instructions_reached += 1;
+                    }
+
+                    if instr.branch {
+                        report.branches += 2;
+                        report.branches_reached += usize::from(coverage.reached_branch(instr.addr, true))
+                            + usize::from(coverage.reached_branch(instr.addr, false));
+                    }
+
+                    if is_print(&instr.name) || instr.string.is_some() {
+                        report.prints += 1;
+
+                        if reached {
+                            report.prints_reached += 1;
+                        } else {
+                            report.unreached_prints.push(instr.to_text());
+                        }
+                    }
+                }
+
+                report
+            })
+            .collect();
+
+        let unreached_strings: Vec<StoryString> = disassembly
+            .strings
+            .iter()
+            .filter(|string| !coverage.printed_string(string.addr))
+            .cloned()
+            .collect();
+
+        CoverageReport {
+            story: coverage.story.clone(),
+            routines,
+            strings: disassembly.strings.len(),
+            strings_reached: disassembly.strings.len() - unreached_strings.len(),
+            unreached_strings,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let total = |count: fn(&RoutineCoverage) -> usize| self.routines.iter().map(count).sum::<usize>();
+
+        let lines = [
+            ("routines", self.routines.iter().filter(|routine| routine.reached).count(), self.routines.len()),
+            ("instructions", total(|routine| routine.instructions_reached), total(|routine| routine.instructions)),
+            ("branches", total(|routine| routine.branches_reached), total(|routine| routine.branches)),
+            ("prints", total(|routine| routine.prints_reached), total(|routine| routine.prints)),
+            ("strings", self.strings_reached, self.strings),
+        ];
+
+        writeln!(text, "; coverage of story {}", self.story).unwrap();
+
+        for (name, reached, total) in lines {
+            writeln!(text, "; {:12} {:>6}/{:<6} {:>6.2}%", name, reached, total, percent(reached, total)).unwrap();
+        }
+
+        for routine in &self.routines {
+            let kind = if routine.main { "Main routine" } else { "Routine" };
+
+            if routine.reached {
+                writeln!(
+                    text,
+                    "\n{} {:05x}: {}/{} instructions, {}/{} branches, {}/{} prints",
+                    kind,
+                    routine.addr,
+                    routine.instructions_reached,
+                    routine.instructions,
+                    routine.branches_reached,
+                    routine.branches,
+                    routine.prints_reached,
+                    routine.prints
+                )
+                .unwrap();
+            } else {
+                writeln!(text, "\n{} {:05x}: not reached", kind, routine.addr).unwrap();
+            }
+
+            for print in &routine.unreached_prints {
+                writeln!(text, "{}", print).unwrap();
+            }
+        }
+
+        if !self.unreached_strings.is_empty() {
+            writeln!(text, "\n[Strings not printed]\n").unwrap();
+        }
+
+        for string in &self.unreached_strings {
+            writeln!(text, "{:5x}: \"{}\"", string.addr, string.text).unwrap();
+        }
+
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
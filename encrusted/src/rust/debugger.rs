@@ -0,0 +1,334 @@
+//! Breakpoints, watchpoints and stepping for `Zmachine::run`. The CLI and the
+//! web DebugPanel drive it with the same text commands, see `DebugCommand`.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
+use ts_rs::TS;
+
+pub const DEBUG_HELP: &str = "\
+break ADDR            stop before the instruction at ADDR
+break routine ADDR    stop when the routine at ADDR is entered
+watch global N        stop when global N changes
+watch memory ADDR [N] stop when any of N bytes at ADDR change
+watch attr OBJ ATTR   stop when attribute ATTR of OBJ flips
+watch parent OBJ      stop when OBJ moves
+delete ID             remove a breakpoint or watchpoint
+list                  show breakpoints and watchpoints
+step                  run one instruction
+next                  run one instruction, stepping over calls
+finish                run until the current routine returns
+continue              run until something stops the story
+record N              keep the last N instructions for going back (0 is off)
+back [N]              go back N instructions (1 by default)
+reverse               go back to the last breakpoint or watchpoint change
+frame                 show the next instruction and the current frame
+backtrace             show every frame, innermost first
+(numbers are decimal, or hex with 0x)";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before the instruction at this address
+    Pc(usize),
+    /// On entry to the routine at this (unpacked) address
+    Routine(usize),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Pc(addr) => write!(f, "pc {:#x}", addr),
+            Breakpoint::Routine(addr) => write!(f, "routine {:#x}", addr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watchpoint {
+    Global(u8),
+    Memory { addr: usize, len: usize },
+    Attribute { object: u16, attr: u16 },
+    Parent(u16),
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Watchpoint::Global(index) => write!(f, "global g{}", index),
+            Watchpoint::Memory { addr, len } => write!(f, "memory {:#x} ({} bytes)", addr, len),
+            Watchpoint::Attribute { object, attr } => write!(f, "attr {} of object {}", attr, object),
+            Watchpoint::Parent(object) => write!(f, "parent of object {}", object),
+        }
+    }
+}
+
+/// Why the debugger stopped the story, the pc is the instruction run next
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DebugStop {
+    Breakpoint {
+        #[cfg_attr(not(target_arch = "wasm32"), ts(type = "number"))]
+        id: usize,
+        #[cfg_attr(not(target_arch = "wasm32"), ts(type = "number"))]
+        pc: usize,
+    },
+    Watchpoint {
+        #[cfg_attr(not(target_arch = "wasm32"), ts(type = "number"))]
+        id: usize,
+        #[cfg_attr(not(target_arch = "wasm32"), ts(type = "number"))]
+        pc: usize,
+        /// Address of the instruction that made the change
+        #[cfg_attr(not(target_arch = "wasm32"), ts(type = "number"))]
+        changed_by: usize,
+        old: Vec<u16>,
+        new: Vec<u16>,
+    },
+    Step {
+        #[cfg_attr(not(target_arch = "wasm32"), ts(type = "number"))]
+        pc: usize,
+    },
+}
+
+impl DebugStop {
+    pub fn pc(&self) -> usize {
+        match *self {
+            DebugStop::Breakpoint { pc, .. }
+            | DebugStop::Watchpoint { pc, .. }
+            | DebugStop::Step { pc } => pc,
+        }
+    }
+}
+
+impl fmt::Display for DebugStop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = |values: &[u16]| {
+            values
+                .iter()
+                .map(|value| format!("{:04x}", value))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        match *self {
+            DebugStop::Breakpoint { id, pc } => write!(f, "Breakpoint {} at {:#x}", id, pc),
+            DebugStop::Watchpoint {
+                id,
+                changed_by,
+                ref old,
+                ref new,
+                ..
+            } => write!(
+                f,
+                "Watchpoint {}: [{}] -> [{}] by {:#x}",
+                id,
+                hex(old),
+                hex(new),
+                changed_by
+            ),
+            DebugStop::Step { pc } => write!(f, "Stepped to {:#x}", pc),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugCommand {
+    Break(Breakpoint),
+    Watch(Watchpoint),
+    Delete(usize),
+    List,
+    Step,
+    Next,
+    Finish,
+    Continue,
+    Record(usize),
+    Back(usize),
+    Reverse,
+    Frame,
+    Backtrace,
+    Help,
+}
+
+fn parse_number(word: Option<&str>) -> Result<usize, String> {
+    let word = word.ok_or_else(|| "Missing number".to_string())?;
+
+    let parsed = match word.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => word.parse(),
+    };
+
+    parsed.map_err(|_| format!("Not a number: {}", word))
+}
+
+fn parse_u16(word: Option<&str>) -> Result<u16, String> {
+    let number = parse_number(word)?;
+    u16::try_from(number).map_err(|_| format!("Too big: {}", number))
+}
+
+impl DebugCommand {
+    pub fn parse(line: &str) -> Result<DebugCommand, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("help");
+
+        let parsed = match command {
+            "break" | "b" => match words.next() {
+                Some("routine") => DebugCommand::Break(Breakpoint::Routine(parse_number(words.next())?)),
+                addr => DebugCommand::Break(Breakpoint::Pc(parse_number(addr)?)),
+            },
+            "watch" | "w" => {
+                let watch = match words.next() {
+                    Some("global") => {
+                        let index = parse_number(words.next())?;
+                        if index >= 240 {
+                            return Err(format!("There are only 240 globals, not {}", index));
+                        }
+                        Watchpoint::Global(index as u8)
+                    }
+                    Some("memory") => Watchpoint::Memory {
+                        addr: parse_number(words.next())?,
+                        len: match words.next() {
+                            Some(len) => parse_number(Some(len))?.max(1),
+                            None => 1,
+                        },
+                    },
+                    Some("attr") => Watchpoint::Attribute {
+                        object: parse_u16(words.next())?,
+                        attr: parse_u16(words.next())?,
+                    },
+                    Some("parent") => Watchpoint::Parent(parse_u16(words.next())?),
+                    _ => return Err("Watch a global, memory, attr or parent".to_string()),
+                };
+                DebugCommand::Watch(watch)
+            }
+            "delete" | "d" => DebugCommand::Delete(parse_number(words.next())?),
+            "list" | "l" => DebugCommand::List,
+            "step" | "s" => DebugCommand::Step,
+            "next" | "n" => DebugCommand::Next,
+            "finish" | "f" => DebugCommand::Finish,
+            "continue" | "c" => DebugCommand::Continue,
+            "record" => DebugCommand::Record(parse_number(words.next())?),
+            "back" | "rs" => match words.next() {
+                Some(count) => DebugCommand::Back(parse_number(Some(count))?),
+                None => DebugCommand::Back(1),
+            },
+            "reverse" | "rc" => DebugCommand::Reverse,
+            "frame" => DebugCommand::Frame,
+            "backtrace" | "bt" => DebugCommand::Backtrace,
+            "help" | "h" => DebugCommand::Help,
+            _ => return Err(format!("Unknown debugger command: {}", command)),
+        };
+
+        match words.next() {
+            Some(extra) => Err(format!("Unexpected: {}", extra)),
+            None => Ok(parsed),
+        }
+    }
+
+    /// True for the commands that hand control back to the story
+    pub fn resumes(&self) -> bool {
+        matches!(
+            *self,
+            DebugCommand::Step | DebugCommand::Next | DebugCommand::Finish | DebugCommand::Continue
+        )
+    }
+}
+
+// What to stop on after the next instructions, by frame depth
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stepping {
+    Into,
+    Over(usize),
+    Out(usize),
+}
+
+/// Breakpoints and watchpoints are numbered together, starting at 1
+#[derive(Debug, Default)]
+pub struct Debugger {
+    next_id: usize,
+    breakpoints: Vec<(usize, Breakpoint)>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    stepping: Option<Stepping>,
+    // a stop at a breakpoint doesn't stop there again when resuming
+    resume_pc: Option<usize>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    /// False when nothing can stop the story, so `run` skips all checks
+    pub fn is_active(&self) -> bool {
+        !self.breakpoints.is_empty() || !self.watchpoints.is_empty() || self.stepping.is_some()
+    }
+
+    fn next_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_id();
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_id();
+        self.watchpoints.push((id, watchpoint));
+        id
+    }
+
+    /// Removes a breakpoint or watchpoint, false if there was none
+    pub fn remove(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|&(other, _)| other != id);
+        self.watchpoints.retain(|&(other, _)| other != id);
+
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[(usize, Watchpoint)] {
+        &self.watchpoints
+    }
+
+    pub(crate) fn stepping(&self) -> Option<Stepping> {
+        self.stepping
+    }
+
+    pub(crate) fn set_stepping(&mut self, stepping: Option<Stepping>) {
+        self.stepping = stepping;
+    }
+
+    pub(crate) fn stopped_at(&mut self, pc: usize) {
+        self.stepping = None;
+        self.resume_pc = Some(pc);
+    }
+
+    // True once for the pc the story last stopped at
+    pub(crate) fn take_resume(&mut self, pc: usize) -> bool {
+        self.resume_pc.take() == Some(pc)
+    }
+
+    pub fn list(&self) -> String {
+        if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+            return "No breakpoints or watchpoints".to_string();
+        }
+
+        let breaks = self
+            .breakpoints
+            .iter()
+            .map(|(id, breakpoint)| format!("{}: break {}", id, breakpoint));
+        let watches = self
+            .watchpoints
+            .iter()
+            .map(|(id, watchpoint)| format!("{}: watch {}", id, watchpoint));
+
+        breaks.chain(watches).collect::<Vec<_>>().join("\n")
+    }
+}
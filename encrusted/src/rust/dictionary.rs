@@ -0,0 +1,149 @@
+//! Dictionary lookups the way the standard does them (section 13): words are
+//! encoded to Z-characters first and compared with the entries' encoded
+//! text, so punctuation, characters outside the alphabet and the v4+ nine
+//! character resolution match what the story expects. Works on the story's
+//! own dictionary and on the user tables `tokenise` can be given.
+
+use crate::buffer::{Buffer, MemoryError};
+use crate::zstring::Alphabet;
+
+/// Where a dictionary is and how it's laid out, the entries stay in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    pub addr: usize,
+    /// Word separators, in ZSCII
+    pub separators: Vec<u8>,
+    pub entry_length: usize,
+    pub entry_count: usize,
+    /// The story's dictionary is in encoded order, user tables with a
+    /// negative entry count aren't
+    pub sorted: bool,
+    pub entries_start: usize,
+}
+
+/// Bytes of encoded text per entry: 6 Z-characters in v1-3, 9 after
+pub fn text_length(version: u8) -> usize {
+    if version <= 3 { 4 } else { 6 }
+}
+
+// Z-character of `letter` in an alphabet row (A2 also skips the escape)
+fn find_letter(alphabet: &Alphabet, row: usize, letter: char) -> Option<u8> {
+    let first = if row == 2 { 7 } else { 6 };
+
+    alphabet[row][first..]
+        .iter()
+        .position(|&entry| entry == letter)
+        .map(|index| (index + first) as u8)
+}
+
+/// Encodes a ZSCII word to dictionary resolution (standard 3.7): letters
+/// from A1 and A2 get a shift, anything else is a 10-bit ZSCII escape, then
+/// it's cut or padded with 5s and packed 3 Z-characters to a word
+pub fn encode_text(word: &[u8], version: u8, alphabet: &Alphabet) -> Vec<u8> {
+    let length = text_length(version);
+    let resolution = length / 2 * 3;
+    let mut zchars = Vec::with_capacity(resolution + 3);
+
+    for &zscii in word {
+        if zchars.len() >= resolution {
+            break;
+        }
+
+        if zscii == b' ' {
+            zchars.push(0);
+            continue;
+        }
+
+        let letter = zscii as char;
+
+        if let Some(zchar) = find_letter(alphabet, 0, letter) {
+            zchars.push(zchar);
+        } else if let Some(zchar) = find_letter(alphabet, 1, letter) {
+            zchars.extend_from_slice(&[4, zchar]);
+        } else if let Some(zchar) = find_letter(alphabet, 2, letter) {
+            zchars.extend_from_slice(&[5, zchar]);
+        } else {
+            zchars.extend_from_slice(&[5, 6, zscii >> 5, zscii & 0b0001_1111]);
+        }
+    }
+
+    zchars.resize(resolution, 5);
+
+    let mut encoded = Vec::with_capacity(length);
+
+    for (n, chunk) in zchars.chunks(3).enumerate() {
+        let mut word = u16::from(chunk[0]) << 10 | u16::from(chunk[1]) << 5 | u16::from(chunk[2]);
+
+        // stop bit on the last word
+        if (n + 1) * 2 == length {
+            word |= 0x8000;
+        }
+
+        encoded.extend_from_slice(&word.to_be_bytes());
+    }
+
+    encoded
+}
+
+impl Dictionary {
+    /// Reads a dictionary's header, the story's or a user table
+    pub fn read(memory: &Buffer, addr: usize) -> Result<Dictionary, MemoryError> {
+        let separator_count = memory.try_read_byte(addr)? as usize;
+        let separators = memory.try_read(addr + 1, separator_count)?.to_vec();
+
+        let entry_length = memory.try_read_byte(addr + 1 + separator_count)? as usize;
+        let count = memory.try_read_word(addr + 2 + separator_count)? as i16;
+        let entries_start = addr + 4 + separator_count;
+
+        Ok(Dictionary {
+            addr,
+            separators,
+            entry_length,
+            entry_count: count.unsigned_abs() as usize,
+            sorted: count >= 0,
+            entries_start,
+        })
+    }
+
+    pub fn entry_addr(&self, index: usize) -> usize {
+        self.entries_start + index * self.entry_length
+    }
+
+    pub fn is_separator(&self, zscii: u8) -> bool {
+        self.separators.contains(&zscii)
+    }
+
+    /// The address of the entry whose text is `encoded`, 0 if there's none
+    pub fn lookup(&self, memory: &Buffer, encoded: &[u8]) -> Result<usize, MemoryError> {
+        if self.entry_length < encoded.len() {
+            return Ok(0);
+        }
+
+        if !self.sorted {
+            for index in 0..self.entry_count {
+                let addr = self.entry_addr(index);
+
+                if memory.try_read(addr, encoded.len())? == encoded {
+                    return Ok(addr);
+                }
+            }
+
+            return Ok(0);
+        }
+
+        let (mut low, mut high) = (0, self.entry_count);
+
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let addr = self.entry_addr(middle);
+
+            match memory.try_read(addr, encoded.len())?.cmp(encoded) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Ok(addr),
+            }
+        }
+
+        Ok(0)
+    }
+}
@@ -0,0 +1,530 @@
+//! Walks a story's code the way txd does: routines are found from the initial
+//! pc and from call targets, each one is decoded by following its branches,
+//! and whatever else is in high memory is marked as strings or data.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use enum_primitive::FromPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::instruction::{Instruction, Opcode, Operand};
+use crate::zmachine::Zmachine;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisassembledInstruction {
+    pub addr: usize,
+    pub name: String,
+    /// The txd-style line from `Instruction`'s `Display`
+    pub text: String,
+    /// Where a branch or jump goes, or the routine a call starts
+    pub target: Option<usize>,
+    /// Whether it branches on a condition
+    pub branch: bool,
+    /// Text printed by print_paddr/print_addr with a constant address
+    pub string: Option<String>,
+    pub next: usize,
+}
+
+impl DisassembledInstruction {
+    pub fn to_text(&self) -> String {
+        match self.string {
+            Some(ref string) => format!("{}  ; \"{}\"", self.text, string),
+            None => self.text.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Routine {
+    pub addr: usize,
+    /// Initial values of the locals (always 0 in v5+)
+    pub locals: Vec<u16>,
+    /// The routine the story starts in
+    pub main: bool,
+    pub instructions: Vec<DisassembledInstruction>,
+    /// First byte after the routine's last instruction
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoryString {
+    pub addr: usize,
+    pub text: String,
+    /// Encoded length in bytes
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionKind {
+    Header,
+    Dynamic,
+    Static,
+    Code,
+    Strings,
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub kind: RegionKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Disassembly {
+    pub version: u8,
+    pub initial_pc: usize,
+    pub routines: Vec<Routine>,
+    pub strings: Vec<StoryString>,
+    pub regions: Vec<Region>,
+}
+
+impl Disassembly {
+    pub fn routine_at(&self, addr: usize) -> Option<&Routine> {
+        self.routines.iter().find(|routine| routine.addr == addr)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        writeln!(
+            text,
+            "; version {}, {} routines, {} strings",
+            self.version,
+            self.routines.len(),
+            self.strings.len()
+        )
+        .unwrap();
+
+        for region in &self.regions {
+            writeln!(text, "; {:7} {:05x}-{:05x}", region_name(region.kind), region.start, region.end).unwrap();
+        }
+
+        for routine in &self.routines {
+            let locals: Vec<String> = routine.locals.iter().map(|local| format!("{:04x}", local)).collect();
+
+            let kind = if routine.main { "Main routine" } else { "Routine" };
+
+            if locals.is_empty() {
+                writeln!(text, "\n{} {:05x}, 0 locals\n", kind, routine.addr).unwrap();
+            } else {
+                writeln!(
+                    text,
+                    "\n{} {:05x}, {} locals ({})\n",
+                    kind,
+                    routine.addr,
+                    locals.len(),
+                    locals.join(", ")
+                )
+                .unwrap();
+            }
+
+            for instr in &routine.instructions {
+                writeln!(text, "{}", instr.to_text()).unwrap();
+            }
+        }
+
+        if !self.strings.is_empty() {
+            writeln!(text, "\n[Strings]\n").unwrap();
+        }
+
+        for string in &self.strings {
+            writeln!(text, "{:5x}: \"{}\"", string.addr, string.text).unwrap();
+        }
+
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+fn region_name(kind: RegionKind) -> &'static str {
+    match kind {
+        RegionKind::Header => "header",
+        RegionKind::Dynamic => "dynamic",
+        RegionKind::Static => "static",
+        RegionKind::Code => "code",
+        RegionKind::Strings => "strings",
+        RegionKind::Data => "data",
+    }
+}
+
+fn constant(operand: Option<&Operand>) -> Option<u16> {
+    match operand {
+        Some(&Operand::Large(value)) => Some(value),
+        Some(&Operand::Small(value)) => Some(u16::from(value)),
+        _ => None,
+    }
+}
+
+struct Walker<'a> {
+    zvm: &'a Zmachine,
+    len: usize,
+    routines: BTreeMap<usize, Routine>,
+    strings: BTreeMap<usize, StoryString>,
+    queue: Vec<usize>,
+}
+
+impl<'a> Walker<'a> {
+    // Routine headers have at most 15 locals
+    fn is_routine(&self, addr: usize) -> bool {
+        addr > 0 && addr < self.len && self.zvm.memory().read_byte(addr) <= 15
+    }
+
+    // A string is only read if its end is inside the file
+    fn read_string(&self, addr: usize) -> Option<StoryString> {
+        let memory = self.zvm.memory();
+        let mut end = addr;
+
+        loop {
+            if end + 2 > self.len {
+                return None;
+            }
+
+            let word = memory.read_word(end);
+            end += 2;
+
+            if word & 0x8000 != 0 {
+                break;
+            }
+        }
+
+        Some(StoryString {
+            addr,
+            text: self.zvm.read_zstring(addr).ok()?,
+            length: end - addr,
+        })
+    }
+
+    fn note_string(&mut self, addr: usize) -> Option<String> {
+        if let Some(string) = self.strings.get(&addr) {
+            return Some(string.text.clone());
+        }
+
+        let string = self.read_string(addr)?;
+        let text = string.text.clone();
+        self.strings.insert(addr, string);
+
+        Some(text)
+    }
+
+    // Only the opcodes the decoder knows, with inline text that ends in the file
+    fn decodes_at(&self, pc: usize) -> bool {
+        let memory = self.zvm.memory();
+
+        // the longest instruction without inline text
+        if pc + 23 > self.len {
+            return false;
+        }
+
+        let first = memory.read_byte(pc);
+        let number = match first {
+            0xbe if self.zvm.version() >= 5 => 1000 + u16::from(memory.read_byte(pc + 1)),
+            0xbe => return false,
+            0x00..=0x7f | 0xc0..=0xdf => u16::from(first & 0b0001_1111),
+            0x80..=0xaf => 128 + u16::from(first & 0b0000_1111),
+            0xb0..=0xbf => 176 + u16::from(first & 0b0000_1111),
+            0xe0..=0xff => 224 + u16::from(first & 0b0001_1111),
+        };
+
+        match Opcode::from_u16(number) {
+            Some(Opcode::OP0_178) | Some(Opcode::OP0_179) => self.read_string(pc + 1).is_some(),
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    // Decodes a routine by following its branches, None if something in it
+    // isn't code
+    fn trace(&self, addr: usize, headerless: bool) -> Option<(Vec<u16>, BTreeMap<usize, Instruction>)> {
+        let version = self.zvm.version();
+        let mut read = self.zvm.memory().get_reader(addr);
+
+        let locals = if headerless {
+            Vec::new()
+        } else {
+            let count = read.byte();
+            if count > 15 {
+                return None;
+            }
+            (0..count).map(|_| if version <= 4 { read.word() } else { 0 }).collect()
+        };
+
+        let mut pending = vec![read.position()];
+        let mut decoded: BTreeMap<usize, Instruction> = BTreeMap::new();
+
+        while let Some(pc) = pending.pop() {
+            if decoded.contains_key(&pc) {
+                continue;
+            }
+
+            if pc < addr || !self.decodes_at(pc) {
+                return None;
+            }
+
+            let instr = self.zvm.decode_instruction(pc).ok()?;
+
+            if let Some(target) = instr.branch.as_ref().and_then(|branch| branch.address) {
+                pending.push(target);
+            }
+
+            if let Some(target) = self.jump_target(&instr) {
+                pending.push(target);
+            }
+
+            if instr.advances() {
+                pending.push(instr.next);
+            }
+
+            decoded.insert(pc, instr);
+        }
+
+        Some((locals, decoded))
+    }
+
+    fn walk(&mut self, addr: usize, headerless: bool) -> bool {
+        let (locals, decoded) = match self.trace(addr, headerless) {
+            Some(traced) => traced,
+            None => return false,
+        };
+
+        let instructions: Vec<DisassembledInstruction> = decoded
+            .values()
+            .map(|instr| self.describe(instr))
+            .collect();
+        let end = decoded.values().map(|instr| instr.next).max().unwrap_or(addr);
+
+        for instr in decoded.values() {
+            if let Some(routine) = self.call_target(instr) {
+                self.queue.push(routine);
+            }
+        }
+
+        self.routines.insert(
+            addr,
+            Routine {
+                addr,
+                locals,
+                main: false,
+                instructions,
+                end,
+            },
+        );
+
+        true
+    }
+
+    fn walk_queue(&mut self) {
+        while let Some(addr) = self.queue.pop() {
+            if !self.routines.contains_key(&addr) && !self.inside_routine(addr) {
+                self.walk(addr, false);
+            }
+        }
+    }
+
+    fn inside_routine(&self, addr: usize) -> bool {
+        self.routines
+            .range(..addr)
+            .next_back()
+            .is_some_and(|(_, routine)| addr < routine.end)
+    }
+
+    // Routines only reached through variables (property tables, action
+    // routines) are found the way txd does, by trying whatever follows the
+    // routines already known
+    fn walk_gaps(&mut self) {
+        loop {
+            let align = self.align();
+            let candidates: Vec<usize> = self
+                .routines
+                .values()
+                .map(|routine| routine.end.div_ceil(align) * align)
+                .filter(|addr| !self.routines.contains_key(addr))
+                .collect();
+
+            let mut found = false;
+            for addr in candidates {
+                if !self.inside_routine(addr) && self.walk(addr, false) {
+                    found = true;
+                }
+            }
+
+            self.walk_queue();
+
+            if !found {
+                break;
+            }
+        }
+    }
+
+    fn align(&self) -> usize {
+        match self.zvm.version() {
+            1..=3 => 2,
+            4..=7 => 4,
+            _ => 8,
+        }
+    }
+
+    fn jump_target(&self, instr: &Instruction) -> Option<usize> {
+        if instr.opcode != Opcode::OP1_140 {
+            return None;
+        }
+
+        let offset = constant(instr.operands.first())? as i16;
+        Some((instr.next as isize + offset as isize - 2) as usize)
+    }
+
+    fn call_target(&self, instr: &Instruction) -> Option<usize> {
+        if !instr.does_call(self.zvm.version()) {
+            return None;
+        }
+
+        match constant(instr.operands.first())? {
+            0 => None,
+            packed => Some(self.zvm.unpack_routine_addr(packed)).filter(|&addr| self.is_routine(addr)),
+        }
+    }
+
+    fn describe(&mut self, instr: &Instruction) -> DisassembledInstruction {
+        let string = match instr.opcode {
+            Opcode::OP1_141 => constant(instr.operands.first())
+                .map(|packed| self.zvm.unpack_print_paddr(packed))
+                .and_then(|addr| self.note_string(addr)),
+            Opcode::OP1_135 => constant(instr.operands.first()).and_then(|addr| self.note_string(addr as usize)),
+            _ => None,
+        };
+
+        let target = instr
+            .branch
+            .as_ref()
+            .and_then(|branch| branch.address)
+            .or_else(|| self.jump_target(instr))
+            .or_else(|| self.call_target(instr));
+
+        DisassembledInstruction {
+            addr: instr.addr,
+            name: instr.name.clone(),
+            text: instr.to_string(),
+            target,
+            branch: instr.branch.is_some(),
+            string,
+            next: instr.next,
+        }
+    }
+
+    // Strings usually follow the code, packed one after the other
+    fn scan_strings(&mut self, start: usize) {
+        let align = self.align();
+        let mut addr = start.div_ceil(align) * align;
+
+        while let Some(string) = self.read_string(addr) {
+            let next = addr + string.length;
+            self.strings.entry(addr).or_insert(string);
+            addr = next.div_ceil(align) * align;
+        }
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        let header = self.zvm.header();
+        let static_start = header.static_memory;
+        let high_start = header.high_memory.max(static_start);
+
+        let mut regions = vec![
+            Region { kind: RegionKind::Header, start: 0, end: 0x40 },
+            Region { kind: RegionKind::Dynamic, start: 0x40, end: static_start },
+            Region { kind: RegionKind::Static, start: static_start, end: high_start },
+        ];
+
+        // what's known in high memory, anything else is data
+        let mut known: BTreeSet<(usize, usize, RegionKind)> = BTreeSet::new();
+        for routine in self.routines.values() {
+            known.insert((routine.addr, routine.end, RegionKind::Code));
+        }
+        for string in self.strings.values() {
+            known.insert((string.addr, string.addr + string.length, RegionKind::Strings));
+        }
+
+        let mut cursor = high_start;
+        for (start, end, kind) in known {
+            if end <= cursor {
+                continue;
+            }
+
+            let start = start.max(cursor);
+            // a few bytes of padding go with the region before them
+            if start > cursor + 7 {
+                regions.push(Region { kind: RegionKind::Data, start: cursor, end: start });
+            }
+
+            match regions.last_mut() {
+                Some(last) if last.kind == kind && start <= last.end + 7 => last.end = end,
+                _ => regions.push(Region { kind, start, end }),
+            }
+
+            cursor = end;
+        }
+
+        if cursor < self.len {
+            regions.push(Region { kind: RegionKind::Data, start: cursor, end: self.len });
+        }
+
+        regions.retain(|region| region.start < region.end);
+        regions
+    }
+}
+
+/// Disassembles the story `zvm` was loaded with
+pub fn disassemble(zvm: &Zmachine) -> Disassembly {
+    let memory = zvm.memory();
+    let header = zvm.header();
+    let version = header.version;
+    let initial_pc = header.initial_pc;
+
+    // the file length in the header leaves out any padding
+    let len = match header.file_length {
+        0 => memory.len(),
+        len => len.min(memory.len()),
+    };
+
+    let mut walker = Walker {
+        zvm,
+        len,
+        routines: BTreeMap::new(),
+        strings: BTreeMap::new(),
+        queue: Vec::new(),
+    };
+
+    // v6 starts with a call to a packed main routine, the others start
+    // running at the initial pc, usually right after a 0 locals header
+    let main = if version == 6 {
+        header.unpacked_initial_pc()
+    } else if initial_pc > 0 && memory.read_byte(initial_pc - 1) == 0 {
+        initial_pc - 1
+    } else {
+        initial_pc
+    };
+
+    walker.walk(main, version != 6 && main == initial_pc);
+    if let Some(routine) = walker.routines.get_mut(&main) {
+        routine.main = true;
+    }
+
+    walker.walk_queue();
+    walker.walk_gaps();
+
+    let code_end = walker.routines.values().map(|routine| routine.end).max().unwrap_or(0);
+    walker.scan_strings(code_end);
+
+    Disassembly {
+        version,
+        initial_pc,
+        regions: walker.regions(),
+        routines: walker.routines.into_values().collect(),
+        strings: walker.strings.into_values().collect(),
+    }
+}
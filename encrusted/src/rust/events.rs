@@ -0,0 +1,246 @@
+//! Events the engine sends to its host, and an ordered queue for them.
+//!
+//! On the wire an event is a `(type, body)` message pair where the body is
+//! json (html for `print`). `HostEvent` is the typed form of those pairs on
+//! both sides, and the TypeScript types of the web app are generated from it.
+//!
+//! Every queued message gets its own sequence number, so nothing is lost when
+//! a turn flushes more than once or sends both a save and a savestate.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+#[cfg(not(target_arch = "wasm32"))]
+use ts_rs::TS;
+
+use crate::ui_web::TextRun;
+use crate::zmachine::{CrashReport, Object, StepOutcome};
+
+/// Bumped whenever a message changes shape. New event types and new fields
+/// don't need a bump, hosts should ignore what they don't recognise.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Everything the engine tells its host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+pub enum HostEvent {
+    /// Html for the text printed since the last flush
+    Print(String),
+    /// The same text as typed runs, in the tokens output format
+    Tokens(Vec<TextRun>),
+    /// Status line: room on the left, score and moves on the right
+    Header(String, String),
+    /// Current room: object number and name
+    Map(u16, String),
+    /// Object tree, rooted at the null object
+    Tree(Object),
+    /// In-game SAVE: status line and base64 save
+    Save(String, String),
+    /// State at every prompt: status line and base64 save
+    Savestate(String, String),
+    /// The story ran RESTORE and waits for save data
+    Restore,
+    /// A guarded story hit an interpreter fault
+    Crash(CrashReport),
+}
+
+impl HostEvent {
+    pub fn kind(&self) -> EventKind {
+        match *self {
+            HostEvent::Print(_) => EventKind::Print,
+            HostEvent::Tokens(_) => EventKind::Tokens,
+            HostEvent::Header(..) => EventKind::Header,
+            HostEvent::Map(..) => EventKind::Map,
+            HostEvent::Tree(_) => EventKind::Tree,
+            HostEvent::Save(..) => EventKind::Save,
+            HostEvent::Savestate(..) => EventKind::Savestate,
+            HostEvent::Restore => EventKind::Restore,
+            HostEvent::Crash(_) => EventKind::Crash,
+        }
+    }
+
+    /// Parses a `(type, body)` message, None for unknown types and bodies
+    /// that don't match their type
+    pub fn from_message(mtype: &str, msg: &str) -> Option<HostEvent> {
+        let event = match EventKind::from_message_type(mtype)? {
+            EventKind::Print => HostEvent::Print(msg.to_string()),
+            EventKind::Restore => HostEvent::Restore,
+            EventKind::Tokens => HostEvent::Tokens(serde_json::from_str(msg).ok()?),
+            EventKind::Tree => HostEvent::Tree(serde_json::from_str(msg).ok()?),
+            EventKind::Crash => HostEvent::Crash(serde_json::from_str(msg).ok()?),
+            EventKind::Header => {
+                let (left, right) = serde_json::from_str(msg).ok()?;
+                HostEvent::Header(left, right)
+            }
+            EventKind::Map => {
+                let (number, name) = serde_json::from_str(msg).ok()?;
+                HostEvent::Map(number, name)
+            }
+            EventKind::Save => {
+                let (status, data) = serde_json::from_str(msg).ok()?;
+                HostEvent::Save(status, data)
+            }
+            EventKind::Savestate => {
+                let (status, data) = serde_json::from_str(msg).ok()?;
+                HostEvent::Savestate(status, data)
+            }
+        };
+
+        Some(event)
+    }
+
+    /// The `(type, body)` message for this event
+    pub fn to_message(&self) -> (&'static str, String) {
+        let body = match *self {
+            HostEvent::Print(ref html) => html.clone(),
+            HostEvent::Restore => String::new(),
+            _ => self.data().to_string(),
+        };
+
+        (self.kind().as_str(), body)
+    }
+
+    /// The body as json, null for `restore`
+    pub fn data(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap();
+        value
+            .get_mut("data")
+            .map(Value::take)
+            .unwrap_or(Value::Null)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Print,
+    Tokens,
+    Header,
+    Map,
+    Tree,
+    Save,
+    Savestate,
+    Restore,
+    Crash,
+}
+
+impl EventKind {
+    pub fn from_message_type(mtype: &str) -> Option<EventKind> {
+        match mtype {
+            "print" => Some(EventKind::Print),
+            "tokens" => Some(EventKind::Tokens),
+            "header" => Some(EventKind::Header),
+            "map" => Some(EventKind::Map),
+            "tree" => Some(EventKind::Tree),
+            "save" => Some(EventKind::Save),
+            "savestate" => Some(EventKind::Savestate),
+            "restore" => Some(EventKind::Restore),
+            "crash" => Some(EventKind::Crash),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            EventKind::Print => "print",
+            EventKind::Tokens => "tokens",
+            EventKind::Header => "header",
+            EventKind::Map => "map",
+            EventKind::Tree => "tree",
+            EventKind::Save => "save",
+            EventKind::Savestate => "savestate",
+            EventKind::Restore => "restore",
+            EventKind::Crash => "crash",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+pub struct QueuedEvent {
+    #[cfg_attr(not(target_arch = "wasm32"), ts(type = "number"))]
+    pub seq: u64,
+    #[serde(flatten)]
+    pub event: HostEvent,
+}
+
+#[derive(Debug, Default)]
+pub struct EventQueue {
+    next_seq: u64,
+    events: VecDeque<QueuedEvent>,
+}
+
+impl EventQueue {
+    pub fn new() -> EventQueue {
+        EventQueue::default()
+    }
+
+    pub fn push(&mut self, event: HostEvent) {
+        self.next_seq += 1;
+        self.events.push_back(QueuedEvent {
+            seq: self.next_seq,
+            event,
+        });
+    }
+
+    /// Queues a UI message, returns false for messages that aren't events
+    pub fn push_message(&mut self, mtype: &str, msg: &str) -> bool {
+        match HostEvent::from_message(mtype, msg) {
+            Some(event) => {
+                self.push(event);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Takes every queued event, oldest first
+    pub fn drain(&mut self) -> Vec<QueuedEvent> {
+        self.events.drain(..).collect()
+    }
+
+    /// Takes every queued event as a json array
+    pub fn drain_json(&mut self) -> String {
+        serde_json::to_string(&self.drain()).unwrap()
+    }
+}
+
+/// TypeScript declarations of the events, checked into the web app as
+/// apps/web/src/types/events.ts
+#[cfg(not(target_arch = "wasm32"))]
+pub fn typescript_definitions() -> String {
+    let decls = [
+        QueuedEvent::decl(),
+        HostEvent::decl(),
+        TextRun::decl(),
+        crate::ui_web::RunKind::decl(),
+        crate::ui_web::RunStyle::decl(),
+        Object::decl(),
+        StepOutcome::decl(),
+        CrashReport::decl(),
+        crate::trace::TraceRecord::decl(),
+        crate::debugger::DebugStop::decl(),
+    ];
+
+    let mut ts = String::from("// Generated from encrusted/src/rust/events.rs, do not edit.\n");
+    ts.push_str("// Regenerate with: UPDATE_TYPES=1 cargo test --test typescript\n\n");
+    ts.push_str(&format!("export const PROTOCOL_VERSION = {};\n", PROTOCOL_VERSION));
+
+    for decl in decls.iter() {
+        ts.push_str("\nexport ");
+        ts.push_str(decl);
+        ts.push('\n');
+    }
+
+    ts
+}
@@ -7,6 +7,9 @@ pub struct Frame {
     pub arg_count: u8,
     pub resume: usize,
     pub store: Option<u8>,
+    // address of the routine, 0 for the main routine and restored frames
+    // (saves don't record it)
+    pub routine: usize,
 }
 
 impl Frame {
@@ -23,6 +26,7 @@ impl Frame {
             locals,
             resume,
             store,
+            routine: 0,
         }
     }
 
@@ -33,6 +37,7 @@ impl Frame {
             arg_count: 0,
             resume: 0,
             store: None,
+            routine: 0,
         }
     }
 
@@ -90,39 +95,36 @@ impl Frame {
             arg_count,
             resume,
             store,
+            routine: 0,
         }
     }
 
-    pub fn read_local(&self, index: u8) -> u16 {
-        let index = index as usize;
-
-        if index > self.locals.len() {
-            panic!("Trying to read out of bounds local @: {}", index);
-        }
-
-        self.locals[index]
+    // The story picks the local and pops the stack, so these are None
+    // (false for writes) when it asks for one the routine doesn't have
+    pub fn read_local(&self, index: u8) -> Option<u16> {
+        self.locals.get(index as usize).copied()
     }
 
-    pub fn write_local(&mut self, index: u8, value: u16) {
-        let index = index as usize;
-
-        if index > self.locals.len() {
-            panic!("Trying to write out of bounds local @: {}", index);
+    pub fn write_local(&mut self, index: u8, value: u16) -> bool {
+        match self.locals.get_mut(index as usize) {
+            Some(local) => {
+                *local = value;
+                true
+            }
+            None => false,
         }
-
-        self.locals[index] = value;
     }
 
     pub fn stack_push(&mut self, value: u16) {
         self.stack.push(value);
     }
 
-    pub fn stack_pop(&mut self) -> u16 {
-        self.stack.pop().expect("Can't pop off an empty stack!")
+    pub fn stack_pop(&mut self) -> Option<u16> {
+        self.stack.pop()
     }
 
-    pub fn stack_peek(&self) -> u16 {
-        *self.stack.last().expect("Can't peek on an empty stack!")
+    pub fn stack_peek(&self) -> Option<u16> {
+        self.stack.last().copied()
     }
 
     pub fn to_string(&self) -> String {
@@ -158,7 +160,8 @@ impl Frame {
         bytes.push((self.resume & 0x00_00FF) as u8);
 
         let mut flags = self.locals.len() as u8; // 0b0000vvvv
-        if self.store.is_some() {
+        // bit 4 is set when the routine's result is discarded (no store)
+        if self.store.is_none() {
             flags += 0b0001_0000;
         }
 
@@ -1,3 +1,4 @@
+use crate::header::{HeaderError, StoryHeader};
 use crate::zmachine::Zmachine;
 use crate::options::Options;
 use crate::traits::UI;
@@ -9,17 +10,26 @@ pub struct Game;
 
 impl Game {
     pub fn load_from_ui(ui: Box<dyn UI>, mut opts: Options) -> Zmachine {
-        let data = GAME_DATA.to_vec();
-
-        let version = data[0];
-        if version == 0 || version > 8 {
-            panic!("Unsupported game version: {}", version);
-        }
-
         // Use a fixed seed for consistency (WASM can't use rand::random() reliably)
         // The Z-machine will call js_rand for runtime randomness
         opts.rand_seed = [0xDEADBEEF, 0xCAFEBABE, 0x12345678, 0x87654321];
 
-        Zmachine::new(data, ui, opts)
+        Game::load_story(GAME_DATA.to_vec(), ui, opts)
+    }
+
+    // Loads any story file, keeping the seed given in the options
+    pub fn load_story(data: Vec<u8>, ui: Box<dyn UI>, opts: Options) -> Zmachine {
+        Game::try_load_story(data, ui, opts).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // Like load_story, but an invalid header is an error instead of a panic
+    pub fn try_load_story(data: Vec<u8>, ui: Box<dyn UI>, opts: Options) -> Result<Zmachine, HeaderError> {
+        StoryHeader::parse(&data)?;
+
+        Ok(Zmachine::new(data, ui, opts))
+    }
+
+    pub fn story_data() -> &'static [u8] {
+        GAME_DATA
     }
 }
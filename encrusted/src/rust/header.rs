@@ -0,0 +1,289 @@
+//! The 64 byte story file header, parsed once and checked before any code
+//! runs. Field names follow the standard's section 11; fields a version
+//! doesn't have are read anyway (they're 0 in those stories).
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+pub const HEADER_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    TooShort(usize),
+    UnsupportedVersion(u8),
+    StaticMemoryTooLow(usize),
+    OutOfRange {
+        field: &'static str,
+        addr: usize,
+        len: usize,
+    },
+    Truncated {
+        expected: usize,
+        len: usize,
+    },
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeaderError::TooShort(len) => {
+                write!(f, "Story file is {} bytes, too short for a {} byte header", len, HEADER_LENGTH)
+            }
+            HeaderError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported game version: {} (only 1 to 8 are)", version)
+            }
+            HeaderError::StaticMemoryTooLow(addr) => {
+                write!(f, "Static memory starts at {:#x}, inside the header", addr)
+            }
+            HeaderError::OutOfRange { field, addr, len } => {
+                write!(f, "The {} at {:#x} is past the end of the {} byte story file", field, addr, len)
+            }
+            HeaderError::Truncated { expected, len } => {
+                write!(f, "Story file is truncated: the header says {} bytes, it has {}", expected, len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+/// The v5+ header extension table (standard section 11.1.7)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderExtension {
+    pub addr: usize,
+    /// The words after the length word
+    pub words: Vec<u16>,
+}
+
+impl HeaderExtension {
+    fn word(&self, number: usize) -> u16 {
+        self.words.get(number - 1).cloned().unwrap_or(0)
+    }
+
+    pub fn mouse_x(&self) -> u16 {
+        self.word(1)
+    }
+
+    pub fn mouse_y(&self) -> u16 {
+        self.word(2)
+    }
+
+    pub fn unicode_table(&self) -> usize {
+        self.word(3) as usize
+    }
+
+    pub fn flags3(&self) -> u16 {
+        self.word(4)
+    }
+
+    pub fn true_foreground(&self) -> u16 {
+        self.word(5)
+    }
+
+    pub fn true_background(&self) -> u16 {
+        self.word(6)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoryHeader {
+    pub version: u8,
+    pub flags1: u8,
+    pub release: u16,
+    pub high_memory: usize,
+    /// In v6 this is the packed address of the main routine
+    pub initial_pc: usize,
+    pub dictionary: usize,
+    pub object_table: usize,
+    pub globals: usize,
+    pub static_memory: usize,
+    pub flags2: u16,
+    pub serial: [u8; 6],
+    pub abbreviations: usize,
+    /// In bytes, 0 when the story leaves it out (some v1-3 stories)
+    pub file_length: usize,
+    pub checksum: u16,
+    pub interpreter_number: u8,
+    pub interpreter_version: u8,
+    pub screen_height: u8,
+    pub screen_width: u8,
+    pub screen_width_units: u16,
+    pub screen_height_units: u16,
+    pub font_width: u8,
+    pub font_height: u8,
+    /// v6-7, in multiples of 8 bytes
+    pub routine_offset: usize,
+    pub string_offset: usize,
+    pub default_background: u8,
+    pub default_foreground: u8,
+    pub terminating_chars: usize,
+    pub stream3_width: u16,
+    pub standard_revision: u16,
+    pub alphabet_table: usize,
+    pub extension: Option<HeaderExtension>,
+}
+
+fn word(data: &[u8], addr: usize) -> u16 {
+    u16::from(data[addr]) << 8 | u16::from(data[addr + 1])
+}
+
+impl StoryHeader {
+    /// Parses and validates the header of a story file
+    pub fn parse(data: &[u8]) -> Result<StoryHeader, HeaderError> {
+        let len = data.len();
+
+        if len < HEADER_LENGTH {
+            return Err(HeaderError::TooShort(len));
+        }
+
+        let version = data[0x00];
+        if version == 0 || version > 8 {
+            return Err(HeaderError::UnsupportedVersion(version));
+        }
+
+        let file_scale = match version {
+            1..=3 => 2,
+            4..=5 => 4,
+            _ => 8,
+        };
+
+        let mut serial = [0; 6];
+        serial.copy_from_slice(&data[0x12..0x18]);
+
+        let mut header = StoryHeader {
+            version,
+            flags1: data[0x01],
+            release: word(data, 0x02),
+            high_memory: word(data, 0x04) as usize,
+            initial_pc: word(data, 0x06) as usize,
+            dictionary: word(data, 0x08) as usize,
+            object_table: word(data, 0x0A) as usize,
+            globals: word(data, 0x0C) as usize,
+            static_memory: word(data, 0x0E) as usize,
+            flags2: word(data, 0x10),
+            serial,
+            abbreviations: word(data, 0x18) as usize,
+            file_length: word(data, 0x1A) as usize * file_scale,
+            checksum: word(data, 0x1C),
+            interpreter_number: data[0x1E],
+            interpreter_version: data[0x1F],
+            screen_height: data[0x20],
+            screen_width: data[0x21],
+            screen_width_units: word(data, 0x22),
+            screen_height_units: word(data, 0x24),
+            font_width: data[0x26],
+            font_height: data[0x27],
+            routine_offset: word(data, 0x28) as usize,
+            string_offset: word(data, 0x2A) as usize,
+            default_background: data[0x2C],
+            default_foreground: data[0x2D],
+            terminating_chars: word(data, 0x2E) as usize,
+            stream3_width: word(data, 0x30),
+            standard_revision: word(data, 0x32),
+            alphabet_table: word(data, 0x34) as usize,
+            extension: None,
+        };
+
+        header.validate(data)?;
+
+        let extension_addr = word(data, 0x36) as usize;
+        if version >= 5 && extension_addr != 0 {
+            header.extension = Some(StoryHeader::parse_extension(data, extension_addr)?);
+        }
+
+        Ok(header)
+    }
+
+    fn parse_extension(data: &[u8], addr: usize) -> Result<HeaderExtension, HeaderError> {
+        let len = data.len();
+        let in_file = |field, addr, size| match addr + size <= len {
+            true => Ok(()),
+            false => Err(HeaderError::OutOfRange { field, addr, len }),
+        };
+
+        in_file("header extension table", addr, 2)?;
+        let count = word(data, addr) as usize;
+        in_file("header extension table", addr, 2 + count * 2)?;
+
+        let extension = HeaderExtension {
+            addr,
+            words: (0..count).map(|n| word(data, addr + 2 + n * 2)).collect(),
+        };
+
+        if extension.unicode_table() != 0 {
+            in_file("unicode table", extension.unicode_table(), 1)?;
+        }
+
+        Ok(extension)
+    }
+
+    fn validate(&self, data: &[u8]) -> Result<(), HeaderError> {
+        let len = data.len();
+
+        if self.static_memory < HEADER_LENGTH {
+            return Err(HeaderError::StaticMemoryTooLow(self.static_memory));
+        }
+
+        if self.file_length > len {
+            return Err(HeaderError::Truncated {
+                expected: self.file_length,
+                len,
+            });
+        }
+
+        let in_file = |field, addr, size| match addr + size <= len {
+            true => Ok(()),
+            false => Err(HeaderError::OutOfRange { field, addr, len }),
+        };
+
+        // sizes are the least each table needs to be readable
+        in_file("static memory", self.static_memory, 0)?;
+        in_file("high memory", self.high_memory, 0)?;
+        in_file("initial pc", self.unpacked_initial_pc(), 1)?;
+        in_file("dictionary", self.dictionary, 4)?;
+        // the word separators come before the entry length and count
+        in_file("dictionary", self.dictionary, 4 + data[self.dictionary] as usize)?;
+        in_file("object table", self.object_table, self.property_defaults_length())?;
+        in_file("global variables", self.globals, 240 * 2)?;
+
+        if self.version >= 2 {
+            in_file("abbreviations table", self.abbreviations, 96 * 2)?;
+        }
+
+        if self.version >= 5 {
+            if self.alphabet_table != 0 {
+                in_file("alphabet table", self.alphabet_table, 78)?;
+            }
+
+            if self.terminating_chars != 0 {
+                in_file("terminating characters table", self.terminating_chars, 1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // The object table starts with the property defaults
+    fn property_defaults_length(&self) -> usize {
+        if self.version <= 3 { 31 * 2 } else { 63 * 2 }
+    }
+
+    /// Where the first instruction is, v6 starts with a call to a packed
+    /// routine (and its locals header)
+    pub fn unpacked_initial_pc(&self) -> usize {
+        match self.version {
+            6 => self.initial_pc * 4 + self.routine_offset * 8,
+            _ => self.initial_pc,
+        }
+    }
+
+    pub fn serial_text(&self) -> String {
+        String::from_utf8_lossy(&self.serial).into_owned()
+    }
+
+    /// True when the status line shows the time instead of score and turns
+    pub fn is_time_game(&self) -> bool {
+        self.version <= 3 && self.flags1 & 0b0000_0010 != 0
+    }
+}
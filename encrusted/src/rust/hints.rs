@@ -228,6 +228,13 @@ impl HintSystem {
             .collect()
     }
 
+    /// Number of progressively revealed answers a question has
+    pub fn get_answer_count(&self, question_idx: usize) -> usize {
+        self.get_all_questions()
+            .get(question_idx)
+            .map_or(0, |question| question.answers.len())
+    }
+
     /// Get a specific answer at a given level for a question
     pub fn get_answer_at_level(&self, question_idx: usize, level: usize) -> Option<String> {
         let questions = self.get_all_questions();
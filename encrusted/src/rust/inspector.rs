@@ -0,0 +1,343 @@
+//! An infodump for story files: the header, the dictionary, every object
+//! with its attributes and properties, the abbreviations and the strings in
+//! high memory, read with the interpreter's own object and text readers.
+
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::MemoryError;
+use crate::dictionary::{text_length, Dictionary};
+use crate::disassembler::{disassemble, StoryString};
+use crate::header::StoryHeader;
+use crate::zmachine::Zmachine;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DictionaryEntry {
+    pub index: usize,
+    pub addr: usize,
+    pub word: String,
+    /// The bytes after the encoded word, the first is usually the flags
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DictionaryInfo {
+    pub separators: Vec<char>,
+    pub entry_length: usize,
+    pub entries: Vec<DictionaryEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropertyInfo {
+    pub number: u8,
+    pub addr: usize,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectInfo {
+    pub number: u16,
+    pub name: String,
+    pub parent: u16,
+    pub sibling: u16,
+    pub child: u16,
+    pub attributes: Vec<u16>,
+    pub property_table: usize,
+    pub properties: Vec<PropertyInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Abbreviation {
+    pub index: u8,
+    pub addr: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoryInfo {
+    pub header: StoryHeader,
+    pub dictionary: DictionaryInfo,
+    pub objects: Vec<ObjectInfo>,
+    pub abbreviations: Vec<Abbreviation>,
+    pub strings: Vec<StoryString>,
+}
+
+/// What `StoryInfo::to_text` prints, everything by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Header,
+    Dictionary,
+    Objects,
+    Abbreviations,
+    Strings,
+}
+
+impl Section {
+    pub const ALL: [Section; 5] = [
+        Section::Header,
+        Section::Dictionary,
+        Section::Objects,
+        Section::Abbreviations,
+        Section::Strings,
+    ];
+
+    pub fn parse(name: &str) -> Option<Section> {
+        match name {
+            "header" => Some(Section::Header),
+            "dictionary" => Some(Section::Dictionary),
+            "objects" => Some(Section::Objects),
+            "abbreviations" => Some(Section::Abbreviations),
+            "strings" => Some(Section::Strings),
+            _ => None,
+        }
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl StoryInfo {
+    pub fn object(&self, number: u16) -> Option<&ObjectInfo> {
+        self.objects.iter().find(|object| object.number == number)
+    }
+
+    /// Objects whose name contains `name`, ignoring case
+    pub fn find_objects(&self, name: &str) -> Vec<&ObjectInfo> {
+        let name = name.to_lowercase();
+
+        self.objects
+            .iter()
+            .filter(|object| object.name.to_lowercase().contains(&name))
+            .collect()
+    }
+
+    pub fn to_text(&self, sections: &[Section]) -> String {
+        let mut text = String::new();
+
+        for section in sections {
+            match *section {
+                Section::Header => self.header_text(&mut text),
+                Section::Dictionary => self.dictionary_text(&mut text),
+                Section::Objects => self.objects_text(&mut text),
+                Section::Abbreviations => self.abbreviations_text(&mut text),
+                Section::Strings => self.strings_text(&mut text),
+            }
+        }
+
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn header_text(&self, text: &mut String) {
+        let header = &self.header;
+
+        writeln!(text, "\n    **** Story file header ****\n").unwrap();
+        writeln!(text, "Z-code version:           {}", header.version).unwrap();
+        writeln!(text, "Release number:           {}", header.release).unwrap();
+        writeln!(text, "Serial number:            {}", header.serial_text()).unwrap();
+        writeln!(text, "Checksum:                 {:04x}", header.checksum).unwrap();
+        writeln!(text, "Flags 1:                  {:02x}", header.flags1).unwrap();
+        writeln!(text, "Flags 2:                  {:04x}", header.flags2).unwrap();
+        writeln!(text, "Initial PC:               {:05x}", header.initial_pc).unwrap();
+        writeln!(text, "Dictionary address:       {:05x}", header.dictionary).unwrap();
+        writeln!(text, "Object table address:     {:05x}", header.object_table).unwrap();
+        writeln!(text, "Global variables address: {:05x}", header.globals).unwrap();
+        writeln!(text, "Static memory address:    {:05x}", header.static_memory).unwrap();
+        writeln!(text, "High memory address:      {:05x}", header.high_memory).unwrap();
+        writeln!(text, "Abbreviations address:    {:05x}", header.abbreviations).unwrap();
+        writeln!(text, "File size:                {:05x}", header.file_length).unwrap();
+
+        if header.version >= 5 {
+            writeln!(text, "Terminating chars:        {:05x}", header.terminating_chars).unwrap();
+            writeln!(text, "Alphabet table:           {:05x}", header.alphabet_table).unwrap();
+        }
+
+        if header.version >= 6 {
+            writeln!(text, "Routine offset:           {:05x}", header.routine_offset * 8).unwrap();
+            writeln!(text, "Strings offset:           {:05x}", header.string_offset * 8).unwrap();
+        }
+
+        if header.standard_revision != 0 {
+            let revision = header.standard_revision;
+            writeln!(text, "Standard revision:        {}.{}", revision >> 8, revision & 0xFF).unwrap();
+        }
+
+        if let Some(ref extension) = header.extension {
+            writeln!(text, "Header extension:         {:05x} ({} words)", extension.addr, extension.words.len()).unwrap();
+            writeln!(text, "Unicode table:            {:05x}", extension.unicode_table()).unwrap();
+            writeln!(text, "Flags 3:                  {:04x}", extension.flags3()).unwrap();
+        }
+    }
+
+    fn dictionary_text(&self, text: &mut String) {
+        let dictionary = &self.dictionary;
+        let separators: String = dictionary.separators.iter().collect();
+
+        writeln!(text, "\n    **** Dictionary ****\n").unwrap();
+        writeln!(text, "Word separators: \"{}\"", separators).unwrap();
+        writeln!(
+            text,
+            "Word count: {}, word size: {}\n",
+            dictionary.entries.len(),
+            dictionary.entry_length
+        )
+        .unwrap();
+
+        for entry in &dictionary.entries {
+            writeln!(
+                text,
+                "[{:4}] {:05x} {:10} {}",
+                entry.index,
+                entry.addr,
+                entry.word,
+                hex_bytes(&entry.data)
+            )
+            .unwrap();
+        }
+    }
+
+    fn objects_text(&self, text: &mut String) {
+        writeln!(text, "\n    **** Objects ****\n").unwrap();
+        writeln!(text, "Object count: {}", self.objects.len()).unwrap();
+
+        for object in &self.objects {
+            let attributes: Vec<String> = object.attributes.iter().map(|attr| attr.to_string()).collect();
+
+            writeln!(text, "\n{:3}. \"{}\"", object.number, object.name).unwrap();
+            writeln!(text, "     Attributes: {}", attributes.join(", ")).unwrap();
+            writeln!(
+                text,
+                "     Parent: {}  Sibling: {}  Child: {}",
+                object.parent, object.sibling, object.child
+            )
+            .unwrap();
+            writeln!(text, "     Property table: {:05x}", object.property_table).unwrap();
+
+            for property in &object.properties {
+                writeln!(text, "       [{:2}] {}", property.number, hex_bytes(&property.data)).unwrap();
+            }
+        }
+    }
+
+    fn abbreviations_text(&self, text: &mut String) {
+        writeln!(text, "\n    **** Abbreviations ****\n").unwrap();
+
+        for abbreviation in &self.abbreviations {
+            writeln!(
+                text,
+                "[{:2}] {:05x} \"{}\"",
+                abbreviation.index, abbreviation.addr, abbreviation.text
+            )
+            .unwrap();
+        }
+    }
+
+    fn strings_text(&self, text: &mut String) {
+        writeln!(text, "\n    **** Strings ****\n").unwrap();
+
+        for string in &self.strings {
+            writeln!(text, "{:05x}: \"{}\"", string.addr, string.text).unwrap();
+        }
+    }
+}
+
+// Entries past the end of the story (the count is the story's word) are
+// listed with the error as their word
+fn read_dictionary(zvm: &Zmachine) -> Result<DictionaryInfo, MemoryError> {
+    let memory = zvm.memory();
+    let dictionary = Dictionary::read(memory, zvm.header().dictionary)?;
+    let text_length = text_length(zvm.version());
+
+    let entries = (0..dictionary.entry_count)
+        .map(|index| {
+            let addr = dictionary.entry_addr(index);
+            let data_length = dictionary.entry_length.saturating_sub(text_length);
+
+            let (word, data) = match memory.try_read(addr + text_length, data_length) {
+                Ok(data) => (
+                    zvm.read_zstring(addr).unwrap_or_else(|err| format!("[{}]", err)),
+                    data.to_vec(),
+                ),
+                Err(err) => (format!("[{}]", err), Vec::new()),
+            };
+
+            DictionaryEntry {
+                index: index + 1,
+                addr,
+                word,
+                data,
+            }
+        })
+        .collect();
+
+    Ok(DictionaryInfo {
+        separators: dictionary.separators.iter().map(|&zscii| zscii as char).collect(),
+        entry_length: dictionary.entry_length,
+        entries,
+    })
+}
+
+fn read_objects(zvm: &Zmachine) -> Vec<ObjectInfo> {
+    (1..=zvm.get_total_object_count())
+        .map(|number| ObjectInfo {
+            number,
+            name: zvm.get_object_name(number),
+            parent: zvm.get_parent(number),
+            sibling: zvm.get_sibling(number),
+            child: zvm.get_child(number),
+            attributes: zvm.object_attributes(number),
+            property_table: zvm.get_object_prop_table_addr(number),
+            properties: zvm
+                .object_properties(number)
+                .into_iter()
+                .map(|(number, addr, data)| PropertyInfo { number, addr, data })
+                .collect(),
+        })
+        .collect()
+}
+
+fn read_abbreviations(zvm: &Zmachine) -> Vec<Abbreviation> {
+    // v1 has no abbreviations
+    if zvm.version() == 1 {
+        return Vec::new();
+    }
+
+    let memory = zvm.memory();
+    let table = zvm.header().abbreviations;
+
+    (0..96)
+        .map(|index| match memory.try_read_word(table + 2 * index as usize) {
+            Ok(word) => Abbreviation {
+                index,
+                addr: word as usize * 2,
+                text: zvm.get_abbrev(index).unwrap_or_else(|err| format!("[{}]", err)),
+            },
+            Err(err) => Abbreviation {
+                index,
+                addr: 0,
+                text: format!("[{}]", err),
+            },
+        })
+        .collect()
+}
+
+/// Reads everything about the story `zvm` was loaded with, an error if its
+/// dictionary header can't be read
+pub fn inspect(zvm: &Zmachine) -> Result<StoryInfo, MemoryError> {
+    Ok(StoryInfo {
+        header: zvm.header().clone(),
+        dictionary: read_dictionary(zvm)?,
+        objects: read_objects(zvm),
+        abbreviations: read_abbreviations(zvm),
+        strings: disassemble(zvm).strings,
+    })
+}
@@ -1,5 +1,12 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::hash;
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::buffer::MemoryError;
+use crate::zstring::ZStringError;
 
 enum_from_primitive! {
     #[allow(non_camel_case_types)]
@@ -39,26 +46,76 @@ enum_from_primitive! {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Up to 8 operands (call_vs2 and call_vn2 have the most), kept inline so
+/// decoding an instruction and reading its operands doesn't allocate
+#[derive(Clone, Copy, Default)]
+pub struct Operands<T: Copy + Default> {
+    items: [T; 8],
+    len: usize,
+}
+
+impl<T: Copy + Default> Operands<T> {
+    pub fn push(&mut self, value: T) {
+        assert!(self.len < self.items.len(), "Too many operands!");
+
+        self.items[self.len] = value;
+        self.len += 1;
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.items[..self.len]
+    }
+}
+
+impl<T: Copy + Default> Deref for Operands<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: Copy + Default> FromIterator<T> for Operands<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Operands<T> {
+        let mut operands = Operands::default();
+
+        for value in iter {
+            operands.push(value);
+        }
+
+        operands
+    }
+}
+
+impl<'a, T: Copy + Default> IntoIterator for &'a Operands<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_slice().iter()
+    }
+}
+
+impl<T: Copy + Default + fmt::Debug> fmt::Debug for Operands<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub enum OperandType {
     Small,
     Large,
     Variable,
+    #[default]
     Omitted,
 }
 
 impl OperandType {
-    pub fn from(bytes: &[u8]) -> Vec<OperandType> {
+    pub fn from(bytes: &[u8]) -> Operands<OperandType> {
         bytes
             .iter()
-            .fold(Vec::new(), |mut acc, n| {
-                acc.push((n & 0b1100_0000) >> 6);
-                acc.push((n & 0b0011_0000) >> 4);
-                acc.push((n & 0b0000_1100) >> 2);
-                acc.push(n & 0b0000_0011);
-                acc
-            })
-            .into_iter()
+            .flat_map(|n| [(n & 0b1100_0000) >> 6, (n & 0b0011_0000) >> 4, (n & 0b0000_1100) >> 2, n & 0b0000_0011])
             .map(|b| match b {
                 0b00 => OperandType::Large,
                 0b01 => OperandType::Small,
@@ -71,13 +128,20 @@ impl OperandType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Operand {
     Small(u8),
     Large(u16),
     Variable(u8),
 }
 
+// Only fills the unused slots in `Operands`
+impl Default for Operand {
+    fn default() -> Operand {
+        Operand::Small(0)
+    }
+}
+
 impl fmt::Display for Operand {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -92,19 +156,54 @@ impl fmt::Display for Operand {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Branch {
     pub condition: u16,
     pub address: Option<usize>,
     pub returns: Option<u16>,
 }
 
-#[derive(Debug)]
+/// Why there's no instruction at an address, e.g. after a bad jump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The instruction runs past the end of memory
+    Memory(MemoryError),
+    Text(ZStringError),
+    UnknownOpcode { addr: usize, number: u16 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Memory(ref err) => write!(f, "{}", err),
+            DecodeError::Text(ref err) => write!(f, "{}", err),
+            DecodeError::UnknownOpcode { addr, number } => {
+                write!(f, "Opcode not found: {} @ {:#x}", number, addr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<MemoryError> for DecodeError {
+    fn from(err: MemoryError) -> DecodeError {
+        DecodeError::Memory(err)
+    }
+}
+
+impl From<ZStringError> for DecodeError {
+    fn from(err: ZStringError) -> DecodeError {
+        DecodeError::Text(err)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Instruction {
     pub addr: usize,
     pub opcode: Opcode,
     pub name: String,
-    pub operands: Vec<Operand>,
+    pub operands: Operands<Operand>,
     pub store: Option<u8>,
     pub branch: Option<Branch>,
     pub text: Option<String>,
@@ -391,3 +490,54 @@ impl fmt::Display for Instruction {
         write!(f, "")
     }
 }
+
+/// Decoded instructions by address, for code that can't change. Sharing
+/// them means running one again doesn't allocate.
+#[derive(Debug, Default)]
+pub struct InstructionCache {
+    entries: HashMap<usize, Rc<Instruction>>,
+    hits: usize,
+    misses: usize,
+}
+
+impl InstructionCache {
+    pub fn new() -> InstructionCache {
+        InstructionCache::default()
+    }
+
+    pub fn get(&mut self, addr: usize) -> Option<Rc<Instruction>> {
+        match self.entries.get(&addr) {
+            Some(instr) => {
+                self.hits += 1;
+                Some(Rc::clone(instr))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, instr: Instruction) -> Rc<Instruction> {
+        let instr = Rc::new(instr);
+        self.entries.insert(instr.addr, Rc::clone(&instr));
+        instr
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Lookups that found a decoded instruction, and ones that didn't
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits, self.misses)
+    }
+}
@@ -10,22 +10,62 @@ extern crate hmac;
 extern crate sha2;
 
 pub mod ascii_art;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bench;
 pub mod buffer;
+pub mod coverage;
+pub mod debugger;
+pub mod dictionary;
+pub mod disassembler;
+pub mod events;
 pub mod frame;
 pub mod game;
+pub mod header;
 pub mod hints;
+pub mod inspector;
 pub mod instruction;
 pub mod options;
+pub mod profiler;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod protocol;
 pub mod quetzal;
+pub mod rewind;
+pub mod save_inspector;
 pub mod save_security;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+pub mod trace;
 pub mod traits;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tui;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ui_terminal;
 pub mod ui_web;
 pub mod zmachine;
+pub mod zstring;
 
 pub use ascii_art::AsciiArt;
+pub use buffer::{Buffer, MemoryError, Region};
+pub use coverage::{Coverage, CoverageReport};
+pub use debugger::{Breakpoint, DebugCommand, DebugStop, Debugger, Watchpoint};
+pub use dictionary::{encode_text, Dictionary};
+pub use disassembler::{disassemble, Disassembly};
+pub use events::{EventKind, EventQueue, HostEvent, QueuedEvent};
 pub use game::Game;
+pub use header::{HeaderError, StoryHeader};
+pub use inspector::{inspect, StoryInfo};
 pub use options::Options;
+pub use profiler::{ProfileReport, Profiler};
+pub use rewind::RewindLog;
+pub use save_inspector::{read_save, SaveDiff, SaveInfo};
 pub use save_security::SaveValidator;
+pub use trace::{InstructionTrace, TraceEntry, TraceRecord};
 pub use traits::UI;
-pub use ui_web::WebUI;
-pub use zmachine::Zmachine;
+#[cfg(not(target_arch = "wasm32"))]
+pub use tui::{TuiApp, TuiUI};
+#[cfg(not(target_arch = "wasm32"))]
+pub use ui_terminal::TerminalUI;
+pub use ui_web::{
+    ChannelSink, MessageSink, OutputFormat, RunKind, RunStyle, TextRun, VecSink, WebUI,
+};
+pub use zmachine::{CrashReport, StepOutcome, Zmachine};
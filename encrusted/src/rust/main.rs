@@ -0,0 +1,401 @@
+extern crate clap;
+extern crate encrusted;
+
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::io;
+use std::process;
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+
+use encrusted::protocol::{self, Session};
+use encrusted::{
+    Coverage, DebugCommand, Game, Options, OutputFormat, StepOutcome, StoryHeader, TerminalUI, TuiApp, UI,
+    Zmachine,
+};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn read_file(path: &str, what: &str) -> Vec<u8> {
+    match fs::read(path) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("\nCouldn't read {} {}: {}\n", what, path, err);
+            process::exit(1);
+        }
+    }
+}
+
+fn read_lines(path: &str, what: &str) -> Vec<String> {
+    String::from_utf8_lossy(&read_file(path, what))
+        .lines()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+fn load_story(matches: &ArgMatches) -> Vec<u8> {
+    let data = match matches.get_one::<String>("FILE") {
+        Some(path) => read_file(path, "story file"),
+        None => Game::story_data().to_vec(),
+    };
+
+    if let Err(err) = StoryHeader::parse(&data) {
+        eprintln!("\n{}\nIs this a z-code story file?\n", err);
+        process::exit(1);
+    }
+
+    data
+}
+
+fn make_ui(matches: &ArgMatches) -> Box<TerminalUI> {
+    let mut ui = TerminalUI::new();
+
+    if let Some(path) = matches.get_one::<String>("transcript") {
+        match File::create(path) {
+            Ok(file) => ui.set_transcript(file),
+            Err(err) => {
+                eprintln!("\nCouldn't create transcript {}: {}\n", path, err);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(path) = matches.get_one::<String>("script") {
+        ui.set_script(read_lines(path, "script"));
+    }
+
+    ui
+}
+
+fn restore_from_file(zvm: &mut Zmachine, path: &str) {
+    match fs::read_to_string(path) {
+        Ok(data) => zvm.restore(data.trim()),
+        Err(err) => {
+            zvm.ui.print(&format!("[Couldn't read {}: {}]\n", path, err));
+            zvm.restore("");
+        }
+    }
+}
+
+fn prompt_restore(zvm: &mut Zmachine) {
+    zvm.ui.print("\nRestore from file: ");
+    let path = zvm.ui.get_user_input();
+    let path = path.trim();
+
+    if path.is_empty() {
+        zvm.restore("");
+    } else {
+        restore_from_file(zvm, path);
+    }
+}
+
+// Reads debugger commands until one of them lets the story go on
+fn debug_prompt(zvm: &mut Zmachine, eof: &Cell<bool>) {
+    loop {
+        zvm.ui.print("(debug) ");
+        let line = zvm.ui.get_user_input();
+        if eof.get() {
+            return;
+        }
+
+        let resumes = DebugCommand::parse(&line).map_or(false, |command| command.resumes());
+
+        match zvm.debug_command(&line) {
+            Ok(output) if output.is_empty() => (),
+            Ok(output) => zvm.ui.print(&format!("{}\n", output)),
+            Err(err) => zvm.ui.print(&format!("{}\n", err)),
+        }
+
+        if resumes {
+            return;
+        }
+    }
+}
+
+// Meta commands are handled here instead of being passed on to the game
+fn handle_meta_command(zvm: &mut Zmachine, command: &str, eof: &Cell<bool>) -> bool {
+    match command {
+        "$undo" => {
+            if zvm.undo() {
+                let (_, room) = zvm.get_current_room();
+                zvm.ui.print(&format!("\n[Undone, back in: {}]\n\n>", room));
+            } else {
+                zvm.ui.print("\n>");
+            }
+            true
+        }
+        "$redo" => {
+            if zvm.redo() {
+                let (_, room) = zvm.get_current_room();
+                zvm.ui.print(&format!("\n[Redone, now in: {}]\n\n>", room));
+            } else {
+                zvm.ui.print("\n>");
+            }
+            true
+        }
+        "$debug" => {
+            zvm.ui.print("\n[Debugger, type help for commands]\n");
+            debug_prompt(zvm, eof);
+            zvm.ui.print("\n>");
+            true
+        }
+        _ => false,
+    }
+}
+
+// The sorted report goes to `path`, the folded stacks next to it
+fn write_profile(zvm: &Zmachine, path: &str) {
+    let folded = format!("{}.folded", path);
+
+    let written = fs::write(path, zvm.profiler.report().to_text())
+        .and_then(|_| fs::write(&folded, zvm.profiler.folded_stacks()));
+
+    if let Err(err) = written {
+        eprintln!("\nCouldn't write profile {}: {}\n", path, err);
+        process::exit(1);
+    }
+}
+
+// Merges the session into the coverage already in `path`, if there is any
+fn write_coverage(zvm: &Zmachine, path: &str) {
+    let mut coverage = zvm.coverage.clone();
+
+    if let Ok(json) = fs::read_to_string(path) {
+        if let Err(err) = Coverage::from_json(&json).and_then(|saved| coverage.merge(&saved)) {
+            eprintln!("\nCouldn't merge coverage into {}: {}\n", path, err);
+            process::exit(1);
+        }
+    }
+
+    if let Err(err) = fs::write(path, coverage.to_json()) {
+        eprintln!("\nCouldn't write coverage {}: {}\n", path, err);
+        process::exit(1);
+    }
+}
+
+fn run_tui(matches: &ArgMatches, data: Vec<u8>, opts: Options) {
+    let mut app = TuiApp::new(data, opts);
+
+    if let Some(path) = matches.get_one::<String>("restore") {
+        app.restore_from_file(path);
+    }
+
+    app.resume();
+
+    if let Err(err) = app.run() {
+        eprintln!("\nTerminal error: {}\n", err);
+        process::exit(1);
+    }
+}
+
+fn run_json(matches: &ArgMatches, data: Vec<u8>, mut opts: Options) {
+    // hosts get a crash event and can keep going instead of losing the story
+    opts.guarded = true;
+    let mut session = Session::new(data, opts);
+
+    if matches.get_flag("tokens") {
+        session.set_output_format(OutputFormat::Tokens);
+    }
+
+    let stdin = io::stdin();
+
+    if let Err(err) = protocol::run(&mut session, stdin.lock(), io::stdout().lock()) {
+        eprintln!("\nProtocol error: {}\n", err);
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let matches = Command::new("encrusted-cli")
+        .version(VERSION)
+        .about("Plays z-code stories in the terminal (defaults to the bundled h2g2)")
+        .arg(Arg::new("FILE").help("Story file to play"))
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("N")
+                .value_parser(value_parser!(u32))
+                .help("Seeds the random number generator for repeatable runs"),
+        )
+        .arg(
+            Arg::new("transcript")
+                .long("transcript")
+                .value_name("FILE")
+                .help("Writes everything shown on screen to a file"),
+        )
+        .arg(
+            Arg::new("script")
+                .long("script")
+                .value_name("FILE")
+                .help("Reads commands from a file (one per line) before stdin"),
+        )
+        .arg(
+            Arg::new("restore")
+                .long("restore")
+                .value_name("FILE")
+                .help("Starts from a file written by the in-game save command"),
+        )
+        .arg(
+            Arg::new("tui")
+                .long("tui")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["transcript", "script"])
+                .help("Full-screen mode with a map pane and a hint browser (F1)"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["transcript", "script", "restore", "tui"])
+                .help("Speaks the JSON-lines protocol on stdin/stdout (see PROTOCOL.md)"),
+        )
+        .arg(
+            Arg::new("tokens")
+                .long("tokens")
+                .action(ArgAction::SetTrue)
+                .requires("json")
+                .help("Sends story output as typed text runs instead of html"),
+        )
+        .arg(
+            Arg::new("debug")
+                .long("debug")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["json", "tui"])
+                .help("Starts in the debugger, before the first instruction"),
+        )
+        .arg(
+            Arg::new("rewind")
+                .long("rewind")
+                .value_name("N")
+                .value_parser(value_parser!(usize))
+                .conflicts_with_all(["json", "tui"])
+                .help("Keeps the last N instructions so the debugger can go back over them"),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("FILE")
+                .conflicts_with_all(["json", "tui"])
+                .help("On exit, writes instructions per routine and opcode to FILE and folded stacks to FILE.folded"),
+        )
+        .arg(
+            Arg::new("coverage")
+                .long("coverage")
+                .value_name("FILE")
+                .conflicts_with_all(["json", "tui"])
+                .help("On exit, adds the code this session reached to FILE (see encrusted-disasm --coverage)"),
+        )
+        .after_help(
+            "Use $undo and $redo to step through your move history, \
+             and $debug for the debugger.",
+        )
+        .get_matches();
+
+    let data = load_story(&matches);
+
+    let mut opts = Options::default();
+    opts.rand_seed = match matches.get_one::<u32>("seed") {
+        Some(&seed) => [seed, seed, seed, seed],
+        None => rand::random(),
+    };
+    opts.rewind = matches.get_one::<usize>("rewind").copied().unwrap_or(0);
+    opts.profile = matches.contains_id("profile");
+    opts.coverage = matches.contains_id("coverage");
+
+    if matches.get_flag("json") {
+        run_json(&matches, data, opts);
+        return;
+    }
+
+    if matches.get_flag("tui") {
+        run_tui(&matches, data, opts);
+        return;
+    }
+
+    let ui = make_ui(&matches);
+    let eof = ui.eof_flag();
+
+    let mut zvm = Game::load_story(data, ui, opts);
+    zvm.ui.clear();
+
+    if let Some(path) = matches.get_one::<String>("restore") {
+        restore_from_file(&mut zvm, path);
+    }
+
+    if matches.get_flag("debug") {
+        zvm.ui.print(&format!(
+            "[Debugger, type help for commands]\n{}\n",
+            zvm.debug_location()
+        ));
+        debug_prompt(&mut zvm, &eof);
+    }
+
+    // a fault still ends the session normally, so the reports get written
+    let mut fault = None;
+
+    loop {
+        let outcome = zvm.run(usize::MAX);
+        zvm.update_status_bar();
+        zvm.ui.flush();
+
+        let done = match outcome {
+            StepOutcome::Break(stop) => {
+                zvm.ui.print(&format!("\n[{}]\n{}\n", stop, zvm.debug_location()));
+                debug_prompt(&mut zvm, &eof);
+                if eof.get() {
+                    break;
+                }
+                continue;
+            }
+            StepOutcome::Quit => true,
+            // with a recording there's a way back to what caused it
+            StepOutcome::Error(message) if !zvm.rewind.is_empty() => {
+                zvm.ui.print(&format!("\n[{}]\n{}\n", message, zvm.debug_location()));
+                debug_prompt(&mut zvm, &eof);
+                if eof.get() {
+                    break;
+                }
+                continue;
+            }
+            StepOutcome::Error(message) => {
+                fault = Some(message);
+                break;
+            }
+            _ => false,
+        };
+
+        if done {
+            break;
+        }
+
+        if zvm.is_awaiting_restore() {
+            prompt_restore(&mut zvm);
+            continue;
+        }
+
+        let input = zvm.ui.get_user_input();
+        if eof.get() {
+            break;
+        }
+
+        if !handle_meta_command(&mut zvm, input.trim(), &eof) {
+            zvm.handle_input(input);
+        }
+    }
+
+    zvm.ui.flush();
+    zvm.ui.reset();
+
+    if let Some(path) = matches.get_one::<String>("profile") {
+        write_profile(&zvm, path);
+    }
+
+    if let Some(path) = matches.get_one::<String>("coverage") {
+        write_coverage(&zvm, path);
+    }
+
+    if let Some(message) = fault {
+        eprintln!("\n\n{}\n\n", message);
+        process::exit(1);
+    }
+}
@@ -1,11 +1,30 @@
 pub struct Options {
     pub rand_seed: [u32; 4],
+    // roll back to the last prompt on interpreter faults, see Zmachine::run
+    pub guarded: bool,
+    // instructions kept in Zmachine::instr_log, 0 is off
+    pub trace: usize,
+    // instructions the debugger can step back over, 0 is off
+    pub rewind: usize,
+    // illegal story writes (static memory, read-only header bytes) print a
+    // warning and go through instead of faulting
+    pub memory_warnings: bool,
+    // count instructions per routine and opcode into Zmachine::profiler
+    pub profile: bool,
+    // record the code a session reaches into Zmachine::coverage
+    pub coverage: bool,
 }
 
 impl Options {
     pub fn default() -> Options {
         Options {
             rand_seed: [90, 111, 114, 107],
+            guarded: false,
+            trace: 0,
+            rewind: 0,
+            memory_warnings: false,
+            profile: false,
+            coverage: false,
         }
     }
 }
@@ -0,0 +1,201 @@
+//! Optional instruction profiler: instructions run and calls made per
+//! routine (keyed by the address `do_call` unpacks), how often each opcode
+//! runs, and the routine stacks from `frames` as folded stacks for
+//! flamegraph tools (`inferno-flamegraph`, `flamegraph.pl`).
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::instruction::Instruction;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutineProfile {
+    /// 0 for the main routine in v1-5 (which isn't called), and for frames
+    /// restored from a save, which don't say what routine they're in
+    pub addr: usize,
+    pub calls: u64,
+    /// Instructions run in the routine itself, not in routines it called
+    pub instructions: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpcodeProfile {
+    pub name: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileReport {
+    pub instructions: u64,
+    /// Most instructions first
+    pub routines: Vec<RoutineProfile>,
+    /// Most frequent first
+    pub opcodes: Vec<OpcodeProfile>,
+}
+
+fn routine_name(addr: usize) -> String {
+    match addr {
+        0 => String::from("main"),
+        _ => format!("{:05x}", addr),
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    count as f64 * 100.0 / total.max(1) as f64
+}
+
+impl ProfileReport {
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        writeln!(text, "Instructions: {}\n", self.instructions).unwrap();
+        writeln!(text, "{:>8} {:>12} {:>7} {:>10}", "routine", "instructions", "%", "calls").unwrap();
+
+        for routine in &self.routines {
+            writeln!(
+                text,
+                "{:>8} {:>12} {:>6.2}% {:>10}",
+                routine_name(routine.addr),
+                routine.instructions,
+                percent(routine.instructions, self.instructions),
+                routine.calls
+            )
+            .unwrap();
+        }
+
+        writeln!(text, "\n{:>16} {:>12} {:>7}", "opcode", "count", "%").unwrap();
+
+        for opcode in &self.opcodes {
+            writeln!(
+                text,
+                "{:>16} {:>12} {:>6.2}%",
+                opcode.name,
+                opcode.count,
+                percent(opcode.count, self.instructions)
+            )
+            .unwrap();
+        }
+
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Counts nothing until it's enabled
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    enabled: bool,
+    instructions: u64,
+    routines: HashMap<usize, RoutineProfile>,
+    // by opcode number, with its name
+    opcodes: HashMap<u16, OpcodeProfile>,
+    // instructions run by routine stack, outermost first
+    stacks: HashMap<Vec<usize>, u64>,
+    // reused for the current stack
+    stack: Vec<usize>,
+}
+
+impl Profiler {
+    pub fn new(enabled: bool) -> Profiler {
+        Profiler {
+            enabled,
+            ..Profiler::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turning it off keeps what was counted so far
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn clear(&mut self) {
+        self.instructions = 0;
+        self.routines.clear();
+        self.opcodes.clear();
+        self.stacks.clear();
+    }
+
+    fn routine(&mut self, addr: usize) -> &mut RoutineProfile {
+        self.routines.entry(addr).or_insert_with(|| RoutineProfile {
+            addr,
+            ..RoutineProfile::default()
+        })
+    }
+
+    /// Counts `instr`, run by the innermost of the routines in `stack`
+    pub fn record_instruction(&mut self, stack: impl Iterator<Item = usize>, instr: &Instruction) {
+        if !self.enabled {
+            return;
+        }
+
+        self.stack.clear();
+        self.stack.extend(stack);
+
+        let routine = self.stack.last().cloned().unwrap_or(0);
+        self.instructions += 1;
+        self.routine(routine).instructions += 1;
+
+        self.opcodes
+            .entry(instr.opcode as u16)
+            .or_insert_with(|| OpcodeProfile {
+                name: instr.name.clone(),
+                count: 0,
+            })
+            .count += 1;
+
+        // only a stack seen for the first time allocates
+        match self.stacks.get_mut(self.stack.as_slice()) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.stack.clone(), 1);
+            }
+        }
+    }
+
+    pub fn record_call(&mut self, routine: usize) {
+        if self.enabled {
+            self.routine(routine).calls += 1;
+        }
+    }
+
+    pub fn report(&self) -> ProfileReport {
+        let mut routines: Vec<RoutineProfile> = self.routines.values().cloned().collect();
+        routines.sort_by(|a, b| b.instructions.cmp(&a.instructions).then(a.addr.cmp(&b.addr)));
+
+        let mut opcodes: Vec<OpcodeProfile> = self.opcodes.values().cloned().collect();
+        opcodes.sort_by(|a, b| b.count.cmp(&a.count).then(a.name.cmp(&b.name)));
+
+        ProfileReport {
+            instructions: self.instructions,
+            routines,
+            opcodes,
+        }
+    }
+
+    /// One `main;0572c;06a10 123` line per routine stack, sorted
+    pub fn folded_stacks(&self) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(stack, count)| {
+                let names: Vec<String> = stack.iter().map(|&addr| routine_name(addr)).collect();
+                format!("{} {}", names.join(";"), count)
+            })
+            .collect();
+
+        lines.sort();
+
+        let mut text = lines.join("\n");
+        text.push('\n');
+        text
+    }
+}
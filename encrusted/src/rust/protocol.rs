@@ -0,0 +1,357 @@
+//! JSON-lines protocol for driving the interpreter from other programs.
+//!
+//! Hosts write one JSON request per line and read back one JSON event per
+//! line. The events are the messages `WebUI` sends to the web app, see
+//! PROTOCOL.md for the full description.
+
+use std::boxed::Box;
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::events::HostEvent;
+use crate::game::Game;
+use crate::options::Options;
+use crate::ui_web::{OutputFormat, WebUI};
+use crate::zmachine::{StepOutcome, Zmachine};
+
+pub use crate::events::PROTOCOL_VERSION;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Request {
+    Input {
+        text: String,
+    },
+    Undo,
+    Redo,
+    Save,
+    Restore {
+        data: String,
+    },
+    Hints {
+        #[serde(default)]
+        location: Option<String>,
+        #[serde(default)]
+        question: Option<usize>,
+        #[serde(default)]
+        level: usize,
+    },
+    Tree,
+    Location,
+    Output {
+        format: OutputFormat,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub data: Value,
+}
+
+impl Event {
+    pub fn new(kind: &str, data: Value) -> Event {
+        Event {
+            kind: kind.to_string(),
+            data,
+        }
+    }
+
+    pub fn error(message: &str) -> Event {
+        Event::new("error", Value::from(message))
+    }
+
+    /// First line written by the server
+    pub fn hello() -> Event {
+        Event::new(
+            "hello",
+            json!({
+                "protocol": PROTOCOL_VERSION,
+                "version": env!("CARGO_PKG_VERSION"),
+            }),
+        )
+    }
+}
+
+impl From<HostEvent> for Event {
+    fn from(event: HostEvent) -> Event {
+        Event::new(event.kind().as_str(), event.data())
+    }
+}
+
+/// One running story, driven by protocol requests
+pub struct Session {
+    zvm: Zmachine,
+    events: Rc<RefCell<Vec<Event>>>,
+    format: Rc<Cell<OutputFormat>>,
+    last_save: Option<Value>,
+    generation: u64,
+    done: bool,
+}
+
+impl Session {
+    pub fn new(data: Vec<u8>, opts: Options) -> Session {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let queue = Rc::clone(&events);
+        let ui = WebUI::with_sink(Box::new(move |mtype: &str, msg: &str| {
+            if let Some(event) = HostEvent::from_message(mtype, msg) {
+                queue.borrow_mut().push(Event::from(event));
+            }
+        }));
+        let format = ui.format_handle();
+
+        Session {
+            zvm: Game::load_story(data, ui, opts),
+            events,
+            format,
+            last_save: None,
+            generation: 0,
+            done: false,
+        }
+    }
+
+    /// Runs the story up to the first prompt
+    pub fn start(&mut self) -> Vec<Event> {
+        self.run_story();
+        self.finish()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Whether story output comes as `print` (html) or `tokens` (runs) events
+    pub fn set_output_format(&mut self, format: OutputFormat) {
+        self.format.set(format);
+    }
+
+    /// Base64 save from the last prompt, the format save files use
+    pub fn save_data(&self) -> Option<&str> {
+        self.last_save.as_ref().and_then(|save| save[1].as_str())
+    }
+
+    /// Parses and handles one request line, bad requests get an error event
+    pub fn handle_line(&mut self, line: &str) -> Vec<Event> {
+        match serde_json::from_str::<Request>(line) {
+            Ok(request) => self.handle(request),
+            Err(err) => {
+                self.push(Event::error(&format!("Invalid request: {}", err)));
+                self.finish()
+            }
+        }
+    }
+
+    pub fn handle(&mut self, request: Request) -> Vec<Event> {
+        match request {
+            Request::Input { text } => {
+                if self.done {
+                    self.push(Event::error("The story has ended"));
+                } else if self.zvm.is_awaiting_restore() {
+                    self.push(Event::error("Waiting for a restore request"));
+                } else {
+                    self.zvm.handle_input(text);
+                    self.run_story();
+                }
+            }
+            Request::Undo => {
+                if self.zvm.undo() {
+                    self.zvm.ui.flush();
+                    self.push_updates();
+                } else {
+                    self.push(Event::error("Nothing to undo"));
+                }
+            }
+            Request::Redo => {
+                if self.zvm.redo() {
+                    self.zvm.ui.flush();
+                    self.push_updates();
+                } else {
+                    self.push(Event::error("Nothing to redo"));
+                }
+            }
+            Request::Save => match self.last_save.clone() {
+                Some(data) => self.push(Event::new("save", data)),
+                None => self.push(Event::error("Nothing to save yet")),
+            },
+            Request::Restore { data } => {
+                if self.done {
+                    self.push(Event::error("The story has ended"));
+                } else if self.zvm.is_awaiting_restore() {
+                    self.zvm.restore(&data);
+                    self.run_story();
+                } else {
+                    self.zvm.load_savestate(&data);
+                    self.run_story();
+                }
+            }
+            Request::Hints {
+                location,
+                question,
+                level,
+            } => self.push_hints(location, question, level),
+            Request::Tree => {
+                let tree = HostEvent::Tree(self.zvm.get_object_tree());
+                self.push(Event::from(tree));
+            }
+            Request::Location => {
+                let (number, name) = self.zvm.get_current_room();
+                self.push(Event::from(HostEvent::Map(number, name)));
+            }
+            Request::Output { format } => self.set_output_format(format),
+        }
+
+        self.finish()
+    }
+
+    fn push(&mut self, event: Event) {
+        self.events.borrow_mut().push(event);
+    }
+
+    fn run_story(&mut self) {
+        // there's no debugger in the protocol, so breakpoints don't stop it
+        let outcome = loop {
+            match self.zvm.run(usize::MAX) {
+                StepOutcome::Break(_) => continue,
+                outcome => break outcome,
+            }
+        };
+        self.zvm.ui.flush();
+
+        // the pc is stuck on the faulty instruction, so there's no going on
+        if let StepOutcome::Error(message) = outcome {
+            self.push(Event::error(&message));
+            self.done = true;
+            return;
+        }
+
+        self.done = outcome == StepOutcome::Quit;
+        self.push_updates();
+    }
+
+    // Same updates the wasm build sends after every step
+    fn push_updates(&mut self) {
+        let (number, name) = self.zvm.get_current_room();
+        let tree = self.zvm.get_object_tree();
+
+        self.zvm.update_status_bar();
+        self.zvm.ui.event(HostEvent::Map(number, name));
+        self.zvm.ui.event(HostEvent::Tree(tree));
+    }
+
+    // Questions are listed without their answers, those are asked for one
+    // level at a time so hosts can reveal them progressively
+    fn push_hints(&mut self, location: Option<String>, question: Option<usize>, level: usize) {
+        let hint_system = self.zvm.get_hint_system();
+
+        if let Some(question) = question {
+            let event = match hint_system.get_answer_at_level(question, level) {
+                Some(answer) => Event::new(
+                    "hint",
+                    json!({
+                        "question": question,
+                        "level": level,
+                        "levels": hint_system.get_answer_count(question),
+                        "answer": answer,
+                    }),
+                ),
+                None => Event::error(&format!("No such hint question: {}", question)),
+            };
+
+            self.push(event);
+            return;
+        }
+
+        let location = location.unwrap_or_else(|| self.zvm.get_current_room().1);
+        let hint_system = self.zvm.get_hint_system();
+
+        let questions: Vec<(usize, String, String)> = if location == "GENERAL" {
+            hint_system.get_hints_for_state(Some("GENERAL"))
+        } else {
+            hint_system
+                .get_questions_for_location(&location)
+                .into_iter()
+                .map(|(idx, q)| (idx, q.question, q.section))
+                .collect()
+        };
+
+        let list: Vec<Value> = questions
+            .into_iter()
+            .map(|(idx, question, section)| {
+                json!({
+                    "question": idx,
+                    "text": question,
+                    "section": section,
+                    "levels": hint_system.get_answer_count(idx),
+                })
+            })
+            .collect();
+
+        self.push(Event::new(
+            "hints",
+            json!({ "location": location, "questions": list }),
+        ));
+    }
+
+    // Every response ends with a ready event saying what the story waits for
+    fn finish(&mut self) -> Vec<Event> {
+        let mut events: Vec<Event> = self.events.borrow_mut().drain(..).collect();
+
+        if let Some(event) = events.iter().rev().find(|e| e.kind == "savestate") {
+            self.last_save = Some(event.data.clone());
+        }
+
+        self.generation += 1;
+
+        let expects = if self.done {
+            "none"
+        } else if self.zvm.is_awaiting_restore() {
+            "restore"
+        } else {
+            "line"
+        };
+
+        events.push(Event::new(
+            "ready",
+            json!({ "gen": self.generation, "expects": expects }),
+        ));
+
+        events
+    }
+}
+
+fn write_events<W: Write>(output: &mut W, events: &[Event]) -> io::Result<()> {
+    for event in events {
+        serde_json::to_writer(&mut *output, event)?;
+        output.write_all(b"\n")?;
+    }
+
+    output.flush()
+}
+
+/// Serves requests from `input` until it runs out or the story ends
+pub fn run<R: BufRead, W: Write>(session: &mut Session, input: R, mut output: W) -> io::Result<()> {
+    write_events(&mut output, &[Event::hello()])?;
+    write_events(&mut output, &session.start())?;
+
+    let mut lines = input.lines();
+
+    while !session.is_done() {
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        write_events(&mut output, &session.handle_line(&line))?;
+    }
+
+    Ok(())
+}
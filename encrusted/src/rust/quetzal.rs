@@ -92,6 +92,30 @@ impl QuetzalSave {
         Ok(save)
     }
 
+    /// The id and body of every chunk inside the FORM, in file order
+    pub fn chunks(save_data: &[u8]) -> Result<Vec<(String, &[u8])>, QuetzalError> {
+        let (form_header, _, form_body) = QuetzalSave::read_chunk(save_data)?;
+        if form_header != "FORM" {
+            return Err(QuetzalError::MissingFormHeader);
+        }
+
+        if form_body.len() < 4 {
+            return Err(QuetzalError::InvalidChunkHeader);
+        }
+
+        let chunks = &form_body[4..]; // skip the IFZS string at the start
+        let mut found = Vec::new();
+        let mut offset = 0;
+
+        while offset + 8 <= chunks.len() {
+            let (header, length, body) = QuetzalSave::read_chunk(&chunks[offset..])?;
+            found.push((header, body));
+            offset = offset.checked_add(length).unwrap_or(chunks.len());
+        }
+
+        Ok(found)
+    }
+
     pub fn make(
         pc: usize,
         current: &[u8],
@@ -0,0 +1,142 @@
+//! Per-instruction undo log for stepping backwards in the debugger. Each
+//! delta holds what one instruction overwrote, so going back a step puts
+//! it all back; the log starts over at every command the player types.
+
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+
+use crate::frame::Frame;
+
+/// A change to the frames, in the order the instruction made them
+#[derive(Debug)]
+pub enum FrameChange {
+    Local { index: u8, old: u16 },
+    StackPush,
+    StackPop(u16),
+    Call,
+    Return(Frame),
+}
+
+/// Everything needed to undo one instruction
+#[derive(Debug)]
+pub struct InstructionDelta {
+    pub pc: usize,
+    /// Old values of the bytes written, in the order they were written
+    pub memory: Vec<(usize, u8)>,
+    pub frames: Vec<FrameChange>,
+    /// Random number generator before a `random` instruction
+    pub rng: Option<StdRng>,
+}
+
+/// Keeps the deltas of the last `capacity` instructions, 0 turns it off
+#[derive(Debug, Default)]
+pub struct RewindLog {
+    capacity: usize,
+    deltas: VecDeque<InstructionDelta>,
+    // the instruction being run
+    pending: Option<InstructionDelta>,
+}
+
+impl RewindLog {
+    pub fn new(capacity: usize) -> RewindLog {
+        RewindLog {
+            capacity,
+            deltas: VecDeque::new(),
+            pending: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// True while an instruction's changes are being noted
+    pub fn is_recording(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Shrinking drops the oldest deltas
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+
+        while self.deltas.len() > capacity {
+            self.deltas.pop_front();
+        }
+
+        if capacity == 0 {
+            self.pending = None;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Forgets everything, for changes that can't be undone one instruction
+    /// at a time (input, restores, restarts)
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+        self.pending = None;
+    }
+
+    pub fn begin(&mut self, pc: usize) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.pending = Some(InstructionDelta {
+            pc,
+            memory: Vec::new(),
+            frames: Vec::new(),
+            rng: None,
+        });
+    }
+
+    pub fn note(&mut self, change: FrameChange) {
+        if let Some(ref mut delta) = self.pending {
+            delta.frames.push(change);
+        }
+    }
+
+    pub fn note_rng(&mut self, rng: &StdRng) {
+        if let Some(ref mut delta) = self.pending {
+            delta.rng.get_or_insert_with(|| rng.clone());
+        }
+    }
+
+    /// Keeps the pending delta, with the memory journal of the instruction
+    pub fn finish(&mut self, memory: Vec<(usize, u8)>) {
+        let mut delta = match self.pending.take() {
+            Some(delta) => delta,
+            None => return,
+        };
+
+        delta.memory = memory;
+
+        if self.deltas.len() == self.capacity {
+            self.deltas.pop_front();
+        }
+
+        self.deltas.push_back(delta);
+    }
+
+    /// Drops the pending delta, for instructions that paused before changing
+    /// anything
+    pub fn discard(&mut self) {
+        self.pending = None;
+    }
+
+    /// The newest delta, to undo
+    pub fn pop(&mut self) -> Option<InstructionDelta> {
+        self.deltas.pop_back()
+    }
+}
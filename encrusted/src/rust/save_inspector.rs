@@ -0,0 +1,326 @@
+//! Opens saves from outside the engine, for triaging broken ones: checks
+//! the `SaveValidator` envelope, lists the Quetzal chunks and frames, and
+//! diffs two saves by what they mean to the story (objects, attributes,
+//! globals, score and turns) rather than by bytes.
+
+use std::fmt::Write;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::game::Game;
+use crate::options::Options;
+use crate::quetzal::QuetzalSave;
+use crate::save_security::SaveValidator;
+use crate::ui_web::{VecSink, WebUI};
+
+/// What was around the Quetzal data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Envelope {
+    /// A `SaveValidator` envelope whose CRC and signature checked out
+    Signed { version: u8, crc32: u32 },
+    /// Plain Quetzal, like `get_save_state` exports
+    Bare,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkInfo {
+    pub id: String,
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectState {
+    pub number: u16,
+    pub name: String,
+    pub parent: u16,
+    pub attributes: Vec<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SaveInfo {
+    pub envelope: Envelope,
+    pub release: u16,
+    pub serial: String,
+    pub checksum: u16,
+    pub pc: usize,
+    pub chunks: Vec<ChunkInfo>,
+    /// Outermost first, as `Frame` displays them
+    pub frames: Vec<String>,
+    pub memory_length: usize,
+    pub location: String,
+    /// Globals 1 and 2, the status line's score and turns in score games
+    pub score: i16,
+    pub turns: u16,
+    pub globals: Vec<u16>,
+    pub objects: Vec<ObjectState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectMove {
+    pub number: u16,
+    pub name: String,
+    pub from: u16,
+    pub from_name: String,
+    pub to: u16,
+    pub to_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttributeChange {
+    pub number: u16,
+    pub name: String,
+    pub attribute: u16,
+    /// True if the attribute was set, false if it was cleared
+    pub set: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlobalChange {
+    pub index: u8,
+    pub old: u16,
+    pub new: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SaveDiff {
+    pub moved: Vec<ObjectMove>,
+    pub attributes: Vec<AttributeChange>,
+    pub globals: Vec<GlobalChange>,
+    pub score: i32,
+    pub turns: i32,
+}
+
+// Unwraps the envelope, saves exported without one are plain Quetzal
+fn open_envelope(data: &[u8], key: &[u8; 32]) -> Result<(Envelope, Vec<u8>), String> {
+    if data.starts_with(b"FORM") {
+        return Ok((Envelope::Bare, data.to_vec()));
+    }
+
+    let quetzal = SaveValidator::validate_and_extract(data, key)?;
+    let envelope = Envelope::Signed {
+        version: data[0],
+        crc32: u32::from_le_bytes([data[1], data[2], data[3], data[4]]),
+    };
+
+    Ok((envelope, quetzal))
+}
+
+/// Reads a base64 save made by `story`, by restoring it into a fresh
+/// interpreter and reading the story's state back out
+pub fn read_save(story: &[u8], save: &str) -> Result<SaveInfo, String> {
+    let data = BASE64
+        .decode(save.trim())
+        .map_err(|err| format!("Save isn't base64: {}", err))?;
+
+    let ui = WebUI::with_sink(Box::new(VecSink::new()));
+    let mut zvm = Game::try_load_story(story.to_vec(), ui, Options::default()).map_err(|err| err.to_string())?;
+
+    let (envelope, quetzal) = open_envelope(&data, zvm.secret_key())?;
+
+    let chunks = QuetzalSave::chunks(&quetzal).map_err(|err| err.to_string())?;
+    let ifhd = chunks
+        .iter()
+        .find(|(id, _)| id == "IFhd")
+        .map(|(_, body)| *body)
+        .filter(|body| body.len() >= 13)
+        .ok_or_else(|| "Save has no IFhd chunk".to_string())?;
+
+    let original = zvm.memory().read(0, zvm.header().static_memory).to_vec();
+    let parsed = QuetzalSave::from_bytes(&quetzal, &original).map_err(|err| err.to_string())?;
+
+    let story_checksum = zvm.header().checksum;
+    if parsed.chksum != story_checksum {
+        return Err(format!(
+            "Save is for a different story: checksum {:04x}, the story's is {:04x}",
+            parsed.chksum, story_checksum
+        ));
+    }
+
+    // restore_state leaves the fresh story as it is for these
+    if parsed.memory.len() > zvm.header().static_memory {
+        return Err(format!(
+            "Save has {} bytes of memory, the story's dynamic memory is {}",
+            parsed.memory.len(),
+            zvm.header().static_memory
+        ));
+    }
+
+    zvm.restore_state(&quetzal);
+
+    let objects = (1..=zvm.get_total_object_count())
+        .map(|number| ObjectState {
+            number,
+            name: zvm.get_object_name(number),
+            parent: zvm.get_parent(number),
+            attributes: zvm.object_attributes(number),
+        })
+        .collect();
+
+    Ok(SaveInfo {
+        envelope,
+        release: u16::from(ifhd[0]) << 8 | u16::from(ifhd[1]),
+        serial: String::from_utf8_lossy(&ifhd[2..8]).into_owned(),
+        checksum: parsed.chksum,
+        pc: parsed.pc,
+        chunks: chunks
+            .iter()
+            .map(|(id, body)| ChunkInfo {
+                id: id.clone(),
+                length: body.len(),
+            })
+            .collect(),
+        frames: parsed.frames.iter().map(|frame| frame.to_string()).collect(),
+        memory_length: parsed.memory.len(),
+        location: zvm.get_object_name(zvm.read_global(0)),
+        score: zvm.read_global(1) as i16,
+        turns: zvm.read_global(2),
+        globals: (0..240).map(|index| zvm.read_global(index)).collect(),
+        objects,
+    })
+}
+
+impl SaveInfo {
+    fn object_name(&self, number: u16) -> String {
+        match number {
+            0 => String::from("nothing"),
+            _ => self
+                .objects
+                .get(number as usize - 1)
+                .map(|object| object.name.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        match self.envelope {
+            Envelope::Signed { version, crc32 } => {
+                writeln!(text, "Envelope: version {}, CRC32 {:08x}, signature ok", version, crc32).unwrap()
+            }
+            Envelope::Bare => writeln!(text, "Envelope: none (plain Quetzal)").unwrap(),
+        }
+
+        writeln!(
+            text,
+            "Story: release {}, serial {}, checksum {:04x}",
+            self.release, self.serial, self.checksum
+        )
+        .unwrap();
+        writeln!(text, "PC: {:05x}", self.pc).unwrap();
+        writeln!(
+            text,
+            "Location: {}, score {}, turns {}",
+            self.location, self.score, self.turns
+        )
+        .unwrap();
+
+        writeln!(text, "\nChunks:").unwrap();
+        for chunk in &self.chunks {
+            writeln!(text, "  {} ({} bytes)", chunk.id, chunk.length).unwrap();
+        }
+        writeln!(text, "  dynamic memory: {} bytes", self.memory_length).unwrap();
+
+        writeln!(text, "\nFrames:").unwrap();
+        for (depth, frame) in self.frames.iter().enumerate() {
+            writeln!(text, "  #{} {}", depth, frame).unwrap();
+        }
+
+        text
+    }
+
+    /// What changed between this save and a later one
+    pub fn diff(&self, later: &SaveInfo) -> SaveDiff {
+        let mut moved = Vec::new();
+        let mut attributes = Vec::new();
+
+        for (old, new) in self.objects.iter().zip(later.objects.iter()) {
+            if old.parent != new.parent {
+                moved.push(ObjectMove {
+                    number: new.number,
+                    name: new.name.clone(),
+                    from: old.parent,
+                    from_name: self.object_name(old.parent),
+                    to: new.parent,
+                    to_name: later.object_name(new.parent),
+                });
+            }
+
+            let cleared = old.attributes.iter().filter(|attr| !new.attributes.contains(attr));
+            let set = new.attributes.iter().filter(|attr| !old.attributes.contains(attr));
+
+            for (&attribute, set) in cleared.map(|attr| (attr, false)).chain(set.map(|attr| (attr, true))) {
+                attributes.push(AttributeChange {
+                    number: new.number,
+                    name: new.name.clone(),
+                    attribute,
+                    set,
+                });
+            }
+        }
+
+        let globals = self
+            .globals
+            .iter()
+            .zip(later.globals.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(index, (&old, &new))| GlobalChange {
+                index: index as u8,
+                old,
+                new,
+            })
+            .collect();
+
+        SaveDiff {
+            moved,
+            attributes,
+            globals,
+            score: i32::from(later.score) - i32::from(self.score),
+            turns: i32::from(later.turns) - i32::from(self.turns),
+        }
+    }
+}
+
+impl SaveDiff {
+    pub fn is_empty(&self) -> bool {
+        self.moved.is_empty() && self.attributes.is_empty() && self.globals.is_empty()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        writeln!(text, "Score {:+}, turns {:+}", self.score, self.turns).unwrap();
+
+        if !self.moved.is_empty() {
+            writeln!(text, "\nMoved:").unwrap();
+        }
+        for object in &self.moved {
+            writeln!(
+                text,
+                "  {} ({}): {} ({}) -> {} ({})",
+                object.name, object.number, object.from_name, object.from, object.to_name, object.to
+            )
+            .unwrap();
+        }
+
+        if !self.attributes.is_empty() {
+            writeln!(text, "\nAttributes:").unwrap();
+        }
+        for change in &self.attributes {
+            let sign = if change.set { '+' } else { '-' };
+            writeln!(text, "  {} ({}): {}{}", change.name, change.number, sign, change.attribute).unwrap();
+        }
+
+        if !self.globals.is_empty() {
+            writeln!(text, "\nGlobals:").unwrap();
+        }
+        for global in &self.globals {
+            writeln!(text, "  g{}: {:04x} -> {:04x}", global.index, global.old, global.new).unwrap();
+        }
+
+        text
+    }
+}
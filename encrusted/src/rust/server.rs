@@ -0,0 +1,565 @@
+//! Multi-session game server.
+//!
+//! Sessions are managed over HTTP and played over HTTP or a WebSocket, both
+//! speaking the JSON protocol from `protocol.rs`. A story isn't `Send`, so
+//! every session lives on a thread of its own and connection threads talk to
+//! it through a channel.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::json;
+use tiny_http::{Header, Method, Request as HttpRequest, Response};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::options::Options;
+use crate::protocol::{Event, Request, Session};
+
+// How often the sessions thread looks for idle sessions
+const EVICT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Protocol requests are one line of JSON, anything bigger is refused
+const MAX_BODY: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub story: Vec<u8>,
+    /// Sessions are saved here after every request and resumed from here
+    /// after they have been evicted (or the server restarted)
+    pub autosave_dir: Option<PathBuf>,
+    pub idle_timeout: Duration,
+    pub seed: Option<u32>,
+    /// Sent as `Access-Control-Allow-Origin`, no CORS header if it's `None`
+    pub allowed_origin: Option<String>,
+    /// Running sessions at most (each has a thread and a story), new ones
+    /// are refused past it
+    pub max_sessions: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerError {
+    NoSuchSession(String),
+    Full(usize),
+    Stopped,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ServerError::NoSuchSession(ref id) => write!(f, "No such session: {}", id),
+            ServerError::Full(max) => write!(f, "The server is full ({} sessions), try again later", max),
+            ServerError::Stopped => write!(f, "The server is shutting down"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub idle_secs: u64,
+    pub done: bool,
+}
+
+// What a session's thread is asked to do, in order
+enum Job {
+    Start(Sender<Vec<Event>>),
+    Resume(String),
+    Line(String, Sender<Vec<Event>>),
+    // deletes the autosave and ends the thread, true once it's done
+    Close(Sender<bool>),
+}
+
+// Written by the session's thread, read by whoever lists the sessions
+#[derive(Default)]
+struct WorkerState {
+    done: AtomicBool,
+}
+
+struct Entry {
+    jobs: Sender<Job>,
+    state: Arc<WorkerState>,
+    last_used: Instant,
+}
+
+/// All running sessions. Each one runs on a thread of its own (a story
+/// isn't `Send`, so it's created there too), one long turn doesn't hold up
+/// the others.
+pub struct Sessions {
+    config: ServerConfig,
+    entries: HashMap<String, Entry>,
+}
+
+impl Sessions {
+    pub fn new(config: ServerConfig) -> Sessions {
+        if let Some(ref dir) = config.autosave_dir {
+            let _ = fs::create_dir_all(dir);
+        }
+
+        Sessions {
+            config,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn options(&self) -> Options {
+        let mut opts = Options::default();
+        opts.rand_seed = match self.config.seed {
+            Some(seed) => [seed, seed, seed, seed],
+            None => rand::random(),
+        };
+        // a faulty story mustn't take its thread (and the session) down
+        opts.guarded = true;
+
+        opts
+    }
+
+    // Starts the session's thread, its jobs run one after the other
+    fn spawn(&mut self, id: &str) -> Sender<Job> {
+        let (jobs, rx) = mpsc::channel();
+        let state = Arc::new(WorkerState::default());
+        let story = self.config.story.clone();
+        let opts = self.options();
+        let autosave_path = self.autosave_path(id);
+        let worker_state = Arc::clone(&state);
+
+        thread::spawn(move || {
+            let mut session = Session::new(story, opts);
+
+            for job in rx {
+                match job {
+                    Job::Start(reply) => {
+                        let _ = reply.send(session.start());
+                    }
+                    Job::Resume(data) => {
+                        session.start();
+                        session.handle(Request::Restore { data });
+                    }
+                    Job::Line(line, reply) => {
+                        let _ = reply.send(session.handle_line(&line));
+                    }
+                    // after the last autosave, so nothing writes it again
+                    Job::Close(reply) => {
+                        if let Some(ref path) = autosave_path {
+                            let _ = fs::remove_file(path);
+                        }
+                        let _ = reply.send(true);
+                        return;
+                    }
+                }
+
+                worker_state.done.store(session.is_done(), Ordering::Relaxed);
+                autosave(autosave_path.as_ref(), &session);
+            }
+        });
+
+        self.entries.insert(
+            id.to_string(),
+            Entry {
+                jobs: jobs.clone(),
+                state,
+                last_used: Instant::now(),
+            },
+        );
+
+        jobs
+    }
+
+    // Picks a new id and starts its thread, the story starts with Job::Start
+    fn open(&mut self) -> Result<(String, Sender<Job>), ServerError> {
+        if self.entries.len() >= self.config.max_sessions {
+            return Err(ServerError::Full(self.config.max_sessions));
+        }
+
+        let mut id = new_id();
+        while self.entries.contains_key(&id) || self.autosave_path(&id).is_some_and(|p| p.exists()) {
+            id = new_id();
+        }
+
+        let jobs = self.spawn(&id);
+        Ok((id, jobs))
+    }
+
+    // The session's thread, resumed from its autosave if it isn't running
+    fn jobs(&mut self, id: &str) -> Option<Sender<Job>> {
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.last_used = Instant::now();
+            return Some(entry.jobs.clone());
+        }
+
+        let data = match self.autosave_path(id).map(fs::read_to_string) {
+            Some(Ok(data)) => data,
+            _ => return None,
+        };
+
+        let jobs = self.spawn(id);
+        let _ = jobs.send(Job::Resume(data.trim().to_string()));
+
+        Some(jobs)
+    }
+
+    /// Starts a new session, returns its id and the opening events
+    pub fn create(&mut self) -> Result<(String, Vec<Event>), ServerError> {
+        let (id, jobs) = self.open()?;
+        let events = start(&jobs).unwrap_or_default();
+
+        Ok((id, events))
+    }
+
+    /// True if the session is running or can be resumed from its autosave
+    pub fn contains(&mut self, id: &str) -> bool {
+        self.jobs(id).is_some()
+    }
+
+    /// Handles one protocol request line for a session
+    pub fn handle(&mut self, id: &str, line: &str) -> Result<Vec<Event>, ServerError> {
+        match self.jobs(id) {
+            Some(jobs) => handle_line(&jobs, line),
+            None => Err(ServerError::NoSuchSession(id.to_string())),
+        }
+    }
+
+    /// Ends a session and deletes its autosave
+    pub fn close(&mut self, id: &str) -> bool {
+        let (reply, closed) = mpsc::channel();
+        self.close_with(id, reply);
+        closed.recv().unwrap_or(false)
+    }
+
+    // A running session's thread deletes its own autosave, so it can't write
+    // it again afterwards. The reply comes from that thread, the caller waits
+    // for it instead of the sessions thread.
+    fn close_with(&mut self, id: &str, reply: Sender<bool>) {
+        match self.entries.remove(id) {
+            Some(entry) => {
+                let _ = entry.jobs.send(Job::Close(reply));
+            }
+            None => {
+                let deleted = self.autosave_path(id).is_some_and(|path| fs::remove_file(path).is_ok());
+                let _ = reply.send(deleted);
+            }
+        }
+    }
+
+    /// Drops sessions that have been idle too long (their autosave stays),
+    /// returns the ids that were evicted
+    pub fn evict_idle(&mut self, now: Instant) -> Vec<String> {
+        let timeout = self.config.idle_timeout;
+        let idle: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| now.saturating_duration_since(entry.last_used) >= timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in &idle {
+            self.entries.remove(id);
+        }
+
+        idle
+    }
+
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let now = Instant::now();
+        let mut list: Vec<SessionInfo> = self
+            .entries
+            .iter()
+            .map(|(id, entry)| SessionInfo {
+                id: id.clone(),
+                idle_secs: now.duration_since(entry.last_used).as_secs(),
+                done: entry.state.done.load(Ordering::Relaxed),
+            })
+            .collect();
+
+        list.sort_by(|a, b| a.id.cmp(&b.id));
+        list
+    }
+
+    // Ids are checked before they become file names
+    fn autosave_path(&self, id: &str) -> Option<PathBuf> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+
+        self.config
+            .autosave_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.sav", id)))
+    }
+}
+
+// Autosaves use the same format as save files from encrusted-cli
+fn autosave(path: Option<&PathBuf>, session: &Session) {
+    if let (Some(path), Some(data)) = (path, session.save_data()) {
+        let _ = fs::write(path, data);
+    }
+}
+
+fn new_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+// These wait for the session's thread, not for the other sessions
+fn start(jobs: &Sender<Job>) -> Result<Vec<Event>, ServerError> {
+    let (reply, events) = mpsc::channel();
+    jobs.send(Job::Start(reply)).map_err(|_| ServerError::Stopped)?;
+    events.recv().map_err(|_| ServerError::Stopped)
+}
+
+fn handle_line(jobs: &Sender<Job>, line: &str) -> Result<Vec<Event>, ServerError> {
+    let (reply, events) = mpsc::channel();
+    jobs.send(Job::Line(line.to_string(), reply)).map_err(|_| ServerError::Stopped)?;
+    events.recv().map_err(|_| ServerError::Stopped)
+}
+
+enum Command {
+    Open(Sender<Result<(String, Sender<Job>), ServerError>>),
+    Jobs(String, Sender<Option<Sender<Job>>>),
+    Close(String, Sender<bool>),
+}
+
+/// Cloneable, `Send` handle to the thread that keeps track of the sessions.
+/// Requests go straight to the session's own thread.
+#[derive(Clone)]
+pub struct SessionsHandle {
+    tx: Sender<Command>,
+}
+
+impl SessionsHandle {
+    /// Starts the thread that owns the list of sessions
+    pub fn spawn(config: ServerConfig) -> SessionsHandle {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut sessions = Sessions::new(config);
+            let mut last_evict = Instant::now();
+
+            loop {
+                match rx.recv_timeout(EVICT_INTERVAL) {
+                    Ok(Command::Open(reply)) => {
+                        let _ = reply.send(sessions.open());
+                    }
+                    Ok(Command::Jobs(id, reply)) => {
+                        let _ = reply.send(sessions.jobs(&id));
+                    }
+                    Ok(Command::Close(id, reply)) => sessions.close_with(&id, reply),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+
+                if last_evict.elapsed() >= EVICT_INTERVAL {
+                    sessions.evict_idle(Instant::now());
+                    last_evict = Instant::now();
+                }
+            }
+        });
+
+        SessionsHandle { tx }
+    }
+
+    fn call<T>(&self, make: impl FnOnce(Sender<T>) -> Command) -> Result<T, ServerError> {
+        let (reply, response) = mpsc::channel();
+        self.tx.send(make(reply)).map_err(|_| ServerError::Stopped)?;
+        response.recv().map_err(|_| ServerError::Stopped)
+    }
+
+    pub fn create(&self) -> Result<(String, Vec<Event>), ServerError> {
+        let (id, jobs) = self.call(Command::Open)??;
+        Ok((id, start(&jobs)?))
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.call(|reply| Command::Jobs(id.to_string(), reply))
+            .is_ok_and(|jobs| jobs.is_some())
+    }
+
+    pub fn handle(&self, id: &str, line: &str) -> Result<Vec<Event>, ServerError> {
+        match self.call(|reply| Command::Jobs(id.to_string(), reply))? {
+            Some(jobs) => handle_line(&jobs, line),
+            None => Err(ServerError::NoSuchSession(id.to_string())),
+        }
+    }
+
+    pub fn close(&self, id: &str) -> bool {
+        self.call(|reply| Command::Close(id.to_string(), reply))
+            .unwrap_or(false)
+    }
+}
+
+/// Serves requests until the http server is shut down. Session ids are the
+/// only thing that lets a client play or end a session, so no route lists
+/// them:
+///
+/// - `POST /sessions` starts a session: `{"id", "events"}`
+/// - `POST /sessions/{id}` takes one protocol request, returns its events
+/// - `DELETE /sessions/{id}` ends a session
+/// - `GET /sessions/{id}/ws` plays a session over a WebSocket
+pub fn serve(http: tiny_http::Server, config: ServerConfig) {
+    let origin = config.allowed_origin.clone();
+    let sessions = SessionsHandle::spawn(config);
+
+    for request in http.incoming_requests() {
+        let sessions = sessions.clone();
+        let origin = origin.clone();
+        thread::spawn(move || route(request, sessions, origin.as_deref()));
+    }
+}
+
+fn json_response(status: u16, body: String) -> Response<io::Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+fn error_response(status: u16, message: &str) -> Response<io::Cursor<Vec<u8>>> {
+    json_response(status, serde_json::to_string(&Event::error(message)).unwrap())
+}
+
+fn header<'a>(request: &'a HttpRequest, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+// Checks the headers of a WebSocket handshake, returns the accept key
+fn websocket_accept(request: &HttpRequest) -> Option<String> {
+    let upgrade = header(request, "Upgrade")?;
+    let connection = header(request, "Connection")?;
+    let key = header(request, "Sec-WebSocket-Key")?;
+
+    let upgrades_connection = connection
+        .split(',')
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+
+    if upgrade.trim().eq_ignore_ascii_case("websocket") && upgrades_connection {
+        Some(derive_accept_key(key.as_bytes()))
+    } else {
+        None
+    }
+}
+
+// The error response if the body is too long or isn't UTF-8
+fn read_body(request: &mut HttpRequest) -> Result<String, Response<io::Cursor<Vec<u8>>>> {
+    if request.body_length().is_some_and(|length| length > MAX_BODY) {
+        return Err(error_response(413, "Request body is too long"));
+    }
+
+    let mut body = Vec::new();
+    if request.as_reader().take(MAX_BODY as u64 + 1).read_to_end(&mut body).is_err() {
+        return Err(error_response(400, "Couldn't read the request body"));
+    }
+
+    if body.len() > MAX_BODY {
+        return Err(error_response(413, "Request body is too long"));
+    }
+
+    String::from_utf8(body).map_err(|_| error_response(400, "Request body isn't UTF-8"))
+}
+
+fn route(mut request: HttpRequest, sessions: SessionsHandle, origin: Option<&str>) {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let method = request.method().clone();
+
+    let response = match (&method, parts.as_slice()) {
+        (Method::Get, [""]) => json_response(200, serde_json::to_string(&Event::hello()).unwrap()),
+        (Method::Post, ["sessions"]) => match sessions.create() {
+            Ok((id, events)) => {
+                json_response(201, json!({ "id": id, "events": events }).to_string())
+            }
+            Err(err) => error_response(503, &err.to_string()),
+        },
+        (Method::Post, ["sessions", id]) => {
+            match read_body(&mut request) {
+                Ok(body) => match sessions.handle(id, &body) {
+                    Ok(events) => json_response(200, serde_json::to_string(&events).unwrap()),
+                    Err(err @ ServerError::NoSuchSession(_)) => error_response(404, &err.to_string()),
+                    Err(err) => error_response(503, &err.to_string()),
+                },
+                Err(response) => response,
+            }
+        }
+        (Method::Delete, ["sessions", id]) => {
+            if sessions.close(id) {
+                json_response(200, json!({ "closed": id }).to_string())
+            } else {
+                error_response(404, &ServerError::NoSuchSession(id.to_string()).to_string())
+            }
+        }
+        (Method::Get, ["sessions", id, "ws"]) => {
+            let id = id.to_string();
+            match websocket_accept(&request) {
+                None => error_response(400, "Expected a WebSocket upgrade"),
+                Some(_) if !sessions.contains(&id) => {
+                    error_response(404, &ServerError::NoSuchSession(id).to_string())
+                }
+                Some(accept) => {
+                    let response = Response::empty(101)
+                        .with_header(Header::from_bytes("Sec-WebSocket-Accept", accept).unwrap());
+                    let stream = request.upgrade("websocket", response);
+                    play_websocket(WebSocket::from_raw_socket(stream, Role::Server, None), &id, &sessions);
+                    return;
+                }
+            }
+        }
+        _ => error_response(404, "Not found"),
+    };
+
+    let response = match origin {
+        Some(origin) => response.with_header(Header::from_bytes("Access-Control-Allow-Origin", origin).unwrap()),
+        None => response,
+    };
+
+    let _ = request.respond(response);
+}
+
+// Each text message is a protocol request, its events are sent back one
+// message each, ending with the ready event
+fn play_websocket<S: io::Read + io::Write>(mut socket: WebSocket<S>, id: &str, sessions: &SessionsHandle) {
+    let hello = serde_json::to_string(&Event::hello()).unwrap();
+    if socket.send(Message::text(hello)).is_err() {
+        return;
+    }
+
+    loop {
+        let line = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) | Err(_) => return,
+            Ok(_) => continue,
+        };
+
+        let events = match sessions.handle(id, &line) {
+            Ok(events) => events,
+            Err(err) => {
+                let event = serde_json::to_string(&Event::error(&err.to_string())).unwrap();
+                let _ = socket.send(Message::text(event));
+                let _ = socket.close(None);
+                return;
+            }
+        };
+
+        for event in events {
+            let text = serde_json::to_string(&event).unwrap();
+            if socket.write(Message::text(text)).is_err() {
+                return;
+            }
+        }
+
+        if socket.flush().is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Ring buffer of the last instructions run, with their operand values and
+//! store results, for diagnosing interpreter bugs reported from the field.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
+use ts_rs::TS;
+
+use crate::instruction::Instruction;
+
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub instr: Rc<Instruction>,
+    /// Operand values, after variables were read
+    pub args: Vec<u16>,
+    /// Value stored by the instruction, if it stores right away
+    pub result: Option<u16>,
+}
+
+impl TraceEntry {
+    /// Disassembly followed by the operand values and the stored value
+    pub fn to_text(&self) -> String {
+        let mut text = self.instr.to_string();
+
+        if !self.args.is_empty() {
+            let args: Vec<String> = self.args.iter().map(|arg| format!("{:04x}", arg)).collect();
+            write!(text, "  ; [{}]", args.join(" ")).unwrap();
+        }
+
+        if let Some(result) = self.result {
+            write!(text, " = {:04x}", result).unwrap();
+        }
+
+        text
+    }
+
+    pub fn to_record(&self) -> TraceRecord {
+        TraceRecord {
+            addr: self.instr.addr,
+            opcode: self.instr.name.clone(),
+            text: self.instr.to_string(),
+            args: self.args.clone(),
+            result: self.result,
+        }
+    }
+}
+
+/// A trace entry as json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+pub struct TraceRecord {
+    #[cfg_attr(not(target_arch = "wasm32"), ts(type = "number"))]
+    pub addr: usize,
+    pub opcode: String,
+    pub text: String,
+    pub args: Vec<u16>,
+    pub result: Option<u16>,
+}
+
+/// Keeps the last `capacity` instructions, a capacity of 0 turns it off
+#[derive(Debug, Clone, Default)]
+pub struct InstructionTrace {
+    capacity: usize,
+    entries: VecDeque<TraceEntry>,
+}
+
+impl InstructionTrace {
+    pub fn new(capacity: usize) -> InstructionTrace {
+        InstructionTrace {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Shrinking drops the oldest entries
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn push(&mut self, instr: Rc<Instruction>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(TraceEntry {
+            instr,
+            args: Vec::new(),
+            result: None,
+        });
+    }
+
+    // The entry being run, if it is the instruction at `addr`
+    fn current(&mut self, addr: usize) -> Option<&mut TraceEntry> {
+        self.entries
+            .back_mut()
+            .filter(|entry| entry.instr.addr == addr)
+    }
+
+    pub fn record_args(&mut self, addr: usize, args: &[u16]) {
+        if let Some(entry) = self.current(addr) {
+            entry.args = args.to_vec();
+        }
+    }
+
+    pub fn record_result(&mut self, addr: usize, value: u16) {
+        if let Some(entry) = self.current(addr) {
+            entry.result = Some(value);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    /// The last `count` entries, oldest first
+    pub fn last(&self, count: usize) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter().skip(self.entries.len().saturating_sub(count))
+    }
+
+    /// One line per instruction, oldest first
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for entry in &self.entries {
+            text.push_str(&entry.to_text());
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /// Json array of `TraceRecord`s, oldest first
+    pub fn to_json(&self) -> String {
+        let records: Vec<TraceRecord> = self.entries.iter().map(TraceEntry::to_record).collect();
+        serde_json::to_string(&records).unwrap()
+    }
+}
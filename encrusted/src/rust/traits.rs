@@ -1,3 +1,5 @@
+use crate::events::HostEvent;
+
 pub trait UI {
     fn new() -> Box<Self>
     where
@@ -18,4 +20,9 @@ pub trait UI {
     // only used by web ui
     fn flush(&mut self);
     fn message(&self, mtype: &str, msg: &str);
+
+    fn event(&self, event: HostEvent) {
+        let (mtype, msg) = event.to_message();
+        self.message(mtype, &msg);
+    }
 }
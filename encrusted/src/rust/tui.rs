@@ -0,0 +1,648 @@
+use std::boxed::Box;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::events::HostEvent;
+use crate::game::Game;
+use crate::options::Options;
+use crate::traits::UI;
+use crate::zmachine::Zmachine;
+
+const SCROLL_STEP: usize = 10;
+
+// Everything the story printed, as unwrapped lines (the last line is still
+// open for more text). Wrapping happens at draw time, for the current width.
+#[derive(Debug, Default)]
+struct StoryLog {
+    lines: Vec<String>,
+    save: Option<String>,
+}
+
+impl StoryLog {
+    fn push_text(&mut self, text: &str) {
+        if self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.lines.push(String::new());
+            }
+
+            if let Some(line) = self.lines.last_mut() {
+                line.push_str(part);
+            }
+        }
+    }
+
+    // debug output and ascii art get lines of their own
+    fn push_block(&mut self, text: &str) {
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+
+        self.lines.pop();
+        self.lines.extend(text.lines().map(String::from));
+        self.lines.push(String::new());
+    }
+}
+
+/// UI for the full-screen frontend, collects output for the story pane
+pub struct TuiUI {
+    log: Rc<RefCell<StoryLog>>,
+}
+
+impl UI for TuiUI {
+    fn new() -> Box<TuiUI> {
+        Box::new(TuiUI {
+            log: Rc::new(RefCell::new(StoryLog::default())),
+        })
+    }
+
+    fn print(&mut self, text: &str) {
+        self.log.borrow_mut().push_text(text);
+    }
+
+    fn debug(&mut self, text: &str) {
+        self.log.borrow_mut().push_block(text);
+    }
+
+    fn print_object(&mut self, object: &str) {
+        self.log.borrow_mut().push_text(object);
+    }
+
+    fn print_ascii_art(&mut self, art: &str) {
+        self.log.borrow_mut().push_block(art);
+    }
+
+    // the status bar is drawn from Zmachine::get_status on every frame
+    fn set_status_bar(&self, _left: &str, _right: &str) {}
+
+    // the save file name is asked for once the story is waiting for input
+    fn event(&self, event: HostEvent) {
+        if let HostEvent::Save(_, data) = event {
+            self.log.borrow_mut().save = Some(data);
+        }
+    }
+
+    fn message(&self, _mtype: &str, _msg: &str) {}
+
+    fn clear(&self) {}
+    fn reset(&self) {}
+    fn flush(&mut self) {}
+
+    // input comes from the input line, see TuiApp::handle_key
+    fn get_user_input(&self) -> String {
+        String::new()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Prompt {
+    Command,
+    SaveFile(String),
+    RestoreFile,
+}
+
+struct HintBrowser {
+    location: String,
+    questions: Vec<(usize, String, String)>,
+    list: ListState,
+    // how many answers have been revealed for each question
+    revealed: HashMap<usize, usize>,
+}
+
+impl HintBrowser {
+    fn selected(&self) -> Option<usize> {
+        self.list
+            .selected()
+            .and_then(|i| self.questions.get(i))
+            .map(|&(idx, _, _)| idx)
+    }
+}
+
+/// Full-screen terminal frontend: status bar, scrolling story pane, an
+/// object tree pane and a hint browser with progressive reveal.
+pub struct TuiApp {
+    zvm: Zmachine,
+    log: Rc<RefCell<StoryLog>>,
+    input: String,
+    history: Vec<String>,
+    history_pos: Option<usize>,
+    scroll: usize,
+    tree_lines: Vec<String>,
+    show_tree: bool,
+    full_tree: bool,
+    hints: Option<HintBrowser>,
+    prompt: Prompt,
+    done: bool,
+}
+
+impl TuiApp {
+    pub fn new(data: Vec<u8>, opts: Options) -> TuiApp {
+        let log = Rc::new(RefCell::new(StoryLog::default()));
+        let ui = Box::new(TuiUI { log: Rc::clone(&log) });
+
+        TuiApp {
+            zvm: Game::load_story(data, ui, opts),
+            log,
+            input: String::new(),
+            history: Vec::new(),
+            history_pos: None,
+            scroll: 0,
+            tree_lines: Vec::new(),
+            show_tree: true,
+            full_tree: false,
+            hints: None,
+            prompt: Prompt::Command,
+            done: false,
+        }
+    }
+
+    /// Starts the story from a file written by the in-game save command
+    pub fn restore_from_file(&mut self, path: &str) {
+        match fs::read_to_string(path) {
+            Ok(data) => self.zvm.restore(data.trim()),
+            Err(err) => {
+                self.print(&format!("[Couldn't read {}: {}]\n", path, err));
+                self.zvm.restore("");
+            }
+        }
+    }
+
+    /// Runs the story until it needs input, then updates the panes
+    pub fn resume(&mut self) {
+        let done = self.zvm.step();
+        self.zvm.ui.flush();
+        self.update_tree();
+
+        if done {
+            self.done = true;
+            self.print("\n\n[The story has ended. Press any key to exit.]");
+            return;
+        }
+
+        if let Some(data) = self.log.borrow_mut().save.take() {
+            self.prompt = Prompt::SaveFile(data);
+        } else if self.zvm.is_awaiting_restore() {
+            self.prompt = Prompt::RestoreFile;
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn print(&mut self, text: &str) {
+        self.log.borrow_mut().push_text(text);
+    }
+
+    fn update_tree(&mut self) {
+        let tree = self.zvm.get_object_tree();
+        let (room, _) = self.zvm.get_current_room();
+
+        let text = match tree.find(room) {
+            Some(object) if !self.full_tree => object.to_string(),
+            _ => tree.to_string(),
+        };
+
+        self.tree_lines = text.lines().map(String::from).collect();
+    }
+
+    // Same questions as the web hint modal: the ones tagged with the current
+    // room, or the general hints when asked for (or nothing matches the room)
+    fn open_hints(&mut self, general: bool) {
+        let (_, room) = self.zvm.get_current_room();
+        let hint_system = self.zvm.get_hint_system();
+
+        let mut questions: Vec<(usize, String, String)> = if general {
+            Vec::new()
+        } else {
+            hint_system
+                .get_questions_for_location(&room)
+                .into_iter()
+                .map(|(idx, q)| (idx, q.question, q.section))
+                .collect()
+        };
+
+        let location = if questions.is_empty() {
+            questions = hint_system.get_hints_for_state(Some("GENERAL"));
+            "General".to_string()
+        } else {
+            room
+        };
+
+        let mut list = ListState::default();
+        if !questions.is_empty() {
+            list.select(Some(0));
+        }
+
+        self.hints = Some(HintBrowser {
+            location,
+            questions,
+            list,
+            revealed: HashMap::new(),
+        });
+    }
+
+    fn submit(&mut self) {
+        let input = std::mem::take(&mut self.input);
+        let prompt = std::mem::replace(&mut self.prompt, Prompt::Command);
+        self.history_pos = None;
+        self.scroll = 0;
+
+        match prompt {
+            Prompt::Command => {
+                self.print(&format!("{}\n", input));
+
+                if !input.trim().is_empty() {
+                    self.history.push(input.clone());
+                }
+
+                match input.trim() {
+                    "$undo" => {
+                        let message = if self.zvm.undo() {
+                            let (_, room) = self.zvm.get_current_room();
+                            format!("\n[Undone, back in: {}]\n\n>", room)
+                        } else {
+                            "\n>".to_string()
+                        };
+                        self.print(&message);
+                    }
+                    "$redo" => {
+                        let message = if self.zvm.redo() {
+                            let (_, room) = self.zvm.get_current_room();
+                            format!("\n[Redone, now in: {}]\n\n>", room)
+                        } else {
+                            "\n>".to_string()
+                        };
+                        self.print(&message);
+                    }
+                    _ => self.zvm.handle_input(input),
+                }
+
+                self.resume();
+            }
+            Prompt::SaveFile(data) => {
+                let path = input.trim();
+                let result = if path.is_empty() {
+                    "[No file name given, nothing saved.]".to_string()
+                } else {
+                    match fs::write(path, data.as_bytes()) {
+                        Ok(()) => format!("[Saved to {}]", path),
+                        Err(err) => format!("[Couldn't save to {}: {}]", path, err),
+                    }
+                };
+
+                self.print(&format!("\n{}\n\n>", result));
+            }
+            Prompt::RestoreFile => {
+                let path = input.trim();
+
+                if path.is_empty() {
+                    self.zvm.restore("");
+                } else {
+                    let path = path.to_string();
+                    self.restore_from_file(&path);
+                }
+
+                self.resume();
+            }
+        }
+    }
+
+    fn browse_history(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+
+        let last = self.history.len() - 1;
+        self.history_pos = match (self.history_pos, older) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(pos), true) => Some(pos.saturating_sub(1)),
+            (Some(pos), false) if pos < last => Some(pos + 1),
+            (Some(_), false) => None,
+        };
+
+        self.input = match self.history_pos {
+            Some(pos) => self.history[pos].clone(),
+            None => String::new(),
+        };
+    }
+
+    fn handle_hint_key(&mut self, key: KeyEvent) {
+        if matches!(key.code, KeyCode::Esc | KeyCode::F(1)) {
+            self.hints = None;
+            return;
+        }
+
+        if key.code == KeyCode::Char('g') {
+            let general = self.hints.as_ref().is_some_and(|b| b.location != "General");
+            self.open_hints(general);
+            return;
+        }
+
+        let hint_system = self.zvm.get_hint_system();
+        let browser = match self.hints.as_mut() {
+            Some(browser) => browser,
+            None => return,
+        };
+
+        match key.code {
+            KeyCode::Up => browser.list.select_previous(),
+            KeyCode::Down => browser.list.select_next(),
+            KeyCode::Enter | KeyCode::Right => {
+                if let Some(idx) = browser.selected() {
+                    let count = hint_system.get_answer_count(idx);
+                    let revealed = browser.revealed.entry(idx).or_insert(0);
+                    *revealed = (*revealed + 1).min(count);
+                }
+            }
+            KeyCode::Left => {
+                if let Some(idx) = browser.selected() {
+                    let revealed = browser.revealed.entry(idx).or_insert(0);
+                    *revealed = revealed.saturating_sub(1);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Handles one key press, returns false when the frontend should exit
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if self.done {
+            return false;
+        }
+
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+
+        if self.hints.is_some() {
+            self.handle_hint_key(key);
+            return true;
+        }
+
+        match key.code {
+            KeyCode::F(10) => return false,
+            KeyCode::F(1) => self.open_hints(false),
+            KeyCode::F(2) => self.show_tree = !self.show_tree,
+            KeyCode::F(3) => {
+                self.full_tree = !self.full_tree;
+                self.update_tree();
+            }
+            KeyCode::PageUp => self.scroll += SCROLL_STEP,
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(SCROLL_STEP),
+            KeyCode::Up => self.browse_history(true),
+            KeyCode::Down => self.browse_history(false),
+            KeyCode::Enter => self.submit(),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            _ => (),
+        }
+
+        true
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let [status, body, input, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        self.draw_status(frame, status);
+
+        if self.show_tree {
+            let [story, tree] =
+                Layout::horizontal([Constraint::Percentage(65), Constraint::Percentage(35)])
+                    .areas(body);
+
+            self.draw_story(frame, story);
+            self.draw_tree(frame, tree);
+        } else {
+            self.draw_story(frame, body);
+        }
+
+        let label = match self.prompt {
+            Prompt::Command => "> ",
+            Prompt::SaveFile(_) => "Save to file: ",
+            Prompt::RestoreFile => "Restore from file: ",
+        };
+        let line = format!("{}{}", label, self.input);
+        let cursor_x = input.x + line.chars().count().min(input.width as usize) as u16;
+
+        frame.render_widget(Paragraph::new(line), input);
+        frame.set_cursor_position((cursor_x, input.y));
+
+        let keys = "F1 hints  F2 object tree  F3 room/all objects  PgUp/PgDn scroll  F10 quit";
+        frame.render_widget(
+            Paragraph::new(keys).style(Style::default().add_modifier(Modifier::DIM)),
+            help,
+        );
+
+        if self.hints.is_some() {
+            self.draw_hints(frame);
+        }
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let (left, right) = self.zvm.get_status();
+        let width = area.width as usize;
+        let left = format!(" {}", left);
+        let right = format!("{} ", right);
+        let padding = width.saturating_sub(left.chars().count() + right.chars().count());
+        let bar = format!("{}{}{}", left, " ".repeat(padding), right);
+
+        frame.render_widget(
+            Paragraph::new(bar).style(Style::default().add_modifier(Modifier::REVERSED)),
+            area,
+        );
+    }
+
+    fn draw_story(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::default().borders(Borders::ALL);
+        let inner = block.inner(area);
+        let width = inner.width.max(1) as usize;
+        let height = inner.height as usize;
+
+        let wrapped: Vec<String> = self
+            .log
+            .borrow()
+            .lines
+            .iter()
+            .flat_map(|line| wrap(line, width))
+            .collect();
+
+        // scrolling is counted in lines up from the bottom
+        let max_scroll = wrapped.len().saturating_sub(height);
+        self.scroll = self.scroll.min(max_scroll);
+
+        let end = wrapped.len() - self.scroll;
+        let start = end.saturating_sub(height);
+        let lines: Vec<Line> = wrapped[start..end].iter().map(|l| Line::from(l.as_str())).collect();
+
+        let title = if self.scroll > 0 {
+            format!(" Story (scrolled back {} lines) ", self.scroll)
+        } else {
+            " Story ".to_string()
+        };
+
+        frame.render_widget(Paragraph::new(lines).block(block.title(title)), area);
+    }
+
+    fn draw_tree(&self, frame: &mut Frame, area: Rect) {
+        let title = if self.full_tree { " All objects " } else { " Map " };
+        let lines: Vec<Line> = self.tree_lines.iter().map(|l| Line::from(l.as_str())).collect();
+
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+            area,
+        );
+    }
+
+    fn draw_hints(&mut self, frame: &mut Frame) {
+        let area = centered(frame.area(), 80, 80);
+        let hint_system = self.zvm.get_hint_system();
+        let browser = match self.hints.as_mut() {
+            Some(browser) => browser,
+            None => return,
+        };
+
+        frame.render_widget(Clear, area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" Hints: {} ", browser.location))
+            .title_bottom(" Up/Down choose  Enter reveal  Left hide  g general/room  Esc close ");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        if browser.questions.is_empty() {
+            frame.render_widget(Paragraph::new("No hints available."), inner);
+            return;
+        }
+
+        let [questions, answers] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(inner);
+
+        let items: Vec<ListItem> = browser
+            .questions
+            .iter()
+            .map(|(_, question, section)| ListItem::new(format!("[{}] {}", section, question)))
+            .collect();
+
+        let list = List::new(items)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, questions, &mut browser.list);
+
+        let idx = match browser.selected() {
+            Some(idx) => idx,
+            None => return,
+        };
+
+        let count = hint_system.get_answer_count(idx);
+        let revealed = browser.revealed.get(&idx).cloned().unwrap_or(0);
+        let mut lines: Vec<Line> = (0..revealed)
+            .filter_map(|level| hint_system.get_answer_at_level(idx, level))
+            .map(Line::from)
+            .collect();
+
+        if revealed < count {
+            lines.push(Line::from(format!(
+                "(Enter to reveal hint {} of {})",
+                revealed + 1,
+                count
+            )));
+        }
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: true })
+                .block(Block::default().borders(Borders::TOP).title(" Answers ")),
+            answers,
+        );
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Takes over the terminal until the player quits
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+
+        result
+    }
+}
+
+fn centered(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+    // in u32, a u16 overflows on terminals over 655 columns
+    let width = (u32::from(area.width) * u32::from(percent_x) / 100) as u16;
+    let height = (u32::from(area.height) * u32::from(percent_y) / 100) as u16;
+
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+// greedy word wrap, long words get split
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+
+    for word in line.split(' ') {
+        let current = lines.last_mut().unwrap();
+        let used = current.chars().count();
+        let length = word.chars().count();
+
+        if used > 0 && used + 1 + length > width {
+            lines.push(String::new());
+        } else if used > 0 {
+            current.push(' ');
+        }
+
+        let mut chars = word.chars().peekable();
+        while chars.peek().is_some() {
+            let current = lines.last_mut().unwrap();
+            let room = width - current.chars().count();
+
+            if room == 0 {
+                lines.push(String::new());
+                continue;
+            }
+
+            current.extend(chars.by_ref().take(room));
+        }
+    }
+
+    lines
+}
@@ -0,0 +1,349 @@
+use std::boxed::Box;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::rc::Rc;
+
+use crossterm::{cursor, queue, style, terminal};
+
+use crate::events::HostEvent;
+use crate::traits::UI;
+
+const DEFAULT_WIDTH: usize = 80;
+
+// Tracks the output column so text can be wrapped across separate print calls.
+// Everything written to the terminal is mirrored into the transcript, if any,
+// except for control sequences; the status line goes in as text instead.
+struct Screen {
+    out: Box<dyn Write>,
+    transcript: Option<File>,
+    width: usize,
+    column: usize,
+    pending_spaces: usize,
+    // last status line written to the transcript
+    status: String,
+}
+
+impl Screen {
+    fn emit(&mut self, text: &str) {
+        let _ = self.out.write_all(text.as_bytes());
+
+        if let Some(ref mut file) = self.transcript {
+            let _ = file.write_all(text.as_bytes());
+        }
+    }
+
+    fn newline(&mut self) {
+        self.emit("\n");
+        self.column = 0;
+        self.pending_spaces = 0;
+    }
+
+    fn write_wrapped(&mut self, text: &str) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.newline();
+            }
+
+            for (j, word) in line.split(' ').enumerate() {
+                if j > 0 {
+                    self.pending_spaces += 1;
+                }
+
+                if word.is_empty() {
+                    continue;
+                }
+
+                let length = word.chars().count();
+
+                // spaces are dropped when a word gets wrapped onto a new line
+                if self.column > 0 && self.column + self.pending_spaces + length > self.width {
+                    self.newline();
+                } else if self.pending_spaces > 0 {
+                    let spaces = " ".repeat(self.pending_spaces);
+                    self.emit(&spaces);
+                    self.column += self.pending_spaces;
+                }
+
+                self.pending_spaces = 0;
+                self.emit(word);
+                self.column += length;
+            }
+        }
+    }
+
+    fn write_raw(&mut self, text: &str) {
+        if self.column > 0 {
+            self.newline();
+        }
+
+        self.emit(text);
+
+        if !text.ends_with('\n') {
+            self.emit("\n");
+        }
+
+        self.column = 0;
+        self.pending_spaces = 0;
+    }
+
+    // The status line goes in the transcript on its own line when it changes
+    fn record_status(&mut self, status: &str) {
+        if self.status == status {
+            return;
+        }
+
+        if let Some(ref mut file) = self.transcript {
+            let newline = if self.column > 0 { "\n" } else { "" };
+            let _ = writeln!(file, "{}[{}]", newline, status);
+        }
+
+        self.status = status.to_string();
+    }
+
+    fn flush(&mut self) {
+        // trailing spaces matter for prompts like "> "
+        if self.pending_spaces > 0 && self.column + self.pending_spaces <= self.width {
+            let spaces = " ".repeat(self.pending_spaces);
+            self.emit(&spaces);
+            self.column += self.pending_spaces;
+        }
+
+        self.pending_spaces = 0;
+        let _ = self.out.flush();
+
+        if let Some(ref mut file) = self.transcript {
+            let _ = file.flush();
+        }
+    }
+}
+
+// Commands come from the script first (echoed, since nobody typed them),
+// then from stdin
+struct Input {
+    script: VecDeque<String>,
+    echo_stdin: bool,
+    eof: Rc<Cell<bool>>,
+}
+
+/// Line-mode terminal frontend: word wraps output to the terminal width,
+/// draws the status line on the top row and reads commands from stdin.
+pub struct TerminalUI {
+    screen: RefCell<Screen>,
+    input: RefCell<Input>,
+    styled: bool,
+}
+
+impl TerminalUI {
+    pub fn with_output(out: Box<dyn Write>, width: usize, styled: bool) -> Box<TerminalUI> {
+        Box::new(TerminalUI {
+            screen: RefCell::new(Screen {
+                out,
+                transcript: None,
+                width,
+                column: 0,
+                pending_spaces: 0,
+                status: String::new(),
+            }),
+            input: RefCell::new(Input {
+                script: VecDeque::new(),
+                echo_stdin: !io::stdin().is_terminal(),
+                eof: Rc::new(Cell::new(false)),
+            }),
+            styled,
+        })
+    }
+
+    /// Width of the attached terminal, falling back to $COLUMNS and then 80
+    pub fn terminal_width() -> usize {
+        if io::stdout().is_terminal() {
+            if let Ok((cols, _)) = terminal::size() {
+                if cols > 0 {
+                    return cols as usize;
+                }
+            }
+        }
+
+        env::var("COLUMNS")
+            .ok()
+            .and_then(|cols| cols.parse().ok())
+            .filter(|cols| *cols > 0)
+            .unwrap_or(DEFAULT_WIDTH)
+    }
+
+    pub fn set_transcript(&mut self, file: File) {
+        self.screen.get_mut().transcript = Some(file);
+    }
+
+    pub fn set_script(&mut self, commands: Vec<String>) {
+        self.input.get_mut().script = commands.into();
+    }
+
+    /// Shared flag that is set once stdin runs out of input
+    pub fn eof_flag(&self) -> Rc<Cell<bool>> {
+        Rc::clone(&self.input.borrow().eof)
+    }
+
+    fn draw_status_bar(&self, left: &str, right: &str) {
+        let mut screen = self.screen.borrow_mut();
+        let cols = screen.width;
+        let left = format!(" {}", left);
+        let right = format!("{} ", right);
+        let padding = cols.saturating_sub(left.chars().count() + right.chars().count());
+        let bar: String = format!("{}{}{}", left, " ".repeat(padding), right)
+            .chars()
+            .take(cols)
+            .collect();
+
+        let _ = queue!(
+            screen.out,
+            cursor::SavePosition,
+            cursor::MoveTo(0, 0),
+            style::SetAttribute(style::Attribute::Reverse),
+            style::Print(bar),
+            style::SetAttribute(style::Attribute::Reset),
+            cursor::RestorePosition
+        );
+        let _ = screen.out.flush();
+    }
+}
+
+impl UI for TerminalUI {
+    fn new() -> Box<TerminalUI> {
+        let styled = io::stdout().is_terminal();
+        TerminalUI::with_output(Box::new(io::stdout()), TerminalUI::terminal_width(), styled)
+    }
+
+    fn clear(&self) {
+        if !self.styled {
+            return;
+        }
+
+        // keep the top row for the status bar by scrolling everything below it
+        let (_, rows) = terminal::size().unwrap_or((0, 24));
+        let mut screen = self.screen.borrow_mut();
+        let _ = queue!(
+            screen.out,
+            terminal::Clear(terminal::ClearType::All),
+            style::Print(format!("\x1B[2;{}r", rows)),
+            cursor::MoveTo(0, 1)
+        );
+        let _ = screen.out.flush();
+    }
+
+    fn print(&mut self, text: &str) {
+        self.screen.get_mut().write_wrapped(text);
+    }
+
+    fn debug(&mut self, text: &str) {
+        self.screen.get_mut().write_raw(text);
+    }
+
+    fn print_object(&mut self, object: &str) {
+        self.screen.get_mut().write_wrapped(object);
+    }
+
+    fn print_ascii_art(&mut self, art: &str) {
+        self.screen.get_mut().write_raw(art);
+    }
+
+    fn set_status_bar(&self, left: &str, right: &str) {
+        self.screen.borrow_mut().record_status(&format!("{} | {}", left, right));
+
+        if self.styled {
+            self.draw_status_bar(left, right);
+        }
+    }
+
+    fn reset(&self) {
+        if !self.styled {
+            return;
+        }
+
+        let mut screen = self.screen.borrow_mut();
+        let _ = queue!(
+            screen.out,
+            style::Print("\x1B[r"),
+            style::SetAttribute(style::Attribute::Reset)
+        );
+        let _ = screen.out.flush();
+    }
+
+    fn get_user_input(&self) -> String {
+        let mut screen = self.screen.borrow_mut();
+        let mut input = self.input.borrow_mut();
+        screen.flush();
+
+        let line = match input.script.pop_front() {
+            Some(line) => {
+                screen.emit(&line);
+                screen.newline();
+                line
+            }
+            None => {
+                let mut line = String::new();
+
+                match io::stdin().lock().read_line(&mut line) {
+                    Ok(0) | Err(_) => {
+                        input.eof.set(true);
+                        return String::new();
+                    }
+                    Ok(_) => (),
+                }
+
+                let line = line.trim_end_matches(['\n', '\r']).to_string();
+
+                // the terminal already echoed what was typed, the transcript didn't
+                if input.echo_stdin {
+                    screen.emit(&line);
+                    screen.newline();
+                } else {
+                    if let Some(ref mut file) = screen.transcript {
+                        let _ = writeln!(file, "{}", line);
+                    }
+
+                    screen.column = 0;
+                    screen.pending_spaces = 0;
+                }
+
+                line
+            }
+        };
+
+        screen.flush();
+        line
+    }
+
+    fn flush(&mut self) {
+        self.screen.get_mut().flush();
+    }
+
+    // Saves are written to a file named by the player. Restores need the
+    // engine, so those are left to the loop driving the game.
+    fn event(&self, event: HostEvent) {
+        let data = match event {
+            HostEvent::Save(_, data) => data,
+            _ => return,
+        };
+
+        self.screen.borrow_mut().write_wrapped("\nSave to file: ");
+        let path = self.get_user_input();
+        let path = path.trim();
+
+        let result = if path.is_empty() {
+            "[No file name given, nothing saved.]\n".to_string()
+        } else {
+            match File::create(path).and_then(|mut file| file.write_all(data.as_bytes())) {
+                Ok(()) => format!("[Saved to {}]\n", path),
+                Err(err) => format!("[Couldn't save to {}: {}]\n", path, err),
+            }
+        };
+
+        self.screen.borrow_mut().write_wrapped(&result);
+    }
+
+    // every message the engine sends arrives as an event
+    fn message(&self, _mtype: &str, _msg: &str) {}
+}
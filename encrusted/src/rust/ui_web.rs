@@ -1,14 +1,59 @@
 use std::boxed::Box;
-use std::ffi::CString;
-use std::fmt::Write;
-use std::os::raw::c_char;
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Write};
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
 
-use serde_json;
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
+use ts_rs::TS;
 
+use crate::events::HostEvent;
 use crate::traits::UI;
 
-extern "C" {
-    fn js_message(mtype: *mut c_char, message: *mut c_char);
+/// Receives the messages `WebUI` sends to its host (`print`, `header`,
+/// `save`, ...). Closures taking the message type and body are sinks too.
+pub trait MessageSink {
+    fn send(&self, mtype: &str, msg: &str);
+}
+
+impl<F: Fn(&str, &str)> MessageSink for F {
+    fn send(&self, mtype: &str, msg: &str) {
+        self(mtype, msg)
+    }
+}
+
+/// Keeps messages in memory until they are taken, clones share the messages
+#[derive(Debug, Clone, Default)]
+pub struct VecSink {
+    messages: Rc<RefCell<Vec<(String, String)>>>,
+}
+
+impl VecSink {
+    pub fn new() -> VecSink {
+        VecSink::default()
+    }
+
+    pub fn take(&self) -> Vec<(String, String)> {
+        self.messages.borrow_mut().drain(..).collect()
+    }
+}
+
+impl MessageSink for VecSink {
+    fn send(&self, mtype: &str, msg: &str) {
+        self.messages
+            .borrow_mut()
+            .push((mtype.to_string(), msg.to_string()));
+    }
+}
+
+/// Passes messages on to a channel, e.g. to another thread
+pub struct ChannelSink(pub Sender<(String, String)>);
+
+impl MessageSink for ChannelSink {
+    fn send(&self, mtype: &str, msg: &str) {
+        let _ = self.0.send((mtype.to_string(), msg.to_string()));
+    }
 }
 
 #[derive(Debug)]
@@ -20,16 +65,68 @@ enum Token {
     AsciiArt(String),
 }
 
-#[derive(Debug)]
-pub struct WebUI {
-    buffer: Vec<Token>,
+/// What a run of output is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+#[serde(rename_all = "snake_case")]
+pub enum RunKind {
+    Text,
+    Newline,
+    Object,
+    Debug,
+    AsciiArt,
 }
 
-impl UI for WebUI {
-    fn new() -> Box<WebUI> {
-        Box::new(WebUI { buffer: Vec::new() })
+/// How a run should look: objects are either the room title or an object
+/// mentioned in the text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+#[serde(rename_all = "snake_case")]
+pub enum RunStyle {
+    Plain,
+    Room,
+    Object,
+    Debug,
+    Preformatted,
+}
+
+/// One piece of flushed output, sent as json in `tokens` messages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+pub struct TextRun {
+    pub kind: RunKind,
+    pub text: String,
+    pub style: RunStyle,
+}
+
+impl TextRun {
+    pub fn new(kind: RunKind, text: &str, style: RunStyle) -> TextRun {
+        TextRun {
+            kind,
+            text: text.to_string(),
+            style,
+        }
     }
+}
+
+/// How `WebUI::flush` sends output: one `print` message with html, or one
+/// `tokens` message with a json array of `TextRun`s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Html,
+    Tokens,
+}
+
+// Output is buffered as tokens between flushes and grouped into runs, which
+// are sent as html or json depending on the output format
+#[derive(Debug, Default)]
+struct OutputBuffer {
+    buffer: Vec<Token>,
+}
 
+impl OutputBuffer {
     fn print(&mut self, text: &str) {
         if text.is_empty() {
             return;
@@ -80,12 +177,13 @@ impl UI for WebUI {
         self.buffer.push(Token::AsciiArt(String::from(art)));
     }
 
-    fn flush(&mut self) {
+    // Groups and empties the buffer, None if nothing was printed
+    fn take_runs(&mut self) -> Option<Vec<TextRun>> {
         if self.buffer.is_empty() {
-            return;
+            return None;
         }
 
-        let mut html = String::new();
+        let mut runs: Vec<TextRun> = Vec::new();
 
         for (index, item) in self.buffer.iter().enumerate() {
             let prev = if index == 0 {
@@ -96,76 +194,156 @@ impl UI for WebUI {
 
             let next = self.buffer.get(index + 1);
 
-            match *item {
-                Token::Newline => {
-                    html.push_str("<br>");
-                }
+            let run = match *item {
+                Token::Newline => TextRun::new(RunKind::Newline, "", RunStyle::Plain),
                 Token::Text(ref text) => {
-                    let escaped = text.replace("&", "&amp;")
-                        .replace("<", "&lt;")
-                        .replace(">", "&gt;");
-
-                    match prev {
-                        Some(&Token::Text(_)) => (),
-                        _ => html.push_str("<span>"),
+                    // consecutive text tokens are one run
+                    if let Some(&Token::Text(_)) = prev {
+                        if let Some(last) = runs.last_mut() {
+                            last.text.push_str(text);
+                            continue;
+                        }
                     }
 
-                    html.push_str(&escaped);
-
-                    match next {
-                        Some(&Token::Text(_)) => (),
-                        _ => html.push_str("</span>"),
-                    }
+                    TextRun::new(RunKind::Text, text, RunStyle::Plain)
                 }
                 Token::Object(ref obj) => {
-                    let escaped = obj.replace("&", "&amp;")
-                        .replace("<", "&lt;")
-                        .replace(">", "&gt;");
-
-                    let class = match (prev, next) {
-                        (None, Some(&Token::Newline)) => "room",
-                        (Some(&Token::Newline), Some(&Token::Newline)) => "room",
-                        _ => "object",
+                    // an object on a line of its own is the room title
+                    let style = match (prev, next) {
+                        (None, Some(&Token::Newline)) => RunStyle::Room,
+                        (Some(&Token::Newline), Some(&Token::Newline)) => RunStyle::Room,
+                        _ => RunStyle::Object,
                     };
 
-                    write!(html, r#"<span class="{}">{}</span>"#, class, escaped).unwrap();
-                }
-                Token::Debug(ref text) => {
-                    let escaped = text.replace("&", "&amp;")
-                        .replace("<", "&lt;")
-                        .replace(">", "&gt;");
-
-                    write!(html, r#"<span class="debug">{}</span>"#, escaped).unwrap();
+                    TextRun::new(RunKind::Object, obj, style)
                 }
+                Token::Debug(ref text) => TextRun::new(RunKind::Debug, text, RunStyle::Debug),
                 Token::AsciiArt(ref art) => {
-                    let escaped = art.replace("&", "&amp;")
-                        .replace("<", "&lt;")
-                        .replace(">", "&gt;");
-                    write!(html, r#"<pre class="ascii-art">{}</pre>"#, escaped).unwrap();
+                    TextRun::new(RunKind::AsciiArt, art, RunStyle::Preformatted)
                 }
-            }
+            };
+
+            runs.push(run);
         }
 
-        self.message("print", &html);
         self.buffer.clear();
+        Some(runs)
     }
+}
 
-    fn set_status_bar(&self, left: &str, right: &str) {
-        let msg = serde_json::to_string(&(left, right)).unwrap();
-        self.message("header", &msg)
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Renders runs as the html the web app displays
+pub fn render_html(runs: &[TextRun]) -> String {
+    let mut html = String::new();
+
+    for run in runs {
+        let escaped = escape_html(&run.text);
+
+        match run.kind {
+            RunKind::Newline => html.push_str("<br>"),
+            RunKind::Text => write!(html, "<span>{}</span>", escaped).unwrap(),
+            RunKind::Object => {
+                let class = if run.style == RunStyle::Room { "room" } else { "object" };
+                write!(html, r#"<span class="{}">{}</span>"#, class, escaped).unwrap();
+            }
+            RunKind::Debug => write!(html, r#"<span class="debug">{}</span>"#, escaped).unwrap(),
+            RunKind::AsciiArt => {
+                write!(html, r#"<pre class="ascii-art">{}</pre>"#, escaped).unwrap();
+            }
+        }
     }
 
-    fn message(&self, mtype: &str, msg: &str) {
-        let type_ptr = CString::new(mtype).unwrap().into_raw();
-        let msg_ptr = CString::new(msg).unwrap().into_raw();
+    html
+}
+
+/// UI for hosts that take messages (web, protocol, tests). There is no
+/// `UI::new()` for it: create it with `WebUI::with_sink`.
+pub struct WebUI {
+    buffer: OutputBuffer,
+    format: Rc<Cell<OutputFormat>>,
+    sink: Box<dyn MessageSink>,
+}
+
+impl WebUI {
+    pub fn with_sink(sink: Box<dyn MessageSink>) -> Box<WebUI> {
+        Box::new(WebUI {
+            buffer: OutputBuffer::default(),
+            format: Rc::new(Cell::new(OutputFormat::default())),
+            sink,
+        })
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        self.format.get()
+    }
+
+    pub fn set_output_format(&self, format: OutputFormat) {
+        self.format.set(format);
+    }
+
+    /// Shared output format, so hosts can still switch it after the UI
+    /// has been handed to a `Zmachine`
+    pub fn format_handle(&self) -> Rc<Cell<OutputFormat>> {
+        Rc::clone(&self.format)
+    }
+}
+
+impl fmt::Debug for WebUI {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WebUI")
+            .field("buffer", &self.buffer)
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
 
-        unsafe {
-            js_message(type_ptr, msg_ptr);
-            let _ = CString::from_raw(type_ptr);
-            let _ = CString::from_raw(msg_ptr);
+impl UI for WebUI {
+    // Without a sink every message would be lost, hosts must pick one
+    fn new() -> Box<WebUI> {
+        panic!("WebUI has nowhere to send messages, create it with WebUI::with_sink");
+    }
+
+    fn print(&mut self, text: &str) {
+        self.buffer.print(text);
+    }
+
+    fn debug(&mut self, text: &str) {
+        self.buffer.debug(text);
+    }
+
+    fn print_object(&mut self, obj: &str) {
+        self.buffer.print_object(obj);
+    }
+
+    fn print_ascii_art(&mut self, art: &str) {
+        self.buffer.print_ascii_art(art);
+    }
+
+    fn flush(&mut self) {
+        let runs = match self.buffer.take_runs() {
+            Some(runs) => runs,
+            None => return,
+        };
+
+        match self.format.get() {
+            OutputFormat::Html => self.event(HostEvent::Print(render_html(&runs))),
+            OutputFormat::Tokens => self.event(HostEvent::Tokens(runs)),
         }
     }
 
+    fn set_status_bar(&self, left: &str, right: &str) {
+        self.event(HostEvent::Header(left.to_string(), right.to_string()))
+    }
+
+    fn message(&self, mtype: &str, msg: &str) {
+        self.sink.send(mtype, msg);
+    }
+
     fn clear(&self) {}
     fn reset(&self) {}
     fn get_user_input(&self) -> String {
@@ -1,8 +1,12 @@
 
+use std::any::Any;
 use std::boxed::Box;
-use std::collections::HashMap;
+use std::cell::RefCell;
 use std::fmt;
 use std::fmt::Write as FmtWrite;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
 use std::str;
 
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
@@ -11,32 +15,82 @@ use rand;
 use rand::Rng;
 use rand::rngs::StdRng;
 use rand::SeedableRng;
-use serde::{Serialize};
-use serde_json;
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
+use ts_rs::TS;
 
 use crate::ascii_art::AsciiArt;
-use crate::buffer::Buffer;
+use crate::buffer::{Buffer, MemoryError};
+use crate::coverage::Coverage;
+use crate::debugger::{Breakpoint, DebugCommand, DebugStop, Debugger, Stepping, Watchpoint, DEBUG_HELP};
+use crate::dictionary::{encode_text, Dictionary};
+use crate::events::HostEvent;
 use crate::frame::Frame;
+use crate::header::StoryHeader;
 use crate::hints::HintSystem;
 use crate::instruction::Branch;
+use crate::instruction::DecodeError;
 use crate::instruction::Instruction;
+use crate::instruction::InstructionCache;
 use crate::instruction::Opcode;
 use crate::instruction::Operand;
 use crate::instruction::OperandType;
+use crate::instruction::Operands;
 use crate::options::Options;
+use crate::profiler::Profiler;
 use crate::quetzal::QuetzalSave;
+use crate::rewind::{FrameChange, InstructionDelta, RewindLog};
 use crate::save_security::SaveValidator;
+use crate::trace::InstructionTrace;
 use crate::traits::UI;
+use crate::zstring::{self, ZStringDecoder, ZStringError};
+
+/// Why `Zmachine::run` handed control back to the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+#[serde(tag = "outcome", content = "message", rename_all = "snake_case")]
+pub enum StepOutcome {
+    /// Paused on READ, resume with `handle_input`
+    NeedsLine,
+    /// Paused on READ_CHAR, resume with `handle_input` (first char is used)
+    NeedsChar,
+    /// Paused on RESTORE, resume with `restore` (an empty string cancels)
+    NeedsRestoreData,
+    /// The instruction budget ran out, call `run` again to continue
+    Yielded,
+    /// The story has ended
+    Quit,
+    /// The story hit something the interpreter can't run
+    Error(String),
+    /// Stopped by the debugger, call `run` again to continue
+    Break(DebugStop),
+}
 
-#[derive(Debug)]
-enum ZStringState {
-    Alphabet(usize),
-    Abbrev(u8),
-    Tenbit1,
-    Tenbit2(u8),
+/// What went wrong when a guarded story hit an interpreter fault, for bug
+/// reports
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS))]
+pub struct CrashReport {
+    pub message: String,
+    #[cfg_attr(not(target_arch = "wasm32"), ts(type = "number"))]
+    pub pc: usize,
+    /// Name of the instruction at the pc, if it was decoded
+    pub opcode: Option<String>,
+    /// The last instructions before the fault, oldest first
+    pub recent: Vec<String>,
+    pub last_command: Option<String>,
+    /// False when there was no earlier state to go back to
+    pub recovered: bool,
 }
 
-#[derive(Debug, Serialize)]
+// Instructions traced at least, for crash reports in guarded mode
+const CRASH_HISTORY: usize = 16;
+
+// ZSCII 155 onwards when the story has no unicode table (standard 3.8.5.3)
+const DEFAULT_UNICODE: &str = "äöüÄÖÜß»«ëïÿËÏáéíóúýÁÉÍÓÚÝàèìòùÀÈÌÒÙâêîôûÂÊÎÔÛåÅøØãñõÃÑÕæÆçÇþðÞÐ£œŒ¡¿";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(TS), ts(rename = "GameObject"))]
 pub struct Object {
     number: u16,
     name: String,
@@ -90,6 +144,27 @@ impl Object {
         out
     }
 
+    pub fn number(&self) -> u16 {
+        self.number
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = &Object> {
+        self.children.iter().map(|child| &**child)
+    }
+
+    // depth first search for an object anywhere below this one
+    pub fn find(&self, number: u16) -> Option<&Object> {
+        if self.number == number {
+            return Some(self);
+        }
+
+        self.children().find_map(|child| child.find(number))
+    }
+
     fn to_string(&self) -> String {
         if !self.children.is_empty() {
             self.print_tree("", 0, false)
@@ -127,17 +202,22 @@ impl ObjectProperty {
 pub struct Zmachine {
     pub ui: Box<dyn UI>,
     pub options: Options,
-    pub instr_log: String,
+    pub instr_log: InstructionTrace,
+    pub debugger: Debugger,
+    pub rewind: RewindLog,
+    pub profiler: Profiler,
+    pub coverage: Coverage,
+    header: StoryHeader,
     version: u8,
     memory: Buffer,
     original_dynamic: Vec<u8>,
     static_start: usize,
     routine_offset: usize,
     string_offset: usize,
-    alphabet: [Vec<String>; 3],
-    abbrev_table: usize,
-    separators: Vec<char>,
-    dictionary: HashMap<String, usize>,
+    text: ZStringDecoder,
+    // writes here change cached print text, see note_write
+    abbrev_span: Range<usize>,
+    dictionary: Dictionary,
     frames: Vec<Frame>,
     initial_pc: usize,
     pc: usize,
@@ -146,7 +226,13 @@ pub struct Zmachine {
     obj_table_addr: usize,
     obj_size: usize,
     attr_width: usize,
-    paused_instr: Option<Instruction>,
+    paused_instr: Option<Rc<Instruction>>,
+    instr_cache: InstructionCache,
+    // instructions run since the story was loaded
+    instr_count: u64,
+    quit: bool,
+    fault: RefCell<Option<String>>,
+    last_command: Option<String>,
     current_state: Option<(String, Vec<u8>)>,
     undos: Vec<(String, Vec<u8>)>,
     redos: Vec<(String, Vec<u8>)>,
@@ -156,41 +242,55 @@ pub struct Zmachine {
 }
 
 impl Zmachine {
+    /// Panics if the header is invalid, `Game::try_load_story` checks first
     pub fn new(data: Vec<u8>, ui: Box<dyn UI>, options: Options) -> Zmachine {
-        let memory = Buffer::new(data);
+        let header = StoryHeader::parse(&data).unwrap_or_else(|err| panic!("{}", err));
+        let mut memory = Buffer::new(data);
+        memory.set_regions(header.static_memory, header.high_memory);
 
-        let version = memory.read_byte(0x00);
-        let initial_pc = memory.read_word(0x06) as usize;
-        let prop_defaults = memory.read_word(0x0A) as usize;
-        let static_start = memory.read_word(0x0E) as usize;
+        let version = header.version;
+        let initial_pc = header.initial_pc;
+        let prop_defaults = header.object_table;
+        let static_start = header.static_memory;
 
-        let alphabet = if version >= 5 {
-            Zmachine::load_alphabet(&memory)
+        let alphabet = if version >= 5 && header.alphabet_table != 0 {
+            zstring::load_alphabet(&memory, header.alphabet_table)
         } else {
-            Zmachine::default_alphabet()
+            zstring::default_alphabet()
         };
 
-        let mut zvm = Zmachine {
+        let text = ZStringDecoder::new(alphabet, header.abbreviations, static_start);
+        let abbrev_span = text.abbreviation_span(&memory);
+
+        Zmachine {
             version,
             ui,
-            instr_log: String::new(),
+            instr_log: InstructionTrace::new(Self::trace_capacity(&options)),
+            debugger: Debugger::new(),
+            rewind: RewindLog::new(options.rewind),
+            profiler: Profiler::new(options.profile),
+            coverage: Coverage::new(&header, options.coverage),
             original_dynamic: memory.slice(0, static_start).to_vec(),
-            globals_addr: memory.read_word(0x0C) as usize,
-            routine_offset: memory.read_word(0x28) as usize,
-            string_offset: memory.read_word(0x2A) as usize,
+            globals_addr: header.globals,
+            routine_offset: header.routine_offset,
+            string_offset: header.string_offset,
             static_start,
             initial_pc,
             pc: initial_pc,
             frames: vec![Frame::empty()],
-            alphabet,
-            abbrev_table: memory.read_word(0x18) as usize,
-            separators: Vec::new(),
-            dictionary: HashMap::new(),
+            text,
+            abbrev_span,
+            dictionary: Dictionary::read(&memory, header.dictionary).unwrap_or_else(|err| panic!("{}", err)),
             prop_defaults,
             obj_table_addr: prop_defaults + (if version <= 3 { 31 } else { 63 }) * 2,
             obj_size: if version <= 3 { 9 } else { 14 },
             attr_width: if version <= 3 { 4 } else { 6 },
             paused_instr: None,
+            instr_cache: InstructionCache::new(),
+            instr_count: 0,
+            quit: false,
+            fault: RefCell::new(None),
+            last_command: None,
             current_state: None,
             undos: Vec::new(),
             redos: Vec::new(),
@@ -203,29 +303,26 @@ impl Zmachine {
                 StdRng::from_seed(seed)
             },
             hint_system: HintSystem::new(),
-            secret_key: Self::derive_secret_key(&memory),
+            secret_key: Self::derive_secret_key(&header),
+            header,
             memory,
             options,
-        };
-
-        // read into dictionary & word separators
-        zvm.populate_dictionary();
+        }
+    }
 
-        zvm
+    pub(crate) fn secret_key(&self) -> &[u8; 32] {
+        &self.secret_key
     }
 
-    fn derive_secret_key(memory: &Buffer) -> [u8; 32] {
+    fn derive_secret_key(header: &StoryHeader) -> [u8; 32] {
         use sha2::Sha256;
         use sha2::Digest;
 
         let mut hasher = Sha256::new();
-        let release = memory.read_word(0x02);
-        let serial = memory.read(0x12, 6);
-        let checksum = memory.read_word(0x1C);
 
-        hasher.update(&release.to_le_bytes());
-        hasher.update(serial);
-        hasher.update(&checksum.to_le_bytes());
+        hasher.update(&header.release.to_le_bytes());
+        hasher.update(header.serial);
+        hasher.update(&header.checksum.to_le_bytes());
 
         let result = hasher.finalize();
         let mut key = [0u8; 32];
@@ -233,41 +330,16 @@ impl Zmachine {
         key
     }
 
-    fn to_alphabet_entry(s: &str) -> Vec<String> {
-        s.chars().map(|c| c.to_string()).collect()
+    pub fn header(&self) -> &StoryHeader {
+        &self.header
     }
 
-    #[allow(non_snake_case)]
-    fn default_alphabet() -> [Vec<String>; 3] {
-        let A0 = " .....abcdefghijklmnopqrstuvwxyz";
-        let A1 = " .....ABCDEFGHIJKLMNOPQRSTUVWXYZ";
-        let A2 = " ......\n0123456789.,!?_#'\"/\\-:()";
-
-        [
-            Zmachine::to_alphabet_entry(A0),
-            Zmachine::to_alphabet_entry(A1),
-            Zmachine::to_alphabet_entry(A2),
-        ]
+    pub(crate) fn version(&self) -> u8 {
+        self.version
     }
 
-    #[allow(non_snake_case)]
-    fn load_alphabet(memory: &Buffer) -> [Vec<String>; 3] {
-        let alphabet_addr = memory.read_word(0x34) as usize;
-
-        if alphabet_addr == 0 {
-            Zmachine::default_alphabet()
-        } else {
-            let A0 = format!(" .....{}", str::from_utf8(memory.read(alphabet_addr, 26)).expect("bad alphabet table A0!"));
-            let A1 = format!(" .....{}", str::from_utf8(memory.read(alphabet_addr + 26, 26)).expect("bad alphabet table A1!"));
-            // First two characters are ignored and accounted for in our padding.
-            let A2 = format!(" ......\n{}", str::from_utf8(memory.read(alphabet_addr + 26 + 26 + 2, 24)).expect("Bad alphabet table A2!"));
-
-            [
-                Zmachine::to_alphabet_entry(&A0),
-                Zmachine::to_alphabet_entry(&A1),
-                Zmachine::to_alphabet_entry(&A2),
-            ]
-        }
+    pub(crate) fn memory(&self) -> &Buffer {
+        &self.memory
     }
 
     fn unpack(&self, addr: u16) -> usize {
@@ -281,50 +353,61 @@ impl Zmachine {
         }
     }
 
-    fn unpack_routine_addr(&self, addr: u16) -> usize {
+    pub(crate) fn unpack_routine_addr(&self, addr: u16) -> usize {
         match self.unpack(addr) {
             x @ 6..=7 => x + self.routine_offset * 8,
             x => x,
         }
     }
 
-    fn unpack_print_paddr(&self, addr: u16) -> usize {
+    pub(crate) fn unpack_print_paddr(&self, addr: u16) -> usize {
         match self.unpack(addr) {
             x @ 6..=7 => x + self.string_offset * 8,
             x => x,
         }
     }
 
-    fn read_global(&self, index: u8) -> u16 {
+    pub(crate) fn read_global(&self, index: u8) -> u16 {
         if index > 240 {
-            panic!("Can't read global{}!", index);
+            self.raise(format!("Can't read global{}!", index));
+            return 0;
         }
 
         let addr = self.globals_addr + index as usize * 2;
-        self.memory.read_word(addr)
+        self.story_word(addr)
     }
 
     fn write_global(&mut self, index: u8, value: u16) {
         if index > 240 {
-            panic!("Can't write global{}!", index);
+            self.raise(format!("Can't write global{}!", index));
+            return;
         }
 
         let addr = self.globals_addr + index as usize * 2;
-        self.memory.write_word(addr, value);
+        self.store_word(addr, value);
     }
 
+    // Missing locals and an empty stack are faults, reading as 0
     fn read_local(&self, index: u8) -> u16 {
-        self.frames
-            .last()
-            .expect("Can't write local, no frames!")
-            .read_local(index)
+        let frame = self.frames.last().expect("Can't read local, no frames!");
+
+        frame.read_local(index).unwrap_or_else(|| {
+            self.raise(format!("Can't read local {}, the routine doesn't have it", index + 1));
+            0
+        })
     }
 
     fn write_local(&mut self, index: u8, value: u16) {
-        self.frames
-            .last_mut()
-            .expect("Can't write local, no frames!")
-            .write_local(index, value);
+        if self.rewind.is_recording() {
+            let old = self.read_local(index);
+            self.rewind.note(FrameChange::Local { index, old });
+        }
+
+        let frame = self.frames.last_mut().expect("Can't write local, no frames!");
+
+        if !frame.write_local(index, value) {
+            self.raise(format!("Can't write local {}, the routine doesn't have it", index + 1));
+        }
     }
 
     fn stack_push(&mut self, value: u16) {
@@ -332,20 +415,39 @@ impl Zmachine {
             .last_mut()
             .expect("Can't push to stack, no frames!")
             .stack_push(value);
+        self.rewind.note(FrameChange::StackPush);
     }
 
     fn stack_pop(&mut self) -> u16 {
-        self.frames
+        let value = self
+            .frames
             .last_mut()
             .expect("Can't pop stack, no frames!")
-            .stack_pop()
+            .stack_pop();
+
+        match value {
+            Some(value) => {
+                self.rewind.note(FrameChange::StackPop(value));
+                value
+            }
+            None => {
+                self.raise(String::from("Can't pop off an empty stack"));
+                0
+            }
+        }
     }
 
     fn stack_peek(&mut self) -> u16 {
-        self.frames
-            .last_mut()
+        let value = self
+            .frames
+            .last()
             .expect("Can't peek stack, no frames!")
-            .stack_peek()
+            .stack_peek();
+
+        value.unwrap_or_else(|| {
+            self.raise(String::from("Can't peek on an empty stack"));
+            0
+        })
     }
 
     fn read_variable(&mut self, index: u8) -> u16 {
@@ -391,173 +493,113 @@ impl Zmachine {
         }
     }
 
-    fn get_abbrev(&self, index: u8) -> String {
-        if index > 96 {
-            panic!("Bad abbrev index: {}", index);
-        }
-
-        let offset = 2 * index as usize;
-        let word_addr = self.memory.read_word(self.abbrev_table + offset);
-        let addr = word_addr * 2; // "Word addresses are used only in the abbreviations table" - 1.2.2
-
-        self.read_zstring_from_abbrev(addr as usize)
-    }
-
-    fn read_zstring_from_abbrev(&self, addr: usize) -> String {
-        self.read_zstring_impl(addr, false)
-    }
-
-    fn read_zstring(&self, addr: usize) -> String {
-        self.read_zstring_impl(addr, true)
-    }
-
-    fn read_zstring_impl(&self, addr: usize, allow_abbrevs: bool) -> String {
-        use self::ZStringState::*;
-
-        let mut state = Alphabet(0);
-        let mut index = addr;
-        let mut zstring = String::new();
-
-        // this closure borrows the zstring while it steps through each zchar.
-        // (wrapped here in its own scope to force the borrow to end)
-        {
-            let mut step = |zchar: u8| {
-                state = match (zchar, &state) {
-                    // the next zchar will be an abbrev index
-                    (zch, &Alphabet(_)) if zch >= 1 && zch <= 3 => {
-                        assert!(allow_abbrevs, "Abbrev at {} contained recursive abbrev!", addr);
-                        Abbrev(zch)
-                    }
-                    // shift character for the next zchar
-                    (4, &Alphabet(_)) => Alphabet(1),
-                    (5, &Alphabet(_)) => Alphabet(2),
-                    // special 10bit case, next 2 zchars = one 10bit zscii char
-                    (6, &Alphabet(2)) => Tenbit1,
-                    (_, &Tenbit1) => Tenbit2(zchar),
-                    (_, &Tenbit2(first)) => {
-                        let letter = ((first << 5) + zchar) as char;
-                        zstring.push_str(&letter.to_string());
-                        Alphabet(0)
-                    }
-                    // get the abbrev at this addr
-                    (_, &Abbrev(num)) => {
-                        let abbrev = self.get_abbrev((num - 1) * 32 + zchar);
-                        zstring.push_str(&abbrev);
-                        Alphabet(0)
-                    }
-                    // normal case, adds letter from correct alphabet and resets to A0
-                    (_, &Alphabet(num)) => {
-                        let letter = &self.alphabet[num][zchar as usize];
-                        zstring.push_str(letter);
-                        Alphabet(0)
-                    }
-                };
-            };
-
-            // 3 zchars per each 16 bit word + a "stop" bit on top
-            // 0 10101 01010 10101
-            loop {
-                let word = self.memory.read_word(index);
-                index += 2;
-
-                step(((word >> 10) & 0b0001_1111) as u8);
-                step(((word >> 5) & 0b0001_1111) as u8);
-                step((word & 0b0001_1111) as u8);
-
-                // stop bit
-                if word & 0x8000 != 0 {
-                    break;
-                }
-            }
-        } // <- drop process closure, ending zstring borrow
-
-        zstring
+    pub(crate) fn get_abbrev(&self, index: u8) -> Result<String, ZStringError> {
+        self.text.abbreviation(&self.memory, index).map(|text| text.to_string())
     }
 
-    // reads the ENCODED byte length of a zstring, how many consecutive
-    // bytes in memory it is (not just the number of characters)
-    fn zstring_length(&self, addr: usize) -> usize {
-        let mut length = 0;
-
-        loop {
-            let word = self.memory.read_word(addr + length);
-            length += 2;
-
-            // stop bit
-            if word & 0x8000 != 0 {
-                break;
-            }
-        }
-
-        length
+    pub(crate) fn read_zstring(&self, addr: usize) -> Result<String, ZStringError> {
+        self.text.decode(&self.memory, addr).map(|text| text.to_string())
     }
 
-    fn populate_dictionary(&mut self) {
-        let dictionary_start = self.memory.read_word(0x08) as usize;
-        let mut read = self.memory.get_reader(dictionary_start);
+    // Shared with the cache, printing doesn't need a copy. The address
+    // comes from the story, a bad string is a fault and prints nothing
+    fn decode_zstring(&self, addr: usize) -> Rc<str> {
+        self.text.decode(&self.memory, addr).unwrap_or_else(|err| {
+            self.raise(err.to_string());
+            Rc::from("")
+        })
+    }
 
-        let separator_count = read.byte();
+    // Reads at addresses that come from the story (objects, properties,
+    // tables): one past the end is a fault and reads as 0
+    fn story_byte(&self, addr: usize) -> u8 {
+        self.memory.try_read_byte(addr).unwrap_or_else(|err| {
+            self.raise(err.to_string());
+            0
+        })
+    }
 
-        for _ in 0..separator_count {
-            self.separators.push(read.byte() as char);
-        }
+    fn story_word(&self, addr: usize) -> u16 {
+        self.memory.try_read_word(addr).unwrap_or_else(|err| {
+            self.raise(err.to_string());
+            0
+        })
+    }
 
-        let entry_length = read.byte() as usize;
-        let entry_count = read.word() as usize;
-        let entry_start = read.position();
+    fn story_bytes(&self, addr: usize, length: usize) -> &[u8] {
+        self.memory.try_read(addr, length).unwrap_or_else(|err| {
+            self.raise(err.to_string());
+            &[]
+        })
+    }
 
-        for n in 0..entry_count {
-            let addr = entry_start + n * entry_length;
-            let entry = self.read_zstring(addr);
+    // The dictionary entry for a word, 0 if it isn't in `dictionary`
+    fn check_dict(&self, word: &[u8], dictionary: &Dictionary) -> usize {
+        let encoded = encode_text(word, self.version, self.text.alphabet());
 
-            self.dictionary.insert(entry, addr);
-        }
+        dictionary.lookup(&self.memory, &encoded).unwrap_or_else(|err| {
+            self.raise(err.to_string());
+            0
+        })
     }
 
-    fn check_dict(&self, word: &str) -> usize {
-        let length = if self.version <= 3 { 6 } else { 9 };
-        let mut short = word.to_string();
-        short.truncate(length);
+    // Splits `text` into words at spaces and separators (which are words of
+    // their own) and fills in the parse buffer (standard 13.6). `start` is
+    // where the text begins in its text buffer. With `skip_unknown` the
+    // entries for words that aren't in the dictionary are left alone
+    fn tokenise(&mut self, text: &[u8], start: usize, parse_addr: usize, dictionary: &Dictionary, skip_unknown: bool) {
+        let mut words = Vec::new();
+        let mut word_start = None;
 
-        match self.dictionary.get(&short) {
-            Some(addr) => *addr,
-            None => 0,
-        }
-    }
+        for (index, &zscii) in text.iter().enumerate() {
+            let separator = dictionary.is_separator(zscii);
 
-    fn tokenise(&mut self, text: &str, parse_addr: usize) {
-        // v1-4 start storing @ byte 1, v5+ start @2;
-        let start = if self.version <= 4 { 1 } else { 2 };
-        let mut input = String::from(text);
-        let mut found = HashMap::new();
+            if zscii == b' ' || separator {
+                if let Some(first) = word_start.take() {
+                    words.push((first, index));
+                }
 
-        for sep in &self.separators {
-            input = input.replace(&sep.to_string(), &format!(" {} ", sep))
+                if separator {
+                    words.push((index, index + 1));
+                }
+            } else if word_start.is_none() {
+                word_start = Some(index);
+            }
         }
 
-        let tokens: Vec<_> = input
-            .split_whitespace()
-            .filter(|token| !token.is_empty())
-            .map(|token| {
-                let offset = found.entry(token).or_insert(0);
-                let position = text[*offset..].find(token).unwrap();
+        if let Some(first) = word_start {
+            words.push((first, text.len()));
+        }
 
-                let dict_addr = self.check_dict(token);
-                let token_addr = *offset + position + start;
+        let max_words = self.story_byte(parse_addr) as usize;
+        words.truncate(max_words);
 
-                *offset += position + token.len();
+        let tokens: Vec<_> = words
+            .iter()
+            .map(|&(first, end)| {
+                let dict_addr = self.check_dict(&text[first..end], dictionary);
 
-                (dict_addr, token.len(), token_addr)
+                (dict_addr, end - first, first + start)
             })
             .collect();
 
+        if let Err(err) = self.memory.check_write(parse_addr + 1, 1 + tokens.len() * 4) {
+            if !self.illegal_write(err) {
+                return;
+            }
+        }
+
         let mut write = self.memory.get_writer(parse_addr + 1);
         write.byte(tokens.len() as u8);
 
-        tokens.iter().for_each(|&(dict_addr, len, token_addr)| {
-            write.word(dict_addr as u16);
-            write.byte(len as u8);
-            write.byte(token_addr as u8);
+        tokens.iter().enumerate().for_each(|(n, &(dict_addr, len, token_addr))| {
+            write.seek(parse_addr + 2 + n * 4);
+
+            if dict_addr != 0 || !skip_unknown {
+                write.word(dict_addr as u16);
+                write.byte(len as u8);
+                write.byte(token_addr as u8);
+            }
         });
     }
 
@@ -569,7 +611,7 @@ impl Zmachine {
         self.obj_table_addr + ((object as usize - 1) * self.obj_size)
     }
 
-    fn get_object_prop_table_addr(&self, object: u16) -> usize {
+    pub(crate) fn get_object_prop_table_addr(&self, object: u16) -> usize {
         let addr = self.get_object_addr(object)
             // skip attributes
             + self.attr_width
@@ -577,24 +619,28 @@ impl Zmachine {
             + if self.version <= 3 { 3 } else { 6 };
 
         // the property table address is in the next word:
-        self.memory.read_word(addr) as usize
+        self.story_word(addr) as usize
     }
 
     // Object name is found at the start the object's property table:
     //   text-length   text of short name of object
     //   ---byte----   --some even number of bytes--
-    fn get_object_name(&self, object: u16) -> String {
+    pub(crate) fn get_object_name(&self, object: u16) -> String {
+        self.object_name(object).to_string()
+    }
+
+    fn object_name(&self, object: u16) -> Rc<str> {
         let addr = self.get_object_prop_table_addr(object);
-        let text_length = self.memory.read_byte(addr);
+        let text_length = self.story_byte(addr);
 
         if text_length > 0 {
-            self.read_zstring(addr + 1)
+            self.decode_zstring(addr + 1)
         } else {
-            String::new()
+            Rc::from("")
         }
     }
 
-    fn get_parent(&self, object: u16) -> u16 {
+    pub(crate) fn get_parent(&self, object: u16) -> u16 {
         if object == 0 {
             return 0;
         }
@@ -602,9 +648,9 @@ impl Zmachine {
         let addr = self.get_object_addr(object) + self.attr_width;
 
         if self.version <= 3 {
-            u16::from(self.memory.read_byte(addr))
+            u16::from(self.story_byte(addr))
         } else {
-            self.memory.read_word(addr)
+            self.story_word(addr)
         }
     }
 
@@ -612,13 +658,13 @@ impl Zmachine {
         let addr = self.get_object_addr(object) + self.attr_width;
 
         if self.version <= 3 {
-            self.memory.write_byte(addr, parent as u8);
+            self.store_byte(addr, parent as u8);
         } else {
-            self.memory.write_word(addr, parent);
+            self.store_word(addr, parent);
         }
     }
 
-    fn get_sibling(&self, object: u16) -> u16 {
+    pub(crate) fn get_sibling(&self, object: u16) -> u16 {
         if object == 0 {
             return 0;
         }
@@ -626,9 +672,9 @@ impl Zmachine {
         let addr = self.get_object_addr(object) + self.attr_width;
 
         if self.version <= 3 {
-            u16::from(self.memory.read_byte(addr + 1))
+            u16::from(self.story_byte(addr + 1))
         } else {
-            self.memory.read_word(addr + 2)
+            self.story_word(addr + 2)
         }
     }
 
@@ -636,13 +682,13 @@ impl Zmachine {
         let addr = self.get_object_addr(object) + self.attr_width;
 
         if self.version <= 3 {
-            self.memory.write_byte(addr + 1, sibling as u8);
+            self.store_byte(addr + 1, sibling as u8);
         } else {
-            self.memory.write_word(addr + 2, sibling);
+            self.store_word(addr + 2, sibling);
         }
     }
 
-    fn get_child(&self, object: u16) -> u16 {
+    pub(crate) fn get_child(&self, object: u16) -> u16 {
         if object == 0 {
             return 0;
         }
@@ -650,9 +696,9 @@ impl Zmachine {
         let addr = self.get_object_addr(object) + self.attr_width;
 
         if self.version <= 3 {
-            u16::from(self.memory.read_byte(addr + 2))
+            u16::from(self.story_byte(addr + 2))
         } else {
-            self.memory.read_word(addr + 4)
+            self.story_word(addr + 4)
         }
     }
 
@@ -660,9 +706,9 @@ impl Zmachine {
         let addr = self.get_object_addr(object) + self.attr_width;
 
         if self.version <= 3 {
-            self.memory.write_byte(addr + 2, child as u8);
+            self.store_byte(addr + 2, child as u8);
         } else {
-            self.memory.write_word(addr + 4, child);
+            self.store_word(addr + 4, child);
         }
     }
 
@@ -724,7 +770,7 @@ impl Zmachine {
         self.set_sibling(object, parents_first_child);
     }
 
-    fn get_total_object_count(&self) -> u16 {
+    pub(crate) fn get_total_object_count(&self) -> u16 {
         // by convention, the property table for object #1 is located AFTER
         // the last object in the object table:
         let obj_table_end = self.get_object_prop_table_addr(1);
@@ -791,11 +837,12 @@ impl Zmachine {
 
     fn test_attr(&self, object: u16, attr: u16) -> u16 {
         if attr as usize > self.attr_width * 8 {
-            panic!("Can't test out-of-bounds attribute: {}", attr);
+            self.raise(format!("Can't test out-of-bounds attribute: {}", attr));
+            return 0;
         }
 
         let addr = self.get_object_addr(object) + attr as usize / 8;
-        let byte = self.memory.read_byte(addr);
+        let byte = self.story_byte(addr);
         let bit = attr % 8;
 
         if byte & (128 >> bit) != 0 { 1 } else { 0 }
@@ -803,37 +850,44 @@ impl Zmachine {
 
     fn set_attr(&mut self, object: u16, attr: u16) {
         if attr as usize > self.attr_width * 8 {
-            panic!("Can't set out-of-bounds attribute: {}", attr);
+            self.raise(format!("Can't set out-of-bounds attribute: {}", attr));
+            return;
         }
 
         let addr = self.get_object_addr(object) + attr as usize / 8;
-        let byte = self.memory.read_byte(addr);
+        let byte = self.story_byte(addr);
         let bit = attr % 8;
 
-        self.memory.write_byte(addr, byte | (128 >> bit));
+        self.store_byte(addr, byte | (128 >> bit));
     }
 
     fn clear_attr(&mut self, object: u16, attr: u16) {
         if attr as usize > self.attr_width * 8 {
-            panic!("Can't clear out-of-bounds attribute: {}", attr);
+            self.raise(format!("Can't clear out-of-bounds attribute: {}", attr));
+            return;
         }
 
         let addr = self.get_object_addr(object) + attr as usize / 8;
-        let byte = self.memory.read_byte(addr);
+        let byte = self.story_byte(addr);
         let bit = attr % 8;
 
-        self.memory.write_byte(addr, byte & !(128 >> bit));
+        self.store_byte(addr, byte & !(128 >> bit));
     }
 
     fn get_default_prop(&self, property_number: u16) -> u16 {
+        if property_number == 0 {
+            self.raise(String::from("There is no property 0"));
+            return 0;
+        }
+
         let word_index = (property_number - 1) as usize;
         let addr = self.prop_defaults + word_index * 2;
 
-        self.memory.read_word(addr)
+        self.story_word(addr)
     }
 
     fn read_object_prop(&self, addr: usize) -> ObjectProperty {
-        let header = self.memory.read_byte(addr);
+        let header = self.story_byte(addr);
         let mut len;
         let num;
         let value_addr;
@@ -848,7 +902,7 @@ impl Zmachine {
                 num = header & 0b0011_1111; // prop num is bottom 6 bits
 
                 if header & 0b1000_0000 != 0 {
-                    len = self.memory.read_byte(addr + 1) & 0b0011_1111;
+                    len = self.story_byte(addr + 1) & 0b0011_1111;
                     if len == 0 { len = 64; } // Z-Machine standard section 12.4.2.1.1
 
                     value_addr = addr + 2; // 2 byte header
@@ -873,7 +927,7 @@ impl Zmachine {
         }
 
         let addr = self.get_object_prop_table_addr(object);
-        let str_length = self.memory.read_byte(addr) as usize * 2; // words in name
+        let str_length = self.story_byte(addr) as usize * 2; // words in name
         let first_addr = addr + str_length + 1;
 
         let property_number = property_number as u8;
@@ -898,9 +952,9 @@ impl Zmachine {
         if prop.num == 0 {
             self.get_default_prop(property_number)
         } else if prop.len == 1 {
-            u16::from(self.memory.read_byte(prop.addr))
+            u16::from(self.story_byte(prop.addr))
         } else {
-            self.memory.read_word(prop.addr)
+            self.story_word(prop.addr)
         }
     }
 
@@ -917,7 +971,7 @@ impl Zmachine {
         }
 
         // address given is the property DATA, the property HEADER is right before
-        let prop_header = self.memory.read_byte(prop_data_addr - 1);
+        let prop_header = self.story_byte(prop_data_addr - 1);
 
         if self.version <= 3 {
             prop_header / 32 + 1
@@ -937,7 +991,7 @@ impl Zmachine {
         // if property 0 is requested, give the first property present
         if property_number == 0 {
             let addr = self.get_object_prop_table_addr(object);
-            let str_length = self.memory.read_byte(addr) as usize * 2;
+            let str_length = self.story_byte(addr) as usize * 2;
             let first_prop = addr + str_length + 1;
 
             u16::from(self.read_object_prop(first_prop).num)
@@ -952,9 +1006,9 @@ impl Zmachine {
         let prop = self.find_prop(object, property_number);
 
         if prop.len == 1 {
-            self.memory.write_byte(prop.addr, value as u8);
+            self.store_byte(prop.addr, value as u8);
         } else {
-            self.memory.write_word(prop.addr, value);
+            self.store_word(prop.addr, value);
         }
     }
 
@@ -976,14 +1030,14 @@ impl Zmachine {
         &mut self.hint_system
     }
 
-    fn get_status(&self) -> (String, String) {
+    pub fn get_status(&self) -> (String, String) {
         let num = self.read_global(0);
         let left = self.get_object_name(num);
 
         // bit 1 in header flags:
         // 0 => score/turns
         // 1 => AM/PM
-        let right = if self.memory.read_byte(0x01) & 0b0000_0010 == 0 {
+        let right = if !self.header.is_time_game() {
             let score = self.read_global(1) as i16;
             let turns = self.read_global(2);
 
@@ -1017,18 +1071,16 @@ impl Zmachine {
         let dynamic = self.memory.slice(0, self.static_start);
         let original = self.original_dynamic.as_slice();
         let frames = &self.frames;
-        let chksum = self.memory.read_word(0x1c);
-        let release = self.memory.read_word(0x02);
-        let serial = self.memory.read(0x12, 6);
+        let header = &self.header;
 
-        QuetzalSave::make(pc, dynamic, original, frames, chksum, release, serial)
+        QuetzalSave::make(pc, dynamic, original, frames, header.checksum, header.release, &header.serial)
     }
 
     pub fn restore_state(&mut self, data: &[u8]) {
         match QuetzalSave::from_bytes(&data[..], &self.original_dynamic[..]) {
             Ok(save) => {
                 // verify that the save is for the right game and that the memory is ok
-                if save.chksum != self.memory.read_word(0x1C) {
+                if save.chksum != self.header.checksum {
                     self.ui.print("Save file is from a different game version\n");
                     return;
                 }
@@ -1038,9 +1090,12 @@ impl Zmachine {
                     return;
                 }
 
+                self.rewind.clear();
                 self.pc = save.pc;
                 self.frames = save.frames;
                 self.memory.write(0, save.memory.as_slice());
+                self.note_write(0, save.memory.len());
+                self.quit = false;
 
                 // Ensure we have at least one frame to prevent crashes
                 if self.frames.is_empty() {
@@ -1095,7 +1150,7 @@ impl Zmachine {
         true
     }
 
-    fn get_arguments(&mut self, operands: &[Operand]) -> Vec<u16> {
+    fn get_arguments(&mut self, operands: &[Operand]) -> Operands<u16> {
         operands
             .iter()
             .map(|operand| match *operand {
@@ -1107,10 +1162,17 @@ impl Zmachine {
     }
 
     fn return_from_routine(&mut self, value: u16) {
+        // the main routine's frame isn't a call, there's nothing to go back to
+        if self.frames.len() <= 1 {
+            return self.raise(String::from("Can't return from the main routine"));
+        }
+
         let frame = self.frames.pop().expect("Can't pop off last frame!");
+        let store = frame.store;
         self.pc = frame.resume;
+        self.rewind.note(FrameChange::Return(frame));
 
-        if let Some(index) = frame.store {
+        if let Some(index) = store {
             self.write_variable(index, value);
         }
     }
@@ -1139,55 +1201,54 @@ impl Zmachine {
     fn process_result(&mut self, instr: &Instruction, value: u16) {
         // store the result if needed
         if let Some(index) = instr.store {
+            self.instr_log.record_result(instr.addr, value);
             self.write_variable(index, value);
         }
 
         // check if we need to branch
         if let Some(ref branch) = instr.branch {
+            self.coverage.record_branch(instr.addr, u16::from(value >= 1) == branch.condition);
             self.process_branch(branch, instr.next, value);
         } else {
             self.pc = instr.next;
         }
     }
 
-    fn decode_instruction(&self, addr: usize) -> Instruction {
+    pub(crate) fn decode_instruction(&self, addr: usize) -> Result<Instruction, DecodeError> {
         let mut read = self.memory.get_reader(addr);
-        let first = read.byte();
+        let first = read.try_byte()?;
 
         let btm_4 = |num| num & 0b0000_1111;
         let btm_5 = |num| num & 0b0001_1111;
         let get_types = |bytes: &[u8]| OperandType::from(bytes);
+        let fixed = |types: &[OperandType]| types.iter().cloned().collect::<Operands<_>>();
 
         let get_opcode = |code: u8, offset: u16| {
-            let num = u16::from(code) + offset;
-
-            match Opcode::from_u16(num) {
-                Some(val) => val,
-                None => panic!("Opcode not found: {:?}", num),
-            }
+            let number = u16::from(code) + offset;
+            Opcode::from_u16(number).ok_or(DecodeError::UnknownOpcode { addr, number })
         };
 
         use self::OperandType::*;
 
         #[allow(unreachable_patterns)]
         let (opcode, optypes) = match first {
-            0xbe => (get_opcode(read.byte(), 1000), get_types(&[read.byte()])),
-            0x00..=0x1f => (get_opcode(btm_5(first), 0), vec![Small, Small]),
-            0x20..=0x3f => (get_opcode(btm_5(first), 0), vec![Small, Variable]),
-            0x40..=0x5f => (get_opcode(btm_5(first), 0), vec![Variable, Small]),
-            0x60..=0x7f => (get_opcode(btm_5(first), 0), vec![Variable, Variable]),
-            0x80..=0x8f => (get_opcode(btm_4(first), 128), vec![Large]),
-            0x90..=0x9f => (get_opcode(btm_4(first), 128), vec![Small]),
-            0xa0..=0xaf => (get_opcode(btm_4(first), 128), vec![Variable]),
-            0xb0..=0xbd | 0xbf => (get_opcode(btm_4(first), 176), vec![]), // OP_0
-            0xc0..=0xdf => (get_opcode(btm_5(first), 0), get_types(&[read.byte()])),
+            0xbe => (get_opcode(read.try_byte()?, 1000)?, get_types(&[read.try_byte()?])),
+            0x00..=0x1f => (get_opcode(btm_5(first), 0)?, fixed(&[Small, Small])),
+            0x20..=0x3f => (get_opcode(btm_5(first), 0)?, fixed(&[Small, Variable])),
+            0x40..=0x5f => (get_opcode(btm_5(first), 0)?, fixed(&[Variable, Small])),
+            0x60..=0x7f => (get_opcode(btm_5(first), 0)?, fixed(&[Variable, Variable])),
+            0x80..=0x8f => (get_opcode(btm_4(first), 128)?, fixed(&[Large])),
+            0x90..=0x9f => (get_opcode(btm_4(first), 128)?, fixed(&[Small])),
+            0xa0..=0xaf => (get_opcode(btm_4(first), 128)?, fixed(&[Variable])),
+            0xb0..=0xbd | 0xbf => (get_opcode(btm_4(first), 176)?, Operands::default()), // OP_0
+            0xc0..=0xdf => (get_opcode(btm_5(first), 0)?, get_types(&[read.try_byte()?])),
             0xe0..=0xff => {
-                let opcode = get_opcode(btm_5(first), 224);
+                let opcode = get_opcode(btm_5(first), 224)?;
 
                 if opcode == Opcode::VAR_236 || opcode == Opcode::VAR_250 {
-                    (opcode, get_types(&[read.byte(), read.byte()]))
+                    (opcode, get_types(&[read.try_byte()?, read.try_byte()?]))
                 } else {
-                    (opcode, get_types(&[read.byte()]))
+                    (opcode, get_types(&[read.try_byte()?]))
                 }
             }
             _ => unreachable!(),
@@ -1196,27 +1257,27 @@ impl Zmachine {
         let operands = optypes
             .iter()
             .map(|optype| match *optype {
-                OperandType::Small => Operand::Small(read.byte()),
-                OperandType::Large => Operand::Large(read.word()),
-                OperandType::Variable => Operand::Variable(read.byte()),
+                OperandType::Small => read.try_byte().map(Operand::Small),
+                OperandType::Large => read.try_word().map(Operand::Large),
+                OperandType::Variable => read.try_byte().map(Operand::Variable),
                 OperandType::Omitted => unreachable!(),
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
 
         let store = if Instruction::does_store(opcode, self.version) {
-            Some(read.byte())
+            Some(read.try_byte()?)
         } else {
             None
         };
 
         let branch = if Instruction::does_branch(opcode, self.version) {
-            let byte = read.byte() as usize;
+            let byte = read.try_byte()? as usize;
             let condition = if byte & 0b1000_0000 != 0 { 1 } else { 0 };
 
             let offset = if byte & 0b0100_0000 != 0 {
                 byte & 0b0011_1111
             } else {
-                ((byte & 0b0011_1111) << 8) + read.byte() as usize
+                ((byte & 0b0011_1111) << 8) + read.try_byte()? as usize
             };
 
             // the offset (if two bytes) is a 14 bit unsigned int: 2^14 = 16384
@@ -1248,13 +1309,13 @@ impl Zmachine {
         };
 
         let text = if Instruction::does_text(opcode) {
-            Some(self.read_zstring(read.position()))
+            Some(self.read_zstring(read.position())?)
         } else {
             None
         };
 
         let text_length = if text.is_some() {
-            self.zstring_length(read.position())
+            zstring::encoded_length(&self.memory, read.position())?
         } else {
             0
         };
@@ -1262,7 +1323,7 @@ impl Zmachine {
         let name = Instruction::name(opcode, self.version);
         let next = read.position() + text_length;
 
-        Instruction {
+        Ok(Instruction {
             addr,
             opcode,
             name,
@@ -1271,7 +1332,7 @@ impl Zmachine {
             branch,
             text,
             next,
-        }
+        })
     }
 
     pub fn handle_instruction(&mut self, instr: &Instruction) {
@@ -1279,6 +1340,7 @@ impl Zmachine {
 
         // ~mutably~ gets the arguments (might pop stack)
         let args = self.get_arguments(instr.operands.as_slice());
+        self.instr_log.record_args(instr.addr, &args);
 
         // Match instructions that return values for storing or branching (or both)
         // `result` is an option. either a matched instruction or none (no match)
@@ -1369,15 +1431,23 @@ impl Zmachine {
             (VAR_236, _) if !args.is_empty() => self.do_call(instr, args[0], &args[1..]), // call_vs2
             (VAR_249, _) if !args.is_empty() => self.do_call(instr, args[0], &args[1..]), // call_vn
             (VAR_250, _) if !args.is_empty() => self.do_call(instr, args[0], &args[1..]), // call_vn2
+            (VAR_251, &[text, parse]) => self.do_tokenise(text, parse, 0, 0),
+            (VAR_251, &[text, parse, dictionary]) => self.do_tokenise(text, parse, dictionary, 0),
+            (VAR_251, &[text, parse, dictionary, flag]) => self.do_tokenise(text, parse, dictionary, flag),
+            (VAR_252, &[text, length, from, coded]) => self.do_encode_text(text, length, from, coded),
 
             // special cases to no-op: (input/output streams & sound effects)
             // these might be present in some v3 games but aren't implemented yet
             (VAR_243, _) | (VAR_244, _) | (VAR_245, _) => (),
 
-            _ => panic!(
-                "\n\nOpcode not yet implemented: {} ({:?}) @ {:#04x}\n\n",
-                instr.name, instr.opcode, self.pc
-            ),
+            _ => {
+                // reported by run(), the pc stays on the instruction
+                self.raise(format!(
+                    "Opcode not yet implemented: {} ({:?}) @ {:#04x}",
+                    instr.name, instr.opcode, self.pc
+                ));
+                return;
+            }
         }
 
         // advance pc to the next instruction
@@ -1389,69 +1459,434 @@ impl Zmachine {
 
     // Web UI only
     #[allow(dead_code)]
+    /// Runs until the story needs input or quits, returns true on quit
     pub fn step(&mut self) -> bool {
-        // loop through instructions until user input is needed
-        // (saves/restores need a save name, read instructions need user input)
-        // Pauses on these instructions and control is passed back to js
         loop {
-            let instr = self.decode_instruction(self.pc);
+            match self.run(usize::MAX) {
+                StepOutcome::Quit => return true,
+                StepOutcome::Error(message) => panic!("\n\n{}\n\n", message),
+                // step() hosts have no debugger prompt, breakpoints don't stop them
+                StepOutcome::Break(_) => continue,
+                _ => return false,
+            }
+        }
+    }
 
-            match instr.opcode {
-                // SAVE
-                Opcode::OP0_181 => {
-                    let pc = instr.next - 1;
-                    let state = self.make_save_state(pc);
-                    self.send_save_message("save", &state);
+    /// Runs at most `budget` instructions. Hosts that mustn't block (e.g. a
+    /// browser tab) run in slices and call this again while it yields.
+    ///
+    /// With `Options::guarded` a fault rolls the story back to the prompt
+    /// before the last command and sends a `crash` event instead.
+    pub fn run(&mut self, budget: usize) -> StepOutcome {
+        if !self.options.guarded {
+            return self.run_unguarded(budget);
+        }
 
-                    // Advance the pc, assuming that the save was successful
-                    self.process_result(&instr, 1);
-                }
-                // RESTORE (breaks loop)
-                Opcode::OP0_182 => {
-                    self.ui.message("restore", "");
-                    self.paused_instr = Some(instr);
+        let message = match panic::catch_unwind(AssertUnwindSafe(|| self.run_unguarded(budget))) {
+            Ok(StepOutcome::Error(message)) => message,
+            Ok(outcome) => return outcome,
+            Err(payload) => panic_message(payload),
+        };
 
-                    return false;
-                }
-                // QUIT (breaks loop)
-                Opcode::OP0_186 => {
-                    // undo 2x - get to the savestate right before the
-                    // "are you sure?" dialog box that usually shows up:
-                    self.undos.pop();
+        // back at the last prompt, which pauses again right away
+        match self.recover(message) {
+            Ok(()) => self.run(budget),
+            Err(message) => StepOutcome::Error(message),
+        }
+    }
+
+    fn run_unguarded(&mut self, budget: usize) -> StepOutcome {
+        // a fault while handling input
+        if let Some(message) = self.fault.get_mut().take() {
+            return StepOutcome::Error(message);
+        }
+
+        if self.quit {
+            return StepOutcome::Quit;
+        }
+
+        // still waiting for the host, nothing to run
+        if let Some(ref instr) = self.paused_instr {
+            return Self::waiting_for(instr);
+        }
 
-                    if let Some((_, state)) = self.undos.pop() {
-                        self.send_save_message("savestate", &state);
-                    }
+        for _ in 0..budget {
+            if self.pc >= self.memory.len() {
+                return StepOutcome::Error(format!("Program counter out of bounds: {:#x}", self.pc));
+            }
 
-                    return true; // done == true
+            let addr = self.pc;
+            let watched = if self.debugger.is_active() {
+                match self.debug_before() {
+                    Ok(watched) => Some(watched),
+                    Err(stop) => return self.debug_stop(stop),
                 }
-                // READ (breaks loop)
-                Opcode::VAR_228 => {
-                    let state = self.make_save_state(self.pc);
-                    self.send_save_message("savestate", &state);
+            } else {
+                None
+            };
+
+            self.instr_count += 1;
+
+            if let Some(outcome) = self.execute_recorded() {
+                return outcome;
+            }
+
+            if let Some(message) = self.fault.get_mut().take() {
+                // leave the pc on the instruction that faulted
+                self.pc = addr;
+                return StepOutcome::Error(message);
+            }
+
+            if let Some(stop) = watched.and_then(|watched| self.debug_after(watched, addr)) {
+                return self.debug_stop(stop);
+            }
+        }
+
+        StepOutcome::Yielded
+    }
+
+    /// Traces the last `capacity` instructions into `instr_log`, 0 turns
+    /// tracing off (guarded stories still keep a few for crash reports)
+    pub fn set_trace(&mut self, capacity: usize) {
+        self.options.trace = capacity;
+        self.instr_log.set_capacity(Self::trace_capacity(&self.options));
+    }
+
+    fn trace_capacity(options: &Options) -> usize {
+        if options.guarded {
+            options.trace.max(CRASH_HISTORY)
+        } else {
+            options.trace
+        }
+    }
+
+    // Faults are reported by run() after the current instruction, the first
+    // one wins
+    fn raise(&self, message: String) {
+        self.fault.borrow_mut().get_or_insert(message);
+    }
+
+    // Game writes go through these, illegal ones are a fault unless the
+    // memory_warnings option lets them through (never past the end)
+    fn illegal_write(&mut self, err: MemoryError) -> bool {
+        let allowed = self.options.memory_warnings && !matches!(err, MemoryError::OutOfBounds { .. });
+
+        if allowed {
+            // the write may land on cached code or text
+            self.instr_cache.clear();
+            self.text.clear();
+            self.ui.debug(&format!("[warning: {} at pc {:#x}]\n", err, self.pc));
+        } else {
+            self.raise(err.to_string());
+        }
+
+        allowed
+    }
+
+    fn store_byte(&mut self, addr: usize, value: u8) {
+        if let Err(err) = self.memory.store_byte(addr, value) {
+            if self.illegal_write(err) {
+                self.memory.write_byte(addr, value);
+            }
+        }
+
+        self.note_write(addr, 1);
+    }
+
+    fn store_word(&mut self, addr: usize, value: u16) {
+        if let Err(err) = self.memory.store_word(addr, value) {
+            if self.illegal_write(err) {
+                self.memory.write_word(addr, value);
+            }
+        }
+
+        self.note_write(addr, 2);
+    }
+
+    fn store(&mut self, addr: usize, bytes: &[u8]) {
+        if let Err(err) = self.memory.store(addr, bytes) {
+            if self.illegal_write(err) {
+                self.memory.write(addr, bytes);
+            }
+        }
+
+        self.note_write(addr, bytes.len());
+    }
+
+    // Print text is cached with its abbreviations expanded, in decoded
+    // instructions and in the string cache. A story that rewrites its
+    // abbreviation table or strings (they're in dynamic memory) gets both
+    // caches cleared
+    fn note_write(&mut self, addr: usize, length: usize) {
+        if addr < self.abbrev_span.end && addr + length > self.abbrev_span.start {
+            self.instr_cache.clear();
+            self.text.clear();
+            self.abbrev_span = self.text.abbreviation_span(&self.memory);
+        }
+    }
+
+    // Goes back to the state at the last prompt: the current one, or the one
+    // before the last command (the top undo)
+    fn recover(&mut self, message: String) -> Result<(), String> {
+        let snapshot = self.current_state.take().or_else(|| self.undos.pop());
+        let mut report = self.crash_report(message);
+
+        let (_, state) = match snapshot {
+            Some(snapshot) => snapshot,
+            None => {
+                self.ui.event(HostEvent::Crash(report.clone()));
+                return Err(report.message);
+            }
+        };
+
+        self.memory.take_journal();
+        self.restore_state(&state);
+        self.paused_instr = None;
+        self.fault.get_mut().take();
+        self.instr_log.clear();
+
+        report.recovered = true;
+        self.ui.print(&format!(
+            "\n[The interpreter ran into a problem ({}), so the story went back to \
+             before your last command.]\n",
+            report.message
+        ));
+        self.ui.event(HostEvent::Crash(report));
+
+        Ok(())
+    }
+
+    fn crash_report(&self, message: String) -> CrashReport {
+        // the faulting instruction is the last one run, unless the pc
+        // itself was bad
+        let opcode = self
+            .instr_log
+            .last(1)
+            .find(|entry| entry.instr.addr == self.pc)
+            .map(|entry| entry.instr.name.clone());
+
+        CrashReport {
+            message,
+            pc: self.pc,
+            opcode,
+            recent: self.instr_log.last(CRASH_HISTORY).map(|entry| entry.to_text()).collect(),
+            last_command: self.last_command.clone(),
+            recovered: false,
+        }
+    }
+
+    fn waiting_for(instr: &Instruction) -> StepOutcome {
+        match instr.opcode {
+            Opcode::OP0_182 => StepOutcome::NeedsRestoreData,
+            Opcode::VAR_246 => StepOutcome::NeedsChar,
+            _ => StepOutcome::NeedsLine,
+        }
+    }
+
+    // Runs the next instruction, noting what it changes when rewinding is on
+    // (faulty instructions too, so they can be stepped back over)
+    fn execute_recorded(&mut self) -> Option<StepOutcome> {
+        if !self.rewind.is_enabled() {
+            return self.execute_next();
+        }
 
-                    // web ui saves current state here BEFORE processing user input
-                    let (location, _) = self.get_status();
-                    self.current_state = Some((location, state));
-                    self.paused_instr = Some(instr);
+        self.rewind.begin(self.pc);
+        self.memory.start_journal();
+
+        let outcome = self.execute_next();
+        let memory = self.memory.take_journal();
+
+        match outcome {
+            Some(_) => self.rewind.discard(),
+            None => self.rewind.finish(memory),
+        }
 
-                    return false;
+        outcome
+    }
+
+    /// Keeps what the last `capacity` instructions changed, so the debugger
+    /// can go back over them, 0 turns it off
+    pub fn set_rewind(&mut self, capacity: usize) {
+        self.options.rewind = capacity;
+        self.rewind.set_capacity(capacity);
+    }
+
+    /// Goes back `count` instructions, returns how many it could
+    pub fn step_back(&mut self, count: usize) -> usize {
+        for done in 0..count {
+            match self.rewind.pop() {
+                Some(delta) => self.undo_instruction(delta),
+                None => return done,
+            }
+        }
+
+        count
+    }
+
+    fn undo_instruction(&mut self, delta: InstructionDelta) {
+        for (addr, old) in delta.memory.into_iter().rev() {
+            self.memory.write_byte(addr, old);
+            self.note_write(addr, 1);
+        }
+
+        for change in delta.frames.into_iter().rev() {
+            match change {
+                FrameChange::Local { index, old } => self.write_local(index, old),
+                FrameChange::StackPush => {
+                    self.stack_pop();
                 }
-                _ => {
-                    self.handle_instruction(&instr);
+                FrameChange::StackPop(value) => self.stack_push(value),
+                FrameChange::Call => {
+                    self.frames.pop();
                 }
+                FrameChange::Return(frame) => self.frames.push(frame),
             }
         }
+
+        if let Some(rng) = delta.rng {
+            self.rng = rng;
+        }
+
+        // back in the middle of a turn
+        self.pc = delta.pc;
+        self.paused_instr = None;
+        self.quit = false;
+    }
+
+    // Code in static and high memory can't change (story writes there fault,
+    // see Buffer::check_write) so it's decoded once, code in dynamic memory
+    // is decoded every time it runs. Print text is cached along with it,
+    // note_write clears it when the abbreviations change
+    fn fetch_instruction(&mut self, addr: usize) -> Result<Rc<Instruction>, DecodeError> {
+        if let Some(instr) = self.instr_cache.get(addr) {
+            return Ok(instr);
+        }
+
+        let instr = self.decode_instruction(addr)?;
+
+        if addr >= self.static_start {
+            Ok(self.instr_cache.insert(instr))
+        } else {
+            Ok(Rc::new(instr))
+        }
+    }
+
+    /// Instructions run since the story was loaded
+    pub fn instruction_count(&self) -> u64 {
+        self.instr_count
+    }
+
+    /// Lookups that found a decoded instruction in the cache, and ones that
+    /// didn't
+    pub fn instruction_cache_stats(&self) -> (usize, usize) {
+        self.instr_cache.stats()
+    }
+
+    // Runs one instruction, pausing on the ones that need the host
+    // (saves/restores need a save name, reads need user input)
+    fn execute_next(&mut self) -> Option<StepOutcome> {
+        // the pc can be anywhere after a bad jump or return
+        let instr = match self.fetch_instruction(self.pc) {
+            Ok(instr) => instr,
+            Err(err) => return Some(StepOutcome::Error(format!("{} (pc {:#x})", err, self.pc))),
+        };
+
+        if self.instr_log.is_enabled() {
+            self.instr_log.push(Rc::clone(&instr));
+        }
+
+        if self.profiler.is_enabled() {
+            self.profiler.record_instruction(self.frames.iter().map(|frame| frame.routine), &instr);
+        }
+
+        self.coverage.record_instruction(instr.addr);
+
+        match instr.opcode {
+            // SAVE
+            Opcode::OP0_181 => {
+                let pc = instr.next - 1;
+                let state = self.make_save_state(pc);
+                self.send_save_message(HostEvent::Save, &state);
+
+                // Advance the pc, assuming that the save was successful
+                self.process_result(&instr, 1);
+            }
+            // RESTORE (pauses)
+            Opcode::OP0_182 => {
+                self.ui.event(HostEvent::Restore);
+                self.paused_instr = Some(instr);
+
+                return Some(StepOutcome::NeedsRestoreData);
+            }
+            // QUIT (stops)
+            Opcode::OP0_186 => {
+                // undo 2x - get to the savestate right before the
+                // "are you sure?" dialog box that usually shows up:
+                self.undos.pop();
+
+                if let Some((_, state)) = self.undos.pop() {
+                    self.send_save_message(HostEvent::Savestate, &state);
+                }
+
+                self.quit = true;
+                return Some(StepOutcome::Quit);
+            }
+            // READ (pauses)
+            Opcode::VAR_228 => {
+                let state = self.make_save_state(self.pc);
+                self.send_save_message(HostEvent::Savestate, &state);
+
+                // web ui saves current state here BEFORE processing user input
+                let (location, _) = self.get_status();
+                self.current_state = Some((location, state));
+                self.paused_instr = Some(instr);
+
+                return Some(StepOutcome::NeedsLine);
+            }
+            // READ_CHAR (pauses, v4+)
+            Opcode::VAR_246 if self.version >= 4 => {
+                self.paused_instr = Some(instr);
+
+                return Some(StepOutcome::NeedsChar);
+            }
+            _ => {
+                self.handle_instruction(&instr);
+            }
+        }
+
+        None
     }
 
     // Web UI only - gives user input to the paused read instruction
     // (passes control back JS afterwards)
     #[allow(dead_code)]
     pub fn handle_input(&mut self, input: String) {
+        self.last_command = Some(input.clone());
+        self.rewind.clear();
+
+        if !self.options.guarded {
+            self.handle_input_unguarded(input);
+            return;
+        }
+
+        // the next run() reports it and rolls back
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| self.handle_input_unguarded(input))) {
+            self.raise(panic_message(payload));
+        }
+    }
+
+    fn handle_input_unguarded(&mut self, input: String) {
         let instr = self.paused_instr.take().expect(
             "Can't handle input, no paused instruction to resume",
         );
 
+        // read_char stores the key's zscii code, return for an empty line
+        if instr.opcode == Opcode::VAR_246 {
+            let key = input.chars().next().map_or(13, |chr| u16::from(self.to_zscii(chr)));
+            self.process_result(&instr, key);
+            return;
+        }
+
         // new input changes timelines, so remove any obsolete redos
         self.redos.clear();
 
@@ -1475,6 +1910,26 @@ impl Zmachine {
         self.pc = instr.next;
     }
 
+    // Input as ZSCII: ascii stays as it is, other characters come from the
+    // unicode table and anything the story can't show becomes '?'
+    fn to_zscii(&self, chr: char) -> u8 {
+        match chr {
+            '\n' | '\r' => return 13,
+            ' '..='~' => return chr as u8,
+            _ => (),
+        }
+
+        let table = self.header.extension.as_ref().map_or(0, |extension| extension.unicode_table());
+        let index = if table == 0 {
+            DEFAULT_UNICODE.chars().position(|unicode| unicode == chr)
+        } else {
+            let count = self.memory.try_read_byte(table).unwrap_or(0) as usize;
+            (0..count.min(97)).position(|index| self.memory.try_read_word(table + 1 + index * 2) == Ok(chr as u16))
+        };
+
+        index.map_or(b'?', |index| 155 + index as u8)
+    }
+
     fn process_hint(&mut self) {
         // Get contextual hint based on current room
         let (_, room_name) = self.get_current_room();
@@ -1489,24 +1944,40 @@ impl Zmachine {
         let state = BASE64.decode(&data);
 
         if data.is_empty() || state.is_err() {
-            let instr = self.paused_instr.take().unwrap();
-            self.process_result(&instr, 0);
+            self.fail_restore();
             return;
         }
 
         match SaveValidator::validate_and_extract(state.unwrap().as_slice(), &self.secret_key) {
             Ok(decrypted) => {
+                self.paused_instr = None;
                 self.restore_state(decrypted.as_slice());
                 self.process_restore_result();
             }
             Err(err) => {
                 self.ui.print(&format!("Save file validation failed: {}\n", err));
-                let instr = self.paused_instr.take().unwrap();
-                self.process_result(&instr, 0);
+                self.fail_restore();
             }
         }
     }
 
+    // A failed restore resumes the paused restore instruction with 0. Hosts
+    // can also restore before the game has started, with nothing to resume.
+    fn fail_restore(&mut self) {
+        if let Some(instr) = self.paused_instr.take() {
+            self.process_result(&instr, 0);
+        }
+    }
+
+    // True when step() stopped on a restore instruction and the host
+    // needs to call restore() with the save data (or an empty string)
+    pub fn is_awaiting_restore(&self) -> bool {
+        match self.paused_instr {
+            Some(ref instr) => instr.opcode == Opcode::OP0_182,
+            None => false,
+        }
+    }
+
     // Web UI only
     // Loads a saved state _without_ processing a restore result (like the above)
     #[allow(dead_code)]
@@ -1522,15 +1993,284 @@ impl Zmachine {
 
     // Web UI only
     #[allow(dead_code)]
-    fn send_save_message(&mut self, msg_type: &str, state: &[u8]) {
+    // `event` is HostEvent::Save or HostEvent::Savestate
+    fn send_save_message(&mut self, event: fn(String, String) -> HostEvent, state: &[u8]) {
         let secured = SaveValidator::add_security_info(state, &self.secret_key);
         let b64 = BASE64.encode(&secured);
 
         let (location, info) = self.get_status();
         let status = [&location, " - ", &info].concat();
 
-        let msg_body = serde_json::to_string(&(status, b64)).unwrap();
-        self.ui.message(msg_type, &msg_body);
+        self.ui.event(event(status, b64));
+    }
+}
+
+// Debugger
+impl Zmachine {
+    /// Runs a debugger command (see `DEBUG_HELP`) and returns what to show.
+    /// step/next/finish/continue only say where to stop next, the story goes
+    /// on with the next `run`.
+    pub fn debug_command(&mut self, line: &str) -> Result<String, String> {
+        let command = DebugCommand::parse(line)?;
+
+        match command {
+            DebugCommand::Break(breakpoint) => {
+                self.check_breakpoint(&breakpoint)?;
+                let text = format!("{}", breakpoint);
+                let id = self.debugger.add_breakpoint(breakpoint);
+                Ok(format!("Breakpoint {}: {}", id, text))
+            }
+            DebugCommand::Watch(watchpoint) => {
+                self.check_watchpoint(&watchpoint)?;
+                let text = format!("{}", watchpoint);
+                let id = self.debugger.add_watchpoint(watchpoint);
+                Ok(format!("Watchpoint {}: {}", id, text))
+            }
+            DebugCommand::Delete(id) => match self.debugger.remove(id) {
+                true => Ok(format!("Deleted {}", id)),
+                false => Err(format!("No breakpoint or watchpoint {}", id)),
+            },
+            DebugCommand::List => Ok(self.debugger.list()),
+            DebugCommand::Step => self.resume(Some(Stepping::Into)),
+            DebugCommand::Next => self.resume(Some(Stepping::Over(self.frames.len()))),
+            DebugCommand::Finish => self.resume(Some(Stepping::Out(self.frames.len()))),
+            DebugCommand::Continue => self.resume(None),
+            DebugCommand::Record(capacity) => {
+                self.set_rewind(capacity);
+                match capacity {
+                    0 => Ok("Not recording".to_string()),
+                    _ => Ok(format!("Recording the last {} instructions", capacity)),
+                }
+            }
+            DebugCommand::Back(count) => {
+                self.check_rewind()?;
+                let done = self.step_back(count);
+                self.debugger.stopped_at(self.pc);
+                Ok(format!("[Back {} instructions]\n{}", done, self.debug_location()))
+            }
+            DebugCommand::Reverse => {
+                self.check_rewind()?;
+                let stop = self.reverse_until_stop();
+                self.debugger.stopped_at(self.pc);
+                Ok(format!("[{}]\n{}", stop, self.debug_location()))
+            }
+            DebugCommand::Frame => Ok(self.debug_location()),
+            DebugCommand::Backtrace => Ok(self.backtrace()),
+            DebugCommand::Help => Ok(DEBUG_HELP.to_string()),
+        }
+    }
+
+    fn check_rewind(&self) -> Result<(), String> {
+        if !self.rewind.is_enabled() {
+            return Err("Not recording, start with record N".to_string());
+        }
+
+        if self.rewind.is_empty() {
+            return Err("Nothing recorded to go back over".to_string());
+        }
+
+        Ok(())
+    }
+
+    // Goes back until an instruction at a breakpoint or one that changed a
+    // watched value, or to the start of the recording
+    fn reverse_until_stop(&mut self) -> String {
+        let mut count = 0;
+
+        while !self.rewind.is_empty() {
+            let watched = self.watched_values();
+            self.step_back(1);
+            count += 1;
+
+            let changed = self
+                .debugger
+                .watchpoints()
+                .iter()
+                .zip(watched)
+                .find(|((_, watchpoint), new)| self.watch_value(watchpoint) != *new);
+
+            if let Some((&(id, _), _)) = changed {
+                return format!("Watchpoint {} changed at {:#x}, back {}", id, self.pc, count);
+            }
+
+            if let Some(id) = self.breakpoint_at(self.pc) {
+                return format!("Breakpoint {} at {:#x}, back {}", id, self.pc, count);
+            }
+        }
+
+        format!("Start of the recording, back {}", count)
+    }
+
+    fn resume(&mut self, stepping: Option<Stepping>) -> Result<String, String> {
+        self.debugger.set_stepping(stepping);
+        Ok(String::new())
+    }
+
+    /// The next instruction and the current frame's locals and stack
+    pub fn debug_location(&self) -> String {
+        let frame = self.frames.last().map(|frame| frame.to_string()).unwrap_or_default();
+        match self.decode_instruction(self.pc) {
+            Ok(instr) => format!("{}\n{}", instr, frame),
+            Err(err) => format!("{:5x}: [{}]\n{}", self.pc, err, frame),
+        }
+    }
+
+    /// Every frame's locals and stack, innermost first
+    pub fn backtrace(&self) -> String {
+        self.frames
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(depth, frame)| format!("#{} {}", depth, frame.to_string()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn check_address(&self, addr: usize) -> Result<(), String> {
+        if addr < self.memory.len() {
+            Ok(())
+        } else {
+            Err(format!("Address {:#x} is past the end of the story", addr))
+        }
+    }
+
+    fn check_object(&self, object: u16) -> Result<(), String> {
+        let count = self.get_total_object_count();
+
+        if object >= 1 && object <= count {
+            Ok(())
+        } else {
+            Err(format!("Objects are numbered 1 to {}, not {}", count, object))
+        }
+    }
+
+    fn check_breakpoint(&self, breakpoint: &Breakpoint) -> Result<(), String> {
+        match *breakpoint {
+            Breakpoint::Pc(addr) => self.check_address(addr),
+            Breakpoint::Routine(addr) => {
+                self.check_address(addr)?;
+
+                if self.story_byte(addr) > 15 {
+                    return Err(format!("No routine header at {:#x}", addr));
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn check_watchpoint(&self, watchpoint: &Watchpoint) -> Result<(), String> {
+        match *watchpoint {
+            Watchpoint::Global(_) => Ok(()),
+            Watchpoint::Memory { addr, len } => self.check_address(addr.saturating_add(len.max(1) - 1)),
+            Watchpoint::Attribute { object, attr } => {
+                self.check_object(object)?;
+
+                if attr as usize >= self.attr_width * 8 {
+                    return Err(format!("Attributes go from 0 to {}", self.attr_width * 8 - 1));
+                }
+
+                Ok(())
+            }
+            Watchpoint::Parent(object) => self.check_object(object),
+        }
+    }
+
+    // The first instruction of a routine, after its locals
+    fn routine_entry(&self, addr: usize) -> usize {
+        let count = self.story_byte(addr) as usize;
+
+        if self.version <= 4 {
+            addr + 1 + count * 2
+        } else {
+            addr + 1
+        }
+    }
+
+    fn watch_value(&self, watchpoint: &Watchpoint) -> Vec<u16> {
+        match *watchpoint {
+            Watchpoint::Global(index) => vec![self.read_global(index)],
+            Watchpoint::Memory { addr, len } => {
+                self.memory.slice(addr, addr + len).iter().map(|&byte| u16::from(byte)).collect()
+            }
+            Watchpoint::Attribute { object, attr } => vec![self.test_attr(object, attr)],
+            Watchpoint::Parent(object) => vec![self.get_parent(object)],
+        }
+    }
+
+    // Stops on a breakpoint at the pc, otherwise returns the watched values
+    // to compare once the instruction ran
+    fn debug_before(&mut self) -> Result<Vec<Vec<u16>>, DebugStop> {
+        let pc = self.pc;
+
+        if !self.debugger.take_resume(pc) {
+            if let Some(id) = self.breakpoint_at(pc) {
+                return Err(DebugStop::Breakpoint { id, pc });
+            }
+        }
+
+        Ok(self.watched_values())
+    }
+
+    fn breakpoint_at(&self, pc: usize) -> Option<usize> {
+        self.debugger
+            .breakpoints()
+            .iter()
+            .find(|(_, breakpoint)| match *breakpoint {
+                Breakpoint::Pc(addr) => addr == pc,
+                Breakpoint::Routine(addr) => self.routine_entry(addr) == pc,
+            })
+            .map(|&(id, _)| id)
+    }
+
+    fn watched_values(&self) -> Vec<Vec<u16>> {
+        self.debugger
+            .watchpoints()
+            .iter()
+            .map(|(_, watchpoint)| self.watch_value(watchpoint))
+            .collect()
+    }
+
+    // Watchpoints only see changes made by instructions, not by input or
+    // restores
+    fn debug_after(&self, watched: Vec<Vec<u16>>, changed_by: usize) -> Option<DebugStop> {
+        let pc = self.pc;
+
+        for (&(id, ref watchpoint), old) in self.debugger.watchpoints().iter().zip(watched) {
+            let new = self.watch_value(watchpoint);
+
+            if new != old {
+                return Some(DebugStop::Watchpoint {
+                    id,
+                    pc,
+                    changed_by,
+                    old,
+                    new,
+                });
+            }
+        }
+
+        match self.debugger.stepping() {
+            Some(Stepping::Into) => Some(DebugStop::Step { pc }),
+            Some(Stepping::Over(depth)) if self.frames.len() <= depth => Some(DebugStop::Step { pc }),
+            Some(Stepping::Out(depth)) if self.frames.len() < depth => Some(DebugStop::Step { pc }),
+            _ => None,
+        }
+    }
+
+    fn debug_stop(&mut self, stop: DebugStop) -> StepOutcome {
+        self.debugger.stopped_at(stop.pc());
+        StepOutcome::Break(stop)
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Interpreter panicked".to_string(),
+        },
     }
 }
 
@@ -1621,14 +2361,20 @@ impl Zmachine {
         let word_index = index.wrapping_mul(2);
         let word_addr = array_addr.wrapping_add(word_index);
 
-        self.memory.read_word(word_addr as usize)
+        self.memory.try_read_word(word_addr as usize).unwrap_or_else(|err| {
+            self.raise(err.to_string());
+            0
+        })
     }
 
     // OP2_16
     fn do_loadb(&self, array_addr: u16, index: u16) -> u16 {
         let byte_addr = array_addr.wrapping_add(index);
 
-        u16::from(self.memory.read_byte(byte_addr as usize))
+        self.memory.try_read_byte(byte_addr as usize).map(u16::from).unwrap_or_else(|err| {
+            self.raise(err.to_string());
+            0
+        })
     }
 
     // OP2_17
@@ -1663,12 +2409,22 @@ impl Zmachine {
 
     // OP2_23
     fn do_div(&self, a: u16, b: u16) -> u16 {
+        if b == 0 {
+            self.raise(String::from("Division by zero"));
+            return 0;
+        }
+
         (a as i16).wrapping_div(b as i16) as u16
     }
 
     // OP2_24
     fn do_mod(&self, a: u16, b: u16) -> u16 {
-        (a as i16 % b as i16) as u16
+        if b == 0 {
+            self.raise(String::from("Division by zero"));
+            return 0;
+        }
+
+        (a as i16).wrapping_rem(b as i16) as u16
     }
 
     // OP1_128
@@ -1714,7 +2470,8 @@ impl Zmachine {
 
     // OP1_135
     fn do_print_addr(&mut self, addr: u16) {
-        let zstring = self.read_zstring(addr as usize);
+        self.coverage.record_string(addr as usize);
+        let zstring = self.decode_zstring(addr as usize);
         self.ui.print(&zstring);
     }
 
@@ -1727,7 +2484,7 @@ impl Zmachine {
 
     // OP1_138
     fn do_print_obj(&mut self, obj: u16) {
-        let name = self.get_object_name(obj);
+        let name = self.object_name(obj);
         self.ui.print_object(&name);
     }
 
@@ -1748,7 +2505,8 @@ impl Zmachine {
     // OP1_141
     fn do_print_paddr(&mut self, addr: u16) {
         let paddr = self.unpack_print_paddr(addr);
-        let zstring = self.read_zstring(paddr);
+        self.coverage.record_string(paddr);
+        let zstring = self.decode_zstring(paddr);
         self.ui.print(&zstring);
 
         let location = zstring.to_lowercase();
@@ -1794,7 +2552,7 @@ impl Zmachine {
     // OP0_180 : nop, never actually used
 
     fn process_restore_result(&mut self) {
-        let byte = self.memory.read_byte(self.pc);
+        let byte = self.story_byte(self.pc);
 
         if self.version <= 3 {
             if byte & 0b1000_0000 != 0 {
@@ -1810,10 +2568,12 @@ impl Zmachine {
 
     // OP0_183
     fn do_restart(&mut self) {
+        self.rewind.clear();
         self.pc = self.initial_pc;
         self.frames.clear();
         self.frames.push(Frame::empty());
         self.memory.write(0, self.original_dynamic.as_slice());
+        self.note_write(0, self.static_start);
     }
 
     // OP0_184
@@ -1861,20 +2621,30 @@ impl Zmachine {
         let mut read = self.memory.get_reader(routine_addr);
 
         let mut locals = Vec::new();
-        let count = read.byte();
+        let count = match read.try_byte() {
+            Ok(count) => count,
+            Err(err) => return self.raise(err.to_string()),
+        };
 
         for _ in 0..count {
             match self.version {
-                1..=4 => locals.push(read.word()),
+                1..=4 => match read.try_word() {
+                    Ok(local) => locals.push(local),
+                    Err(err) => return self.raise(err.to_string()),
+                },
                 _ => locals.push(0),
             };
         }
 
         let first_instr = read.position();
-        let frame = Frame::new(instr.next, instr.store, locals, args);
+        let mut frame = Frame::new(instr.next, instr.store, locals, args);
+        frame.routine = routine_addr;
+        self.profiler.record_call(routine_addr);
+        self.coverage.record_call(routine_addr);
 
         self.pc = first_instr;
         self.frames.push(frame);
+        self.rewind.note(FrameChange::Call);
     }
 
     // VAR_225
@@ -1882,14 +2652,14 @@ impl Zmachine {
         let word_index = index.wrapping_mul(2);
         let word_addr = array_addr.wrapping_add(word_index);
 
-        self.memory.write_word(word_addr as usize, value);
+        self.store_word(word_addr as usize, value);
     }
 
     // VAR_226
     fn do_storeb(&mut self, array: u16, index: u16, value: u16) {
         let word_addr = array.wrapping_add(index);
 
-        self.memory.write_byte(word_addr as usize, value as u8);
+        self.store_byte(word_addr as usize, value as u8);
     }
 
     // VAR_227
@@ -1923,38 +2693,85 @@ impl Zmachine {
         self.current_state = Some((location, state));
     }
 
-    fn do_sread_second(&mut self, text_addr: u16, parse_addr: u16, mut raw: String) {
+    fn do_sread_second(&mut self, text_addr: u16, parse_addr: u16, raw: String) {
         let text_addr = text_addr as usize;
         let parse_addr = parse_addr as usize;
 
         // versions 1-4 have to store an extra 0, so the max length is 1 less
-        let mut max_length = self.memory.read_byte(text_addr as usize);
+        let mut max_length = self.story_byte(text_addr as usize);
         if self.version <= 4 {
-            max_length -= 1;
+            max_length = max_length.saturating_sub(1);
         }
 
-        raw.truncate(max_length as usize);
-        let input = &raw.to_lowercase();
+        let mut bytes: Vec<u8> = raw.to_lowercase().chars().map(|chr| self.to_zscii(chr)).collect();
+        bytes.truncate(max_length as usize);
 
-        let bytes = input.as_bytes();
+        let bytes = bytes.as_slice();
         let len = bytes.len();
 
         // ver 1-4 start storing @ byte 1, ending with a terminating 0
         // ver 5+ save the input length @1, start storing @2, and DON'T end with 0
         if self.version <= 4 {
-            self.memory.write(text_addr + 1, bytes);
-            self.memory.write_byte(text_addr + 1 + len, 0);
+            self.store(text_addr + 1, bytes);
+            self.store_byte(text_addr + 1 + len, 0);
         } else {
-            self.memory.write_byte(text_addr + 1, len as u8);
-            self.memory.write(text_addr + 2, bytes);
+            self.store_byte(text_addr + 1, len as u8);
+            self.store(text_addr + 2, bytes);
         }
 
         // skip tokenization step if parse_addr is 0
         if parse_addr != 0 {
-            self.tokenise(input, parse_addr);
+            let dictionary = self.dictionary.clone();
+            let start = if self.version <= 4 { 1 } else { 2 };
+
+            self.tokenise(bytes, start, parse_addr, &dictionary, false);
         }
     }
 
+    // VAR_251
+    fn do_tokenise(&mut self, text_addr: u16, parse_addr: u16, dictionary: u16, flag: u16) {
+        let text_addr = text_addr as usize;
+
+        // the text is as sread left it: 0 terminated in v1-4, after a
+        // length byte in v5+
+        let (start, text) = if self.version <= 4 {
+            let text: Vec<u8> = (text_addr + 1..)
+                .map_while(|addr| self.memory.try_read_byte(addr).ok())
+                .take_while(|&zscii| zscii != 0)
+                .collect();
+
+            (1, text)
+        } else {
+            let length = self.story_byte(text_addr + 1) as usize;
+
+            match self.memory.try_read(text_addr + 2, length) {
+                Ok(text) => (2, text.to_vec()),
+                Err(err) => return self.raise(err.to_string()),
+            }
+        };
+
+        let dictionary = match dictionary {
+            0 => self.dictionary.clone(),
+            addr => match Dictionary::read(&self.memory, addr as usize) {
+                Ok(dictionary) => dictionary,
+                Err(err) => return self.raise(err.to_string()),
+            },
+        };
+
+        self.tokenise(&text, start, parse_addr as usize, &dictionary, flag != 0);
+    }
+
+    // VAR_252
+    fn do_encode_text(&mut self, text_addr: u16, length: u16, from: u16, coded_addr: u16) {
+        let word = match self.memory.try_read(text_addr as usize + from as usize, length as usize) {
+            Ok(word) => word.to_vec(),
+            Err(err) => return self.raise(err.to_string()),
+        };
+
+        let encoded = encode_text(&word, self.version, self.text.alphabet());
+        self.store(coded_addr as usize, &encoded);
+    }
+
     // VAR_229
     fn do_print_char(&mut self, chr: u16) {
         self.ui.print(&(chr as u8 as char).to_string());
@@ -1968,6 +2785,7 @@ impl Zmachine {
     // VAR_231
     fn do_random(&mut self, range: u16) -> u16 {
         let range = range as i16;
+        self.rewind.note_rng(&self.rng);
 
         if range <= 0 {
             let mut seed = [0u8; 32];
@@ -2046,17 +2864,17 @@ impl Zmachine {
         let mut out = String::from("Properties:\n");
 
         let addr = self.get_object_prop_table_addr(obj_num);
-        let str_length = self.memory.read_byte(addr) as usize * 2; // words in name
+        let str_length = self.story_byte(addr) as usize * 2; // words in name
         let first_addr = addr + str_length + 1;
 
         let mut prop = self.read_object_prop(first_addr);
-        let mut slice = self.memory.read(prop.addr, prop.len as usize);
+        let mut slice = self.story_bytes(prop.addr, prop.len as usize);
 
         writeln!(out, "{:2} {:?}", prop.num, slice).unwrap();
 
         while prop.num != 0 {
             prop = self.read_object_prop(prop.next);
-            slice = self.memory.read(prop.addr, prop.len as usize);
+            slice = self.story_bytes(prop.addr, prop.len as usize);
 
             writeln!(out, "{:2} {:?}", prop.num, slice).unwrap();
         }
@@ -2074,6 +2892,29 @@ impl Zmachine {
         out
     }
 
+    /// An object's properties in table order: number, data address and data
+    pub(crate) fn object_properties(&self, obj_num: u16) -> Vec<(u8, usize, Vec<u8>)> {
+        let addr = self.get_object_prop_table_addr(obj_num);
+        let str_length = self.story_byte(addr) as usize * 2; // words in name
+
+        let mut properties = Vec::new();
+        let mut prop = self.read_object_prop(addr + str_length + 1);
+
+        while prop.num != 0 {
+            let data = self.story_bytes(prop.addr, prop.len as usize).to_vec();
+            properties.push((prop.num, prop.addr, data));
+            prop = self.read_object_prop(prop.next);
+        }
+
+        properties
+    }
+
+    pub(crate) fn object_attributes(&self, obj_num: u16) -> Vec<u16> {
+        (0..(self.attr_width * 8) as u16)
+            .filter(|&attr| self.test_attr(obj_num, attr) == 1)
+            .collect()
+    }
+
     pub fn debug_history(&mut self) {
         let undo_count = self.undos.len();
         let total = self.undos.len() + self.redos.len() + 1;
@@ -2096,6 +2937,16 @@ impl Zmachine {
         }
     }
 
+    /// Bytes held by the undo and redo states and the current one
+    pub fn history_size(&self) -> usize {
+        self.undos
+            .iter()
+            .chain(self.redos.iter())
+            .chain(self.current_state.iter())
+            .map(|(location, state)| location.len() + state.len())
+            .sum()
+    }
+
     pub fn get_save_state(&self) -> Option<String> {
         self.current_state.as_ref().map(|(_, state)| {
             BASE64.encode(&state)
@@ -0,0 +1,287 @@
+//! Z-string decoding (standard section 3) with char tables and a cache by
+//! address. Strings in static and high memory can't change, so they're
+//! decoded once. Strings in dynamic memory (mostly object names) keep their
+//! encoded bytes and are decoded again only if those changed.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::buffer::{Buffer, MemoryError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZStringError {
+    /// The string (or an abbreviation in it) runs past the end of memory
+    Memory(MemoryError),
+    BadAbbreviation(u8),
+    /// The abbreviation at this address uses another abbreviation
+    NestedAbbreviation(usize),
+}
+
+impl fmt::Display for ZStringError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ZStringError::Memory(ref err) => write!(f, "{}", err),
+            ZStringError::BadAbbreviation(index) => write!(f, "Bad abbreviation index: {}", index),
+            ZStringError::NestedAbbreviation(addr) => {
+                write!(f, "Abbreviation at {:#x} contains another abbreviation", addr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ZStringError {}
+
+impl From<MemoryError> for ZStringError {
+    fn from(err: MemoryError) -> ZStringError {
+        ZStringError::Memory(err)
+    }
+}
+
+/// Alphabet rows A0, A1 and A2 indexed by Z-character. 0-5 are never
+/// looked up (space, abbreviations and shifts), neither is A2's escape at 6
+pub type Alphabet = [[char; 32]; 3];
+
+fn alphabet_row(letters: &[u8], first: usize) -> [char; 32] {
+    let mut row = [' '; 32];
+
+    for (index, &zscii) in letters.iter().enumerate() {
+        row[first + index] = zscii as char;
+    }
+
+    row
+}
+
+/// The standard's alphabet table for v2 and later
+pub fn default_alphabet() -> Alphabet {
+    [
+        alphabet_row(b"abcdefghijklmnopqrstuvwxyz", 6),
+        alphabet_row(b"ABCDEFGHIJKLMNOPQRSTUVWXYZ", 6),
+        alphabet_row(b"\n0123456789.,!?_#'\"/\\-:()", 7),
+    ]
+}
+
+/// A v5+ story's own alphabet table, 78 bytes of ZSCII. A2's first two
+/// entries are ignored: the escape and newline can't be changed
+pub fn load_alphabet(memory: &Buffer, addr: usize) -> Alphabet {
+    let mut alphabet = [
+        alphabet_row(memory.read(addr, 26), 6),
+        alphabet_row(memory.read(addr + 26, 26), 6),
+        alphabet_row(memory.read(addr + 26 + 26 + 2, 24), 8),
+    ];
+
+    alphabet[2][7] = '\n';
+    alphabet
+}
+
+/// How many bytes the Z-string at `addr` takes, up to the word with the
+/// stop bit
+pub fn encoded_length(memory: &Buffer, addr: usize) -> Result<usize, MemoryError> {
+    let mut length = 0;
+
+    loop {
+        let word = memory.try_read_word(addr + length)?;
+        length += 2;
+
+        if word & 0x8000 != 0 {
+            return Ok(length);
+        }
+    }
+}
+
+enum State {
+    Alphabet(usize),
+    Abbrev(u8),
+    Tenbit1,
+    Tenbit2(u8),
+}
+
+#[derive(Debug)]
+struct CachedString {
+    text: Rc<str>,
+    // the encoded bytes, kept for strings in dynamic memory only
+    encoded: Option<Box<[u8]>>,
+}
+
+#[derive(Debug)]
+pub struct ZStringDecoder {
+    alphabet: Alphabet,
+    abbrev_table: usize,
+    static_start: usize,
+    cache: RefCell<HashMap<usize, CachedString>>,
+    // decoding happens here, only finished strings are copied out
+    buffer: RefCell<String>,
+}
+
+impl ZStringDecoder {
+    pub fn new(alphabet: Alphabet, abbrev_table: usize, static_start: usize) -> ZStringDecoder {
+        ZStringDecoder {
+            alphabet,
+            abbrev_table,
+            static_start,
+            cache: RefCell::new(HashMap::new()),
+            buffer: RefCell::new(String::new()),
+        }
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.alphabet
+    }
+
+    /// Forgets every decoded string, for when static memory was written
+    pub fn clear(&self) {
+        self.cache.borrow_mut().clear();
+    }
+
+    /// Strings decoded so far
+    pub fn len(&self) -> usize {
+        self.cache.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.borrow().is_empty()
+    }
+
+    /// The dynamic memory abbreviations are read from: the table and the
+    /// strings it points to below static memory. Strings are cached with
+    /// their abbreviations expanded, so a write here has to clear the cache
+    pub fn abbreviation_span(&self, memory: &Buffer) -> Range<usize> {
+        // v1 has no abbreviations
+        if self.abbrev_table == 0 {
+            return 0..0;
+        }
+
+        let mut span = self.abbrev_table..self.abbrev_table + 96 * 2;
+
+        for index in 0..96 {
+            let addr = match memory.try_read_word(self.abbrev_table + 2 * index) {
+                Ok(word) => word as usize * 2,
+                Err(_) => continue,
+            };
+
+            if addr >= self.static_start {
+                continue;
+            }
+
+            if let Ok(length) = encoded_length(memory, addr) {
+                span.start = span.start.min(addr);
+                span.end = span.end.max(addr + length);
+            }
+        }
+
+        span
+    }
+
+    /// The text of the Z-string at `addr`
+    pub fn decode(&self, memory: &Buffer, addr: usize) -> Result<Rc<str>, ZStringError> {
+        self.cached(memory, addr, true)
+    }
+
+    /// Abbreviation `index` (0-95), which can't contain abbreviations itself
+    pub fn abbreviation(&self, memory: &Buffer, index: u8) -> Result<Rc<str>, ZStringError> {
+        if index >= 96 {
+            return Err(ZStringError::BadAbbreviation(index));
+        }
+
+        // "Word addresses are used only in the abbreviations table" - 1.2.2
+        let addr = memory.try_read_word(self.abbrev_table + 2 * index as usize)? as usize * 2;
+
+        self.cached(memory, addr, false)
+    }
+
+    fn cached(&self, memory: &Buffer, addr: usize, allow_abbrevs: bool) -> Result<Rc<str>, ZStringError> {
+        if let Some(entry) = self.cache.borrow().get(&addr) {
+            let unchanged = match entry.encoded {
+                Some(ref encoded) => memory.try_read(addr, encoded.len()).is_ok_and(|bytes| bytes == &encoded[..]),
+                None => true,
+            };
+
+            if unchanged {
+                return Ok(Rc::clone(&entry.text));
+            }
+        }
+
+        // abbreviations are decoded in the middle of another string, so
+        // they get their own buffer (once, they're cached after)
+        let text: Rc<str> = if allow_abbrevs {
+            let mut buffer = self.buffer.borrow_mut();
+            buffer.clear();
+            self.decode_into(memory, addr, true, &mut buffer)?;
+            Rc::from(buffer.as_str())
+        } else {
+            let mut text = String::new();
+            self.decode_into(memory, addr, false, &mut text)?;
+            Rc::from(text)
+        };
+
+        let encoded = if addr < self.static_start {
+            let length = encoded_length(memory, addr)?;
+            Some(memory.try_read(addr, length)?.into())
+        } else {
+            None
+        };
+
+        self.cache.borrow_mut().insert(
+            addr,
+            CachedString {
+                text: Rc::clone(&text),
+                encoded,
+            },
+        );
+
+        Ok(text)
+    }
+
+    fn decode_into(&self, memory: &Buffer, addr: usize, allow_abbrevs: bool, text: &mut String) -> Result<(), ZStringError> {
+        let mut state = State::Alphabet(0);
+        let mut index = addr;
+
+        // 3 zchars per each 16 bit word + a "stop" bit on top
+        // 0 10101 01010 10101
+        loop {
+            let word = memory.try_read_word(index)?;
+            index += 2;
+
+            for zchar in [(word >> 10) as u8 & 0b0001_1111, (word >> 5) as u8 & 0b0001_1111, word as u8 & 0b0001_1111] {
+                state = match (zchar, state) {
+                    // the next zchar will be an abbrev index
+                    (1..=3, State::Alphabet(_)) if !allow_abbrevs => {
+                        return Err(ZStringError::NestedAbbreviation(addr));
+                    }
+                    (1..=3, State::Alphabet(_)) => State::Abbrev(zchar),
+                    (0, State::Alphabet(_)) => {
+                        text.push(' ');
+                        State::Alphabet(0)
+                    }
+                    // shift character for the next zchar
+                    (4, State::Alphabet(_)) => State::Alphabet(1),
+                    (5, State::Alphabet(_)) => State::Alphabet(2),
+                    // special 10bit case, next 2 zchars = one 10bit zscii char
+                    (6, State::Alphabet(2)) => State::Tenbit1,
+                    (_, State::Tenbit1) => State::Tenbit2(zchar),
+                    (_, State::Tenbit2(first)) => {
+                        let zscii = u32::from(first) << 5 | u32::from(zchar);
+                        text.push(char::from_u32(zscii).unwrap_or('?'));
+                        State::Alphabet(0)
+                    }
+                    (_, State::Abbrev(num)) => {
+                        text.push_str(&self.abbreviation(memory, (num - 1) * 32 + zchar)?);
+                        State::Alphabet(0)
+                    }
+                    // normal case, adds letter from correct alphabet and resets to A0
+                    (_, State::Alphabet(num)) => {
+                        text.push(self.alphabet[num][zchar as usize]);
+                        State::Alphabet(0)
+                    }
+                };
+            }
+
+            // stop bit
+            if word & 0x8000 != 0 {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use encrusted::bench::{run_script, BenchReport, CountingAllocator, Script, SCRIPTS};
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator;
+
+#[test]
+fn scripts_run_to_the_end_with_a_report() {
+    for script in SCRIPTS.iter() {
+        let report = run_script(script, 1);
+
+        assert_eq!(report.turns, script.commands.len() + 1, "{}", script.name);
+        assert!(report.instructions > 0);
+        assert!(report.instructions_per_second > 0.0);
+        assert!(report.allocations_per_turn > 0.0);
+        assert!(report.peak_history_bytes > 0);
+        assert!(report.total_ms >= report.step_ms + report.object_tree_ms + report.flush_ms);
+    }
+}
+
+#[test]
+fn runs_add_up() {
+    let script = Script::find("wait").unwrap();
+    let once = run_script(&script, 1);
+    let twice = run_script(&script, 2);
+
+    assert_eq!(twice.runs, 2);
+    assert_eq!(twice.turns, once.turns * 2);
+    // the same seed runs the same instructions
+    assert_eq!(twice.instructions, once.instructions * 2);
+    assert_eq!(twice.peak_history_bytes, once.peak_history_bytes);
+
+    let table = BenchReport::table(&[once, twice]);
+    assert_eq!(table.lines().count(), 3);
+    assert!(table.lines().nth(1).unwrap().starts_with("wait"));
+}
+
+#[test]
+fn unknown_scripts_are_none() {
+    assert!(Script::find("speedrun").is_none());
+}
@@ -1,7 +1,32 @@
-use encrusted::UI;
+// Each test crate uses a different part of this
+#![allow(dead_code)]
+
+use encrusted::{Game, Options, VecSink, WebUI, Zmachine, UI};
 use std::boxed::Box;
 use std::cell::RefCell;
 
+/// Loads `data` with a WebUI, the sink gets the messages it sends
+pub fn load_story(data: Vec<u8>, options: Options) -> (Zmachine, VecSink) {
+    let sink = VecSink::new();
+    let ui = WebUI::with_sink(Box::new(sink.clone()));
+
+    (Game::load_story(data, ui, options), sink)
+}
+
+/// The bundled h2g2, for tests that don't look at the output
+pub fn h2g2(options: Options) -> Zmachine {
+    load_story(Game::story_data().to_vec(), options).0
+}
+
+/// h2g2 with `code` written over the instructions at the initial pc
+pub fn patched_h2g2(code: &[u8]) -> Vec<u8> {
+    let mut data = Game::story_data().to_vec();
+    let pc = u16::from_be_bytes([data[0x06], data[0x07]]) as usize;
+    data[pc..pc + code.len()].copy_from_slice(code);
+
+    data
+}
+
 /// Mock UI for testing that doesn't require JavaScript bindings
 pub struct MockUI;
 
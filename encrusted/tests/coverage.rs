@@ -0,0 +1,102 @@
+mod common;
+
+use encrusted::{disassemble, Coverage, CoverageReport, Options, Zmachine};
+
+fn load(coverage: bool) -> Zmachine {
+    let mut options = Options::default();
+    options.coverage = coverage;
+
+    common::h2g2(options)
+}
+
+fn play(commands: &[&str]) -> Zmachine {
+    let mut zvm = load(true);
+    zvm.run(usize::MAX);
+
+    for command in commands {
+        zvm.handle_input(command.to_string());
+        zvm.run(usize::MAX);
+    }
+
+    zvm
+}
+
+fn totals(report: &CoverageReport) -> (usize, usize) {
+    let reached = report.routines.iter().map(|routine| routine.instructions_reached).sum();
+    let branches = report.routines.iter().map(|routine| routine.branches_reached).sum();
+    (reached, branches)
+}
+
+#[test]
+fn coverage_is_off_by_default() {
+    let mut zvm = load(false);
+    zvm.run(usize::MAX);
+
+    assert!(zvm.coverage.is_empty());
+    assert!(!zvm.coverage.is_enabled());
+}
+
+#[test]
+fn reached_code_lines_up_with_the_disassembly() {
+    let zvm = play(&["turn on light", "get up"]);
+    let report = CoverageReport::new(&disassemble(&zvm), &zvm.coverage);
+
+    // h2g2's main routine starts at the initial pc, after its header
+    let main = report.routines.iter().find(|routine| routine.main).unwrap();
+    assert_eq!(main.addr, 0x50fc);
+    assert!(main.reached);
+    assert!(zvm.coverage.reached_instruction(0x50fd));
+
+    // the opening text is printed by main's first print
+    assert_eq!(main.prints_reached, main.prints);
+    assert!(main.unreached_prints.is_empty());
+
+    for routine in &report.routines {
+        assert!(routine.instructions_reached <= routine.instructions);
+        assert!(routine.branches_reached <= routine.branches);
+        assert_eq!(routine.unreached_prints.len(), routine.prints - routine.prints_reached);
+        assert_eq!(routine.reached, routine.instructions_reached > 0, "{:05x}", routine.addr);
+    }
+
+    assert!(report.routines.iter().any(|routine| !routine.reached));
+
+    let text = report.to_text();
+    assert!(text.starts_with(&format!("; coverage of story {}", zvm.coverage.story())));
+    assert!(text.contains("Main routine 050fc: "));
+    assert!(text.contains(": not reached"));
+}
+
+#[test]
+fn sessions_merge() {
+    let short = play(&["turn on light"]);
+    let long = play(&["turn on light", "get up", "get gown", "wear gown", "open pocket", "take pill"]);
+    let disassembly = disassemble(&short);
+
+    let mut merged = Coverage::from_json(&short.coverage.to_json()).unwrap();
+    merged.merge(&long.coverage).unwrap();
+
+    let (short_reached, short_branches) = totals(&CoverageReport::new(&disassembly, &short.coverage));
+    let (long_reached, _) = totals(&CoverageReport::new(&disassembly, &long.coverage));
+    let (merged_reached, merged_branches) = totals(&CoverageReport::new(&disassembly, &merged));
+
+    assert!(long_reached > short_reached);
+    assert!(merged_reached >= long_reached);
+    assert!(merged_branches >= short_branches);
+
+    // merging is idempotent
+    let mut again = merged.clone();
+    again.merge(&short.coverage).unwrap();
+    assert_eq!(again, merged);
+}
+
+#[test]
+fn other_stories_dont_merge() {
+    let zvm = play(&[]);
+
+    let json = zvm.coverage.to_json().replace(zvm.coverage.story(), "1.000000.0000");
+    let other = Coverage::from_json(&json).unwrap();
+
+    let mut coverage = zvm.coverage.clone();
+    assert!(coverage.merge(&other).is_err());
+    assert!(Coverage::from_json("{").is_err());
+}
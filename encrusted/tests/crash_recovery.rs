@@ -0,0 +1,121 @@
+mod common;
+
+use encrusted::{CrashReport, HostEvent, Options, StepOutcome, VecSink, Zmachine};
+
+fn guarded_game(data: Vec<u8>) -> (Zmachine, VecSink) {
+    let mut opts = Options::default();
+    opts.guarded = true;
+
+    common::load_story(data, opts)
+}
+
+fn crash_reports(sink: &VecSink) -> Vec<CrashReport> {
+    sink.take()
+        .into_iter()
+        .filter_map(|(mtype, msg)| match HostEvent::from_message(&mtype, &msg) {
+            Some(HostEvent::Crash(report)) => Some(report),
+            _ => None,
+        })
+        .collect()
+}
+
+// Reads a line into a buffer at 0x2000 and loops, unless the line starts
+// with x: then it prints the string at packed address 0xffff, which is past
+// the end of the story
+const X_FAULTS: [u8; 35] = [
+    0xe2, 0x17, 0x20, 0x00, 0x00, 0x14, // storeb 0x2000 0 20
+    0xe2, 0x17, 0x21, 0x00, 0x00, 0x05, // storeb 0x2100 0 5
+    0xe4, 0x0f, 0x20, 0x00, 0x21, 0x00, // loop: sread 0x2000 0x2100
+    0xd0, 0x1f, 0x20, 0x00, 0x01, 0x00, // loadb 0x2000 1 -> sp
+    0x41, 0x00, 0x78, 0xc6, // je sp 'x' ?fault
+    0xbb, // new_line
+    0x8c, 0xff, 0xee, // jump loop
+    0x8d, 0xff, 0xff, // fault: print_paddr 0xffff
+];
+
+#[test]
+fn faults_roll_back_to_the_last_prompt() {
+    let (mut zvm, sink) = guarded_game(common::patched_h2g2(&X_FAULTS));
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    sink.take();
+
+    zvm.handle_input("xyzzy".to_string());
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    zvm.ui.flush();
+
+    let reports = crash_reports(&sink);
+    assert_eq!(reports.len(), 1);
+    assert!(reports[0].recovered);
+    assert!(reports[0].message.contains("past the end of memory"), "{}", reports[0].message);
+    assert_eq!(reports[0].opcode.as_deref(), Some("print_paddr"));
+    assert_eq!(reports[0].last_command.as_deref(), Some("xyzzy"));
+
+    // and the story goes on from there
+    zvm.handle_input("look".to_string());
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    zvm.ui.flush();
+    assert!(crash_reports(&sink).is_empty());
+}
+
+#[test]
+fn stack_underflows_and_missing_locals_are_faults() {
+    let stories: [(&[u8], &str); 3] = [
+        // add sp 1 -> sp, with nothing on the stack
+        (&[0x54, 0x00, 0x01, 0x00], "empty stack"),
+        // inc local 1, the main routine has no locals
+        (&[0x95, 0x01], "local 1"),
+        // ret 0
+        (&[0x9b, 0x00], "main routine"),
+    ];
+
+    for (code, message) in stories {
+        let (mut zvm, _) = guarded_game(common::patched_h2g2(code));
+
+        match zvm.run(usize::MAX) {
+            StepOutcome::Error(error) => assert!(error.contains(message), "{}", error),
+            outcome => panic!("Expected an error, got {:?}", outcome),
+        }
+    }
+}
+
+#[test]
+fn faults_before_the_first_prompt_are_errors_with_a_report() {
+    // put a variable-form opcode 247 (no operands) at the start of the story
+    let (mut zvm, sink) = guarded_game(common::patched_h2g2(&[0xf7, 0xff]));
+    let pc = zvm.header().initial_pc;
+
+    match zvm.run(usize::MAX) {
+        StepOutcome::Error(message) => assert!(message.contains("not yet implemented")),
+        outcome => panic!("Expected an error, got {:?}", outcome),
+    }
+
+    let reports = crash_reports(&sink);
+    assert_eq!(reports.len(), 1);
+    assert!(!reports[0].recovered, "There is no earlier state to go back to");
+    assert_eq!(reports[0].pc, pc);
+    assert_eq!(reports[0].opcode.as_deref(), Some("scan_table"));
+    assert!(reports[0].recent.last().unwrap().contains("scan_table"));
+    assert_eq!(reports[0].last_command, None);
+}
+
+#[test]
+fn division_by_zero_is_a_fault() {
+    let stories: [(&[u8], &str); 2] = [
+        // div 7 0 -> sp
+        (&[0x17, 0x07, 0x00, 0x00], "div"),
+        // mod 7 0 -> sp
+        (&[0x18, 0x07, 0x00, 0x00], "mod"),
+    ];
+
+    for (code, opcode) in stories {
+        let (mut zvm, sink) = guarded_game(common::patched_h2g2(code));
+
+        match zvm.run(usize::MAX) {
+            StepOutcome::Error(error) => assert_eq!(error, "Division by zero"),
+            outcome => panic!("Expected an error, got {:?}", outcome),
+        }
+
+        zvm.ui.flush();
+        assert_eq!(crash_reports(&sink)[0].opcode.as_deref(), Some(opcode));
+    }
+}
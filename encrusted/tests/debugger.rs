@@ -0,0 +1,147 @@
+mod common;
+
+use encrusted::{DebugCommand, DebugStop, Options, StepOutcome, Zmachine};
+
+fn game() -> Zmachine {
+    common::h2g2(Options::default())
+}
+
+fn command(zvm: &mut Zmachine, line: &str) -> String {
+    zvm.debug_command(line).unwrap()
+}
+
+fn stop(outcome: StepOutcome) -> DebugStop {
+    match outcome {
+        StepOutcome::Break(stop) => stop,
+        other => panic!("Expected a debugger stop, got {:?}", other),
+    }
+}
+
+#[test]
+fn breakpoints_stop_before_the_instruction_once() {
+    let mut zvm = game();
+    assert_eq!(command(&mut zvm, "break 0x5102"), "Breakpoint 1: pc 0x5102");
+
+    assert_eq!(stop(zvm.run(usize::MAX)), DebugStop::Breakpoint { id: 1, pc: 0x5102 });
+    assert!(zvm.debug_location().starts_with(" 5102: call"));
+
+    // continuing from the breakpoint doesn't stop on it again
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+}
+
+#[test]
+fn step_runs_past_breakpoints() {
+    let mut zvm = game();
+    command(&mut zvm, "break 0x5102");
+
+    // step() hosts can't show a stop, they get the prompt as usual
+    assert!(!zvm.step());
+    zvm.handle_input(String::from("wait"));
+    assert!(!zvm.step());
+}
+
+#[test]
+fn routine_breakpoints_stop_on_entry() {
+    let mut zvm = game();
+
+    // the first call at 0x5102 is to packed address 2b96
+    command(&mut zvm, "break routine 0x572c");
+    let pc = stop(zvm.run(usize::MAX)).pc();
+
+    assert!(pc > 0x572c);
+    assert_eq!(zvm.backtrace().lines().count(), 2);
+    assert!(zvm.backtrace().ends_with("-> None @ 0000"));
+}
+
+#[test]
+fn step_next_and_finish_follow_frames() {
+    let mut zvm = game();
+    command(&mut zvm, "break 0x5102");
+    zvm.run(usize::MAX);
+
+    // step goes into the call, finish comes back out
+    command(&mut zvm, "step");
+    assert_ne!(stop(zvm.run(usize::MAX)).pc(), 0x510a);
+    assert_eq!(zvm.backtrace().lines().count(), 2);
+
+    command(&mut zvm, "finish");
+    assert_eq!(stop(zvm.run(usize::MAX)), DebugStop::Step { pc: 0x510a });
+    assert_eq!(zvm.backtrace().lines().count(), 1);
+
+    // next runs the whole call
+    let mut zvm = game();
+    command(&mut zvm, "break 0x5102");
+    zvm.run(usize::MAX);
+    command(&mut zvm, "next");
+    assert_eq!(stop(zvm.run(usize::MAX)), DebugStop::Step { pc: 0x510a });
+    assert!(zvm.debug_location().ends_with("Stack: [2256] -> None @ 0000"));
+
+    // and continue lets the story go on
+    command(&mut zvm, "continue");
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+}
+
+#[test]
+fn watchpoints_report_the_change() {
+    let mut zvm = game();
+    command(&mut zvm, "watch global 0");
+
+    match stop(zvm.run(usize::MAX)) {
+        DebugStop::Watchpoint { id, changed_by, old, new, .. } => {
+            assert_eq!(id, 1);
+            assert_eq!(changed_by, 0x512f);
+            assert_eq!(old, vec![0]);
+            assert_ne!(new, vec![0]);
+        }
+        other => panic!("Expected a watchpoint, got {:?}", other),
+    }
+
+    command(&mut zvm, "delete 1");
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+
+    // the player (187) gets out of bed (108)
+    command(&mut zvm, "watch parent 187");
+    zvm.handle_input("get up".to_string());
+
+    match stop(zvm.run(usize::MAX)) {
+        DebugStop::Watchpoint { old, new, .. } => {
+            assert_eq!(old, vec![108]);
+            assert_ne!(new, vec![108]);
+        }
+        other => panic!("Expected a watchpoint, got {:?}", other),
+    }
+}
+
+#[test]
+fn bad_commands_are_errors() {
+    let mut zvm = game();
+
+    assert!(zvm.debug_command("jump 0x100").is_err());
+    assert!(zvm.debug_command("break").is_err());
+    assert!(zvm.debug_command("break 0x5102 extra").is_err());
+    assert!(zvm.debug_command("watch global 240").is_err());
+    assert!(zvm.debug_command("watch attr 0 1").is_err());
+    assert!(zvm.debug_command("watch memory 0xfffff 4").is_err());
+    assert!(zvm.debug_command("delete 7").is_err());
+
+    assert_eq!(command(&mut zvm, "list"), "No breakpoints or watchpoints");
+    assert!(!zvm.debugger.is_active());
+}
+
+#[test]
+fn commands_parse_with_short_names() {
+    assert_eq!(DebugCommand::parse("c"), Ok(DebugCommand::Continue));
+    assert_eq!(DebugCommand::parse("bt"), Ok(DebugCommand::Backtrace));
+    assert!(DebugCommand::parse("n").unwrap().resumes());
+    assert!(!DebugCommand::parse("list").unwrap().resumes());
+}
+
+#[test]
+fn stops_serialize_for_hosts() {
+    let outcome = StepOutcome::Break(DebugStop::Breakpoint { id: 1, pc: 0x5102 });
+
+    assert_eq!(
+        serde_json::to_string(&outcome).unwrap(),
+        r#"{"outcome":"break","message":{"reason":"breakpoint","id":1,"pc":20738}}"#
+    );
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use encrusted::zstring::default_alphabet;
+use encrusted::{encode_text, inspect, Buffer, Dictionary, Game, Options, StoryHeader};
+
+fn story_dictionary() -> (Buffer, Dictionary) {
+    let data = Game::story_data().to_vec();
+    let header = StoryHeader::parse(&data).unwrap();
+    let memory = Buffer::new(data);
+    let dictionary = Dictionary::read(&memory, header.dictionary).unwrap();
+
+    (memory, dictionary)
+}
+
+#[test]
+fn words_are_encoded_to_dictionary_resolution() {
+    let alphabet = default_alphabet();
+
+    // a, then padding 5s, with the stop bit on the last word
+    assert_eq!(encode_text(b"a", 3, &alphabet), vec![0x18, 0xa5, 0x94, 0xa5]);
+    // only the first 6 Z-characters count in v3, 9 after
+    assert_eq!(encode_text(b"abcdefgh", 3, &alphabet), encode_text(b"abcdef", 3, &alphabet));
+    assert_eq!(encode_text(b"abcdefghi", 5, &alphabet).len(), 6);
+    assert_ne!(encode_text(b"abcdefghi", 5, &alphabet), encode_text(b"abcdefgh", 5, &alphabet));
+    // a comma is A2 with a shift, @ isn't in any alphabet and is escaped
+    assert_eq!(encode_text(b",", 3, &alphabet), vec![0x16, 0x65, 0x94, 0xa5]);
+    assert_eq!(encode_text(b"@", 3, &alphabet), vec![0x14, 0xc2, 0x80, 0xa5]);
+}
+
+#[test]
+fn every_story_word_encodes_to_its_own_entry() {
+    let zvm = common::h2g2(Options::default());
+    let info = inspect(&zvm).unwrap();
+    let (memory, dictionary) = story_dictionary();
+
+    assert!(dictionary.sorted);
+    assert_eq!(dictionary.entry_count, 971);
+
+    for entry in &info.dictionary.entries {
+        let encoded = encode_text(entry.word.as_bytes(), 3, &default_alphabet());
+
+        assert_eq!(memory.read(entry.addr, 4), &encoded[..], "{}", entry.word);
+        assert_eq!(dictionary.lookup(&memory, &encoded), Ok(entry.addr), "{}", entry.word);
+    }
+
+    let missing = encode_text(b"xyzzyx", 3, &default_alphabet());
+    assert_eq!(dictionary.lookup(&memory, &missing), Ok(0));
+}
+
+#[test]
+fn user_tables_with_a_negative_count_are_searched_in_order() {
+    let alphabet = default_alphabet();
+    let words: [&[u8]; 3] = [b"zebra", b"apple", b"mango"];
+
+    // no separators, 4 byte entries, -3 entries
+    let mut data = vec![0, 4, 0xff, 0xfd];
+    for word in &words {
+        data.extend(encode_text(word, 3, &alphabet));
+    }
+
+    let memory = Buffer::new(data);
+    let dictionary = Dictionary::read(&memory, 0).unwrap();
+
+    assert!(!dictionary.sorted);
+    assert_eq!(dictionary.entry_count, 3);
+    assert_eq!(dictionary.lookup(&memory, &encode_text(b"apple", 3, &alphabet)), Ok(8));
+    assert_eq!(dictionary.lookup(&memory, &encode_text(b"mango", 3, &alphabet)), Ok(12));
+    assert_eq!(dictionary.lookup(&memory, &encode_text(b"pear", 3, &alphabet)), Ok(0));
+}
+
+#[test]
+fn truncated_user_tables_are_errors() {
+    let memory = Buffer::new(vec![0, 4, 0xff, 0xfd, 0x18]);
+    let dictionary = Dictionary::read(&memory, 0).unwrap();
+    let encoded = encode_text(b"a", 3, &default_alphabet());
+
+    assert!(dictionary.lookup(&memory, &encoded).is_err());
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use encrusted::disassembler::{Disassembly, RegionKind};
+use encrusted::{disassemble, Options};
+
+fn disassembly() -> Disassembly {
+    disassemble(&common::h2g2(Options::default()))
+}
+
+#[test]
+fn main_routine_starts_at_the_initial_pc() {
+    let disassembly = disassembly();
+    assert_eq!(disassembly.version, 3);
+    assert_eq!(disassembly.initial_pc, 0x50fd);
+
+    // h2g2 has a 0 locals header right before the initial pc
+    let main = disassembly.routine_at(0x50fc).unwrap();
+    assert!(main.main);
+    assert!(main.locals.is_empty());
+    assert_eq!(main.instructions[0].addr, 0x50fd);
+}
+
+#[test]
+fn call_targets_are_disassembled() {
+    let disassembly = disassembly();
+    let main = disassembly.routine_at(0x50fc).unwrap();
+
+    let call = main.instructions.iter().find(|instr| instr.addr == 0x5102).unwrap();
+    assert_eq!(call.name, "call");
+    assert_eq!(call.target, Some(0x572c));
+
+    let routine = disassembly.routine_at(0x572c).unwrap();
+    assert!(!routine.main);
+    assert!(routine.end > routine.addr);
+    assert!(routine.instructions.iter().all(|instr| instr.addr > routine.addr));
+}
+
+#[test]
+fn branches_are_followed_and_inline_text_is_shown() {
+    let disassembly = disassembly();
+    let main = disassembly.routine_at(0x50fc).unwrap();
+
+    // every branch target inside the routine was decoded too
+    for instr in &main.instructions {
+        if let Some(target) = instr.target.filter(|_| instr.name != "call") {
+            assert!(main.instructions.iter().any(|other| other.addr == target));
+        }
+    }
+
+    let text = disassembly.to_text();
+    assert!(text.contains("print            You wake up."));
+    assert!(text.contains("Main routine 050fc, 0 locals"));
+}
+
+#[test]
+fn regions_cover_the_story_in_order() {
+    let disassembly = disassembly();
+    let regions = &disassembly.regions;
+
+    assert_eq!(regions[0].kind, RegionKind::Header);
+    assert_eq!(regions[1].kind, RegionKind::Dynamic);
+    assert_eq!(regions[2].kind, RegionKind::Static);
+    assert!(regions.iter().any(|region| region.kind == RegionKind::Code));
+    assert!(regions.iter().any(|region| region.kind == RegionKind::Strings));
+
+    for pair in regions.windows(2) {
+        assert!(pair[0].end <= pair[1].start);
+    }
+
+    // the strings after the code are read as text
+    assert!(disassembly.strings.iter().any(|string| string.text.contains("Marvin")));
+}
+
+#[test]
+fn json_round_trips() {
+    let disassembly = disassembly();
+    let parsed: Disassembly = serde_json::from_str(&disassembly.to_json()).unwrap();
+
+    assert_eq!(parsed, disassembly);
+}
@@ -0,0 +1,85 @@
+use encrusted::{EventKind, EventQueue, HostEvent};
+
+#[test]
+fn messages_of_the_same_type_are_all_kept() {
+    let mut queue = EventQueue::new();
+    queue.push_message("print", "<span>first</span>");
+    queue.push_message("save", r#"["Bedroom - 0/1","AAAA"]"#);
+    queue.push_message("savestate", r#"["Bedroom - 0/1","BBBB"]"#);
+    queue.push_message("print", "<span>second</span>");
+
+    let events = queue.drain();
+    let kinds: Vec<EventKind> = events.iter().map(|event| event.event.kind()).collect();
+
+    assert_eq!(
+        kinds,
+        vec![EventKind::Print, EventKind::Save, EventKind::Savestate, EventKind::Print]
+    );
+    assert_eq!(events[0].event, HostEvent::Print("<span>first</span>".to_string()));
+    assert_eq!(events[3].event, HostEvent::Print("<span>second</span>".to_string()));
+    assert_eq!(
+        events[1].event,
+        HostEvent::Save("Bedroom - 0/1".to_string(), "AAAA".to_string()),
+        "Json bodies are decoded"
+    );
+    assert!(queue.is_empty(), "Draining empties the queue");
+}
+
+#[test]
+fn sequence_numbers_keep_counting_across_drains() {
+    let mut queue = EventQueue::new();
+    queue.push_message("header", r#"["Bedroom","0/0"]"#);
+    queue.push_message("map", r#"[142,"Bedroom"]"#);
+    let first = queue.drain();
+
+    queue.push_message("print", "look");
+    let second = queue.drain();
+
+    assert_eq!(first[0].seq, 1);
+    assert_eq!(first[1].seq, 2);
+    assert_eq!(second[0].seq, 3);
+}
+
+#[test]
+fn unknown_messages_are_dropped() {
+    let mut queue = EventQueue::new();
+
+    assert!(!queue.push_message("bogus", "data"));
+    assert!(!queue.push_message("header", "not a status line"));
+    assert!(queue.push_message("restore", ""));
+    assert_eq!(queue.len(), 1);
+}
+
+#[test]
+fn drain_json_is_an_array_of_events() {
+    let mut queue = EventQueue::new();
+    assert_eq!(queue.drain_json(), "[]");
+
+    queue.push_message("print", "hi");
+    queue.push(HostEvent::Map(142, "Bedroom".to_string()));
+    queue.push(HostEvent::Restore);
+    assert_eq!(
+        queue.drain_json(),
+        concat!(
+            r#"[{"seq":1,"type":"print","data":"hi"},"#,
+            r#"{"seq":2,"type":"map","data":[142,"Bedroom"]},"#,
+            r#"{"seq":3,"type":"restore"}]"#
+        )
+    );
+}
+
+#[test]
+fn host_events_round_trip_through_messages() {
+    let events = vec![
+        HostEvent::Print("<span>hi</span>".to_string()),
+        HostEvent::Header("Bedroom".to_string(), "0/1".to_string()),
+        HostEvent::Map(142, "Bedroom".to_string()),
+        HostEvent::Savestate("Bedroom - 0/1".to_string(), "AAAA".to_string()),
+        HostEvent::Restore,
+    ];
+
+    for event in events {
+        let (mtype, msg) = event.to_message();
+        assert_eq!(HostEvent::from_message(mtype, &msg), Some(event));
+    }
+}
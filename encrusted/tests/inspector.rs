@@ -0,0 +1,92 @@
+mod common;
+
+use encrusted::inspector::Section;
+use encrusted::{inspect, Game, Options, StoryInfo};
+
+fn info() -> StoryInfo {
+    inspect(&common::h2g2(Options::default())).unwrap()
+}
+
+#[test]
+fn header_fields_are_read() {
+    let header = info().header;
+
+    assert_eq!(header.version, 3);
+    assert_eq!(header.release, 60);
+    assert_eq!(header.serial_text(), "861002");
+    assert_eq!(header.initial_pc, 0x50fd);
+    assert!(header.static_memory >= 64);
+    assert!(header.high_memory >= header.static_memory);
+}
+
+#[test]
+fn dictionary_keeps_order_and_data_bytes() {
+    let dictionary = info().dictionary;
+
+    assert_eq!(dictionary.entry_length, 7);
+    assert!(dictionary.separators.contains(&','));
+
+    let bed = dictionary.entries.iter().find(|entry| entry.word == "bed").unwrap();
+    assert_eq!(bed.data.len(), 3);
+
+    for pair in dictionary.entries.windows(2) {
+        assert_eq!(pair[1].addr, pair[0].addr + 7);
+    }
+}
+
+#[test]
+fn dictionary_entries_past_the_end_are_errors() {
+    // h2g2 with an entry count that runs the dictionary past the end
+    let mut data = Game::story_data().to_vec();
+    let addr = u16::from_be_bytes([data[0x08], data[0x09]]) as usize;
+    let count = addr + 2 + data[addr] as usize;
+    data[count..count + 2].copy_from_slice(&0x7fffu16.to_be_bytes());
+
+    let (zvm, _) = common::load_story(data, Options::default());
+    let dictionary = inspect(&zvm).unwrap().dictionary;
+    assert_eq!(dictionary.entries.len(), 0x7fff);
+
+    let last = dictionary.entries.last().unwrap();
+    assert!(last.word.starts_with('['), "{}", last.word);
+    assert!(last.data.is_empty());
+    assert!(dictionary.entries.iter().any(|entry| entry.word == "bed"));
+}
+
+#[test]
+fn objects_have_names_attributes_and_properties() {
+    let info = info();
+
+    // the bed is in the bedroom (the player is only put in it once the
+    // story starts)
+    let bed = info.object(108).unwrap();
+    assert_eq!(bed.name, "bed");
+    assert_eq!(bed.parent, 142);
+    assert_eq!(info.object(187).unwrap().parent, 0);
+
+    let found: Vec<u16> = info.find_objects("BEDroom").iter().map(|object| object.number).collect();
+    assert!(found.contains(&142));
+
+    for object in &info.objects {
+        let numbers: Vec<u8> = object.properties.iter().map(|property| property.number).collect();
+        assert!(numbers.windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(object.attributes.iter().all(|&attr| attr < 32));
+    }
+}
+
+#[test]
+fn text_prints_the_requested_sections() {
+    let info = info();
+    assert_eq!(info.abbreviations.len(), 96);
+    assert_eq!(info.abbreviations[0].text, "the ");
+
+    let text = info.to_text(&[Section::Abbreviations]);
+    assert!(text.contains("**** Abbreviations ****"));
+    assert!(!text.contains("**** Objects ****"));
+
+    let all = info.to_text(&Section::ALL);
+    assert!(all.contains("Serial number:            861002"));
+    assert!(all.contains("**** Strings ****"));
+
+    let parsed: StoryInfo = serde_json::from_str(&info.to_json()).unwrap();
+    assert_eq!(parsed, info);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use encrusted::instruction::{Operand, OperandType, Operands};
+use encrusted::{inspect, Game, Options, StepOutcome};
+
+#[test]
+fn operand_types_stop_at_the_first_omitted() {
+    let types = OperandType::from(&[0b0001_1011, 0b0000_0000]);
+
+    assert_eq!(types.as_slice(), &[OperandType::Large, OperandType::Small, OperandType::Variable]);
+}
+
+#[test]
+fn operands_hold_up_to_eight_values() {
+    let values: Operands<u16> = (1..=8).collect();
+
+    assert_eq!(values.len(), 8);
+    assert_eq!(&values[1..3], &[2, 3]);
+    assert_eq!(format!("{:?}", values), "[1, 2, 3, 4, 5, 6, 7, 8]");
+
+    let operands: Operands<Operand> = [Operand::Large(0x1234), Operand::Variable(16)].into_iter().collect();
+    let text: Vec<String> = operands.iter().map(|op| op.to_string()).collect();
+    assert_eq!(text, vec!["1234", "g0"]);
+}
+
+#[test]
+#[should_panic(expected = "Too many operands")]
+fn operands_past_eight_panic() {
+    let _: Operands<u16> = (0..9).collect();
+}
+
+#[test]
+fn repeated_turns_run_from_the_cache() {
+    let mut zvm = common::h2g2(Options::default());
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+
+    zvm.handle_input("look".to_string());
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    let (hits, misses) = zvm.instruction_cache_stats();
+
+    zvm.handle_input("look".to_string());
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    let (more_hits, more_misses) = zvm.instruction_cache_stats();
+
+    assert!(hits > 0);
+    // the second look only misses on code in dynamic memory, if any
+    assert!(more_misses - misses < (more_hits - hits) / 10);
+}
+
+#[test]
+fn rewritten_abbreviations_are_printed_fresh() {
+    let story = Game::story_data();
+    let table = u16::from_be_bytes([story[0x18], story[0x19]]);
+    let second = &story[table as usize + 2..table as usize + 4];
+
+    // Prints abbreviation 0 from the same (cached) print twice, pointing
+    // its table entry at abbreviation 1's string in between
+    let [table_hi, table_lo] = table.to_be_bytes();
+    let code = [
+        0xe2, 0x17, 0x20, 0x00, 0x00, 0x00, // storeb 0x2000 0 0
+        0xb2, 0x84, 0x05, // again: print "[abbreviation 0]"
+        0xbb, // new_line
+        0xd0, 0x1f, 0x20, 0x00, 0x00, 0x00, // loadb 0x2000 0 -> sp
+        0xa0, 0x00, 0xc3, // jz sp ?rewrite
+        0xba, // quit
+        0xe2, 0x17, 0x20, 0x00, 0x00, 0x01, // rewrite: storeb 0x2000 0 1
+        0xe1, 0x13, table_hi, table_lo, 0x00, second[0], second[1], // storew table 0 (entry 1)
+        0x8c, 0xff, 0xe4, // jump again
+    ];
+
+    let (mut zvm, sink) = common::load_story(common::patched_h2g2(&code), Options::default());
+    let abbreviations = inspect(&zvm).unwrap().abbreviations;
+
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::Quit);
+    zvm.ui.flush();
+
+    let printed: String = sink.take().into_iter().filter(|(mtype, _)| mtype == "print").map(|(_, msg)| msg).collect();
+    let (first, second) = (&abbreviations[0].text, &abbreviations[1].text);
+    assert_eq!(printed.matches(first.as_str()).count(), 1, "{}", printed);
+    assert!(printed.find(first.as_str()) < printed.rfind(second.as_str()), "{}", printed);
+}
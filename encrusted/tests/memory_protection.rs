@@ -0,0 +1,128 @@
+mod common;
+
+use encrusted::{Buffer, MemoryError, Options, Region, StepOutcome, VecSink, Zmachine};
+
+fn regions() -> Buffer {
+    let mut memory = Buffer::new(vec![0; 0x400]);
+    memory.set_regions(0x100, 0x200);
+    memory
+}
+
+// Replaces the first instruction with `storeb 0x2608 0 1`, the first byte
+// of h2g2's static memory
+fn static_write_story(options: Options) -> (Zmachine, VecSink) {
+    let data = common::patched_h2g2(&[0xe2, 0x17, 0x26, 0x08, 0x00, 0x01]);
+    common::load_story(data, options)
+}
+
+#[test]
+fn regions_follow_the_boundaries() {
+    let memory = regions();
+
+    assert_eq!(memory.region(0x10), Region::Header);
+    assert_eq!(memory.region(0x40), Region::Dynamic);
+    assert_eq!(memory.region(0x100), Region::Static);
+    assert_eq!(memory.region(0x3ff), Region::High);
+}
+
+#[test]
+fn game_writes_are_limited_to_dynamic_memory_and_flags_2() {
+    let mut memory = regions();
+
+    assert_eq!(memory.store_byte(0x40, 1), Ok(()));
+    assert_eq!(memory.store_word(0x10, 0x0101), Ok(()));
+    assert_eq!(memory.store_byte(0x0e, 1), Err(MemoryError::ReadOnlyHeader(0x0e)));
+    assert_eq!(
+        memory.store_word(0xff, 1),
+        Err(MemoryError::ReadOnly {
+            addr: 0x100,
+            region: Region::Static
+        })
+    );
+    assert_eq!(memory.read_word(0xfe), 0, "A rejected write changes nothing");
+
+    // interpreter writes skip the region checks
+    memory.write_byte(0x0e, 1);
+    assert_eq!(memory.read_byte(0x0e), 1);
+}
+
+#[test]
+fn reads_past_the_end_are_errors() {
+    let memory = regions();
+
+    assert_eq!(memory.try_read_byte(0x3ff), Ok(0));
+    assert_eq!(
+        memory.try_read_word(0x3ff),
+        Err(MemoryError::OutOfBounds {
+            addr: 0x3ff,
+            length: 2,
+            size: 0x400
+        })
+    );
+    assert!(memory.try_read(usize::MAX, 2).is_err());
+
+    let mut read = memory.get_reader(0x3ff);
+    assert_eq!(read.try_byte(), Ok(0));
+    assert!(read.try_byte().is_err());
+}
+
+#[test]
+fn story_reads_past_the_end_are_faults() {
+    let stories: [&[u8]; 2] = [
+        // print_paddr 0xffff, the string would be at 0x1fffe
+        &[0x8d, 0xff, 0xff],
+        // call 0xffff -> sp, so would the routine
+        &[0xe0, 0x3f, 0xff, 0xff, 0x00],
+    ];
+
+    for code in stories {
+        let (mut zvm, _) = common::load_story(common::patched_h2g2(code), Options::default());
+
+        match zvm.run(usize::MAX) {
+            StepOutcome::Error(message) => assert!(message.contains("past the end of memory"), "{}", message),
+            outcome => panic!("Expected an error, got {:?}", outcome),
+        }
+    }
+}
+
+#[test]
+fn story_writes_go_through_the_region_checks() {
+    let code = [
+        0xe2, 0x17, 0x20, 0x00, 0x00, 0x2a, // storeb 0x2000 0 42
+        0xd0, 0x1f, 0x20, 0x00, 0x00, 0x00, // loadb 0x2000 0 -> sp
+        0x41, 0x00, 0x2a, 0xc5, // je sp 42 ?landed
+        0x8d, 0xff, 0xff, // print_paddr 0xffff
+        0xe1, 0x53, 0x10, 0x00, 0x01, 0x01, // landed: storew 0x10 0 0x101, Flags 2
+        0xe2, 0x57, 0x0e, 0x00, 0x01, // storeb 0x0e 0 1
+    ];
+    let (mut zvm, _) = common::load_story(common::patched_h2g2(&code), Options::default());
+
+    // only the last write is refused
+    match zvm.run(usize::MAX) {
+        StepOutcome::Error(message) => assert_eq!(message, "Illegal write to header byte 0xe"),
+        outcome => panic!("Expected an error, got {:?}", outcome),
+    }
+}
+
+#[test]
+fn static_writes_fault_by_default() {
+    let (mut zvm, _) = static_write_story(Options::default());
+
+    match zvm.run(usize::MAX) {
+        StepOutcome::Error(message) => assert!(message.contains("Illegal write to static memory at 0x2608")),
+        outcome => panic!("Expected an error, got {:?}", outcome),
+    }
+}
+
+#[test]
+fn static_writes_can_be_warnings_instead() {
+    let mut options = Options::default();
+    options.memory_warnings = true;
+    let (mut zvm, sink) = static_write_story(options);
+
+    assert_eq!(zvm.run(1), StepOutcome::Yielded);
+    zvm.ui.flush();
+
+    let printed: String = sink.take().into_iter().map(|(_, msg)| msg).collect();
+    assert!(printed.contains("Illegal write to static memory at 0x2608"));
+}
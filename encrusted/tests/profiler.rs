@@ -0,0 +1,98 @@
+mod common;
+
+use encrusted::{Options, Zmachine};
+
+fn load(profile: bool) -> Zmachine {
+    let mut options = Options::default();
+    options.profile = profile;
+
+    common::h2g2(options)
+}
+
+fn play(zvm: &mut Zmachine, commands: &[&str]) {
+    zvm.run(usize::MAX);
+
+    for command in commands {
+        zvm.handle_input(command.to_string());
+        zvm.run(usize::MAX);
+    }
+}
+
+#[test]
+fn profiling_is_off_by_default() {
+    let mut zvm = load(false);
+    play(&mut zvm, &["wait"]);
+
+    let report = zvm.profiler.report();
+    assert_eq!(report.instructions, 0);
+    assert!(report.routines.is_empty());
+    assert_eq!(zvm.profiler.folded_stacks(), "\n");
+}
+
+#[test]
+fn every_instruction_is_counted_once() {
+    let mut zvm = load(true);
+    play(&mut zvm, &["turn on light", "get up", "wait"]);
+
+    let report = zvm.profiler.report();
+    assert_eq!(report.instructions, zvm.instruction_count());
+    assert_eq!(report.routines.iter().map(|routine| routine.instructions).sum::<u64>(), report.instructions);
+    assert_eq!(report.opcodes.iter().map(|opcode| opcode.count).sum::<u64>(), report.instructions);
+
+    // sorted, busiest first
+    assert!(report.routines.windows(2).all(|pair| pair[0].instructions >= pair[1].instructions));
+    assert!(report.opcodes.windows(2).all(|pair| pair[0].count >= pair[1].count));
+
+    // the main routine is never called, everything else is
+    let main = report.routines.iter().find(|routine| routine.addr == 0).unwrap();
+    assert_eq!(main.calls, 0);
+    assert!(report.routines.iter().filter(|routine| routine.addr != 0).all(|routine| routine.calls > 0));
+    assert!(report.opcodes.iter().any(|opcode| opcode.name == "call"));
+
+    let text = report.to_text();
+    assert!(text.starts_with(&format!("Instructions: {}", report.instructions)));
+    assert!(report.to_json().contains("\"routines\""));
+}
+
+#[test]
+fn folded_stacks_add_up_to_the_routines() {
+    let mut zvm = load(true);
+    play(&mut zvm, &["wait", "wait"]);
+
+    let report = zvm.profiler.report();
+    let folded = zvm.profiler.folded_stacks();
+    let mut total = 0;
+
+    for line in folded.lines() {
+        let (stack, count) = line.rsplit_once(' ').unwrap();
+        assert!(stack.starts_with("main"), "{}", line);
+        total += count.parse::<u64>().unwrap();
+    }
+
+    assert_eq!(total, report.instructions);
+
+    // the innermost routine of each stack is the one the instructions ran in
+    let busiest = &report.routines[0];
+    let name = if busiest.addr == 0 { String::from("main") } else { format!("{:05x}", busiest.addr) };
+    let in_busiest: u64 = folded
+        .lines()
+        .filter_map(|line| line.rsplit_once(' '))
+        .filter(|(stack, _)| stack.rsplit(';').next() == Some(name.as_str()))
+        .map(|(_, count)| count.parse::<u64>().unwrap())
+        .sum();
+
+    assert_eq!(in_busiest, busiest.instructions);
+}
+
+#[test]
+fn profiles_can_be_turned_on_and_cleared() {
+    let mut zvm = load(false);
+    zvm.profiler.set_enabled(true);
+    play(&mut zvm, &["wait"]);
+
+    assert_eq!(zvm.profiler.report().instructions, zvm.instruction_count());
+
+    zvm.profiler.clear();
+    assert_eq!(zvm.profiler.report().instructions, 0);
+    assert!(zvm.profiler.is_enabled());
+}
@@ -0,0 +1,166 @@
+use std::io::Cursor;
+
+use encrusted::protocol::{self, Event, Request, Session, PROTOCOL_VERSION};
+use encrusted::{Game, Options};
+
+fn new_session() -> (Session, Vec<Event>) {
+    let mut session = Session::new(Game::story_data().to_vec(), Options::default());
+    let events = session.start();
+    (session, events)
+}
+
+fn find<'a>(events: &'a [Event], kind: &str) -> Option<&'a Event> {
+    events.iter().find(|event| event.kind == kind)
+}
+
+fn input(text: &str) -> Request {
+    Request::Input {
+        text: text.to_string(),
+    }
+}
+
+#[test]
+fn start_sends_web_messages_and_ready() {
+    let (_, events) = new_session();
+
+    let print = find(&events, "print").expect("Intro should be printed");
+    assert!(print.data.as_str().unwrap().contains("GUIDE TO THE GALAXY"));
+
+    assert!(find(&events, "header").is_some());
+    assert!(find(&events, "map").is_some());
+    assert!(find(&events, "tree").is_some());
+    assert!(find(&events, "savestate").is_some());
+
+    let ready = events.last().unwrap();
+    assert_eq!(ready.kind, "ready", "Responses end with a ready event");
+    assert_eq!(ready.data["expects"], "line");
+}
+
+#[test]
+fn input_requests_play_commands() {
+    let (mut session, _) = new_session();
+    let events = session.handle_line(r#"{"type":"input","text":"turn on light"}"#);
+
+    let print = find(&events, "print").unwrap();
+    assert!(print.data.as_str().unwrap().contains("The light is now on."));
+
+    let header = find(&events, "header").unwrap();
+    assert_eq!(header.data[0], "Bedroom");
+}
+
+#[test]
+fn output_request_switches_to_text_runs() {
+    let (mut session, _) = new_session();
+    session.handle_line(r#"{"type":"output","format":"tokens"}"#);
+
+    let events = session.handle(input("turn on light"));
+    assert!(find(&events, "print").is_none());
+
+    let tokens = find(&events, "tokens").expect("Output should come as runs");
+    let runs = tokens.data.as_array().unwrap();
+    assert!(runs.iter().any(|run| run["kind"] == "text"
+        && run["text"].as_str().unwrap().contains("The light is now on.")));
+}
+
+#[test]
+fn invalid_requests_get_an_error() {
+    let (mut session, _) = new_session();
+
+    let events = session.handle_line("not json");
+    assert!(find(&events, "error").is_some());
+
+    let events = session.handle_line(r#"{"type":"fly"}"#);
+    assert!(find(&events, "error").is_some());
+    assert_eq!(events.last().unwrap().kind, "ready");
+}
+
+#[test]
+fn saves_restore_in_a_new_session() {
+    let (mut session, _) = new_session();
+    session.handle(input("turn on light"));
+    session.handle(input("get up"));
+
+    let events = session.handle(Request::Save);
+    let save = find(&events, "save").expect("Save should send the current state");
+    let data = save.data[1].as_str().unwrap().to_string();
+
+    let (mut other, _) = new_session();
+    let events = other.handle(Request::Restore { data });
+    assert!(find(&events, "error").is_none());
+
+    let events = other.handle(Request::Location);
+    let map = find(&events, "map").unwrap();
+    assert_eq!(map.data[1], "Bedroom");
+
+    let events = other.handle(input("look"));
+    let print = find(&events, "print").unwrap().data.as_str().unwrap();
+    assert!(!print.contains("in the bed"), "Restored state should be out of bed");
+}
+
+#[test]
+fn undo_and_redo_move_through_history() {
+    let (mut session, _) = new_session();
+    session.handle(input("turn on light"));
+    session.handle(input("get up"));
+
+    let events = session.handle(Request::Undo);
+    assert_eq!(find(&events, "header").unwrap().data[1], "0/1");
+
+    let events = session.handle(Request::Redo);
+    assert_eq!(find(&events, "header").unwrap().data[1], "0/2");
+}
+
+#[test]
+fn undo_and_redo_without_history_are_errors() {
+    let (mut session, _) = new_session();
+
+    let events = session.handle(Request::Redo);
+    assert_eq!(find(&events, "error").unwrap().data, "Nothing to redo");
+    assert!(find(&events, "header").is_none());
+
+    session.handle(input("turn on light"));
+    session.handle(Request::Undo);
+
+    let events = session.handle(Request::Undo);
+    assert_eq!(find(&events, "error").unwrap().data, "Nothing to undo");
+    assert_eq!(events.last().unwrap().kind, "ready");
+}
+
+#[test]
+fn hints_are_listed_without_answers() {
+    let (mut session, _) = new_session();
+    session.handle(input("turn on light"));
+
+    let events = session.handle_line(r#"{"type":"hints"}"#);
+    let hints = find(&events, "hints").unwrap();
+    assert_eq!(hints.data["location"], "Bedroom");
+
+    let questions = hints.data["questions"].as_array().unwrap();
+    assert!(!questions.is_empty());
+    assert!(questions[0].get("answers").is_none());
+
+    let idx = questions[0]["question"].as_u64().unwrap();
+    let line = format!(r#"{{"type":"hints","question":{},"level":0}}"#, idx);
+    let events = session.handle_line(&line);
+    let hint = find(&events, "hint").unwrap();
+    assert!(!hint.data["answer"].as_str().unwrap().is_empty());
+}
+
+#[test]
+fn run_writes_one_event_per_line() {
+    let mut session = Session::new(Game::story_data().to_vec(), Options::default());
+
+    let input = Cursor::new("{\"type\":\"input\",\"text\":\"turn on light\"}\n\n{\"type\":\"tree\"}\n");
+    let mut output = Vec::new();
+    protocol::run(&mut session, input, &mut output).unwrap();
+
+    let events: Vec<Event> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Every line is an event"))
+        .collect();
+
+    assert_eq!(events[0].kind, "hello");
+    assert_eq!(events[0].data["protocol"], PROTOCOL_VERSION);
+    assert_eq!(events.iter().filter(|e| e.kind == "ready").count(), 3);
+}
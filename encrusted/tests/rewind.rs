@@ -0,0 +1,78 @@
+mod common;
+
+use encrusted::{Options, StepOutcome, Zmachine};
+
+fn game(rewind: usize) -> Zmachine {
+    let mut opts = Options::default();
+    opts.rewind = rewind;
+
+    let mut zvm = common::h2g2(opts);
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    zvm
+}
+
+#[test]
+fn going_back_over_a_turn_restores_everything() {
+    let mut zvm = game(1_000_000);
+    zvm.handle_input("turn on light".to_string());
+
+    // where the turn starts, right after the input was read
+    let start = (zvm.debug_location(), zvm.backtrace());
+
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    let end = zvm.get_save_state();
+    let count = zvm.rewind.len();
+    assert!(count > 100);
+
+    assert_eq!(zvm.step_back(count + 10), count);
+    assert_eq!((zvm.debug_location(), zvm.backtrace()), start);
+
+    // running it again ends up in exactly the same state
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    assert_eq!(zvm.get_save_state(), end);
+}
+
+#[test]
+fn the_window_keeps_the_newest_instructions() {
+    let mut zvm = game(10);
+    zvm.handle_input("look".to_string());
+    zvm.run(usize::MAX);
+
+    assert_eq!(zvm.rewind.len(), 10);
+    assert_eq!(zvm.step_back(20), 10);
+
+    // input starts a new recording
+    zvm.run(usize::MAX);
+    zvm.handle_input("look".to_string());
+    assert!(zvm.rewind.is_empty());
+}
+
+#[test]
+fn debugger_commands_go_back() {
+    let mut zvm = game(0);
+    assert!(zvm.debug_command("back").is_err());
+
+    zvm.debug_command("record 5000").unwrap();
+    zvm.handle_input("get up".to_string());
+
+    // the player (187) got out of bed, going back finds where
+    zvm.run(usize::MAX);
+    zvm.debug_command("watch parent 187").unwrap();
+
+    let output = zvm.debug_command("reverse").unwrap();
+    assert!(output.starts_with("[Watchpoint 1 changed at 0xe76d"));
+    assert!(output.contains("insert_obj"));
+
+    let output = zvm.debug_command("back 2").unwrap();
+    assert!(output.starts_with("[Back 2 instructions]"));
+
+    // and forward again stops on the same change
+    match zvm.run(usize::MAX) {
+        StepOutcome::Break(stop) => assert_eq!(stop.pc(), 0xe770),
+        other => panic!("Expected a debugger stop, got {:?}", other),
+    }
+
+    zvm.debug_command("record 0").unwrap();
+    assert!(zvm.rewind.is_empty());
+    assert!(zvm.debug_command("back").is_err());
+}
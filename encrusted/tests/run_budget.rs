@@ -0,0 +1,144 @@
+mod common;
+
+use encrusted::{Game, Options, StepOutcome, VecSink, WebUI, Zmachine};
+
+fn new_game() -> (Zmachine, VecSink) {
+    let sink = VecSink::new();
+    let ui = WebUI::with_sink(Box::new(sink.clone()));
+    (Game::load_from_ui(ui, Options::default()), sink)
+}
+
+fn run_to_pause(zvm: &mut Zmachine, budget: usize) -> (StepOutcome, usize) {
+    let mut slices = 1;
+    let mut outcome = zvm.run(budget);
+
+    while outcome == StepOutcome::Yielded {
+        slices += 1;
+        outcome = zvm.run(budget);
+    }
+
+    (outcome, slices)
+}
+
+fn printed(zvm: &mut Zmachine, sink: &VecSink) -> String {
+    zvm.ui.flush();
+    sink.take()
+        .into_iter()
+        .filter(|(mtype, _)| mtype == "print")
+        .map(|(_, html)| html)
+        .collect()
+}
+
+#[test]
+fn small_budgets_yield_and_resume_where_they_left_off() {
+    let (mut whole, whole_sink) = new_game();
+    assert!(!whole.step());
+
+    let (mut sliced, sliced_sink) = new_game();
+    let (outcome, slices) = run_to_pause(&mut sliced, 100);
+
+    assert_eq!(outcome, StepOutcome::NeedsLine);
+    assert!(slices > 1, "The intro takes more than 100 instructions");
+    assert_eq!(
+        printed(&mut sliced, &sliced_sink),
+        printed(&mut whole, &whole_sink),
+        "Running in slices prints the same as running in one go"
+    );
+}
+
+#[test]
+fn run_while_waiting_for_input_does_nothing() {
+    let (mut zvm, sink) = new_game();
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    sink.take();
+
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    assert!(sink.take().is_empty(), "No second savestate is sent");
+
+    zvm.handle_input("turn on light".to_string());
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    assert!(printed(&mut zvm, &sink).contains("The light is now on."));
+}
+
+#[test]
+fn restore_waits_for_save_data() {
+    let (mut zvm, sink) = new_game();
+    zvm.run(usize::MAX);
+    sink.take();
+
+    zvm.handle_input("restore".to_string());
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsRestoreData);
+    assert!(sink.take().iter().any(|(mtype, _)| mtype == "restore"));
+
+    zvm.restore("");
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+}
+
+#[test]
+fn quit_stays_quit() {
+    let (mut zvm, _) = new_game();
+    zvm.run(usize::MAX);
+
+    zvm.handle_input("quit".to_string());
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+
+    // sometimes there's a "hit return" before the are-you-sure question
+    let mut outcome = StepOutcome::NeedsLine;
+    for _ in 0..3 {
+        zvm.handle_input("y".to_string());
+        outcome = zvm.run(usize::MAX);
+        if outcome == StepOutcome::Quit {
+            break;
+        }
+    }
+
+    assert_eq!(outcome, StepOutcome::Quit);
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::Quit);
+    assert!(zvm.step());
+}
+
+#[test]
+fn unimplemented_opcodes_are_errors() {
+    // put a variable-form opcode 247 (no operands) at the start of the story
+    let data = common::patched_h2g2(&[0xf7, 0xff]);
+    let (mut zvm, _) = common::load_story(data, Options::default());
+
+    match zvm.run(usize::MAX) {
+        StepOutcome::Error(message) => assert!(message.contains("not yet implemented")),
+        outcome => panic!("Expected an error, got {:?}", outcome),
+    }
+}
+
+#[test]
+fn read_char_stores_zscii() {
+    // read_char 1 -> sp, print_num sp, three times, then read_char again
+    let key = [0xf6, 0x7f, 0x01, 0x00, 0xe6, 0xbf, 0x00];
+    let code: Vec<u8> = key.iter().cycle().take(key.len() * 3).chain(&key[..4]).cloned().collect();
+    let mut data = common::patched_h2g2(&code);
+    // read_char is v4+, where the file length is counted in 4 bytes
+    let length = u16::from_be_bytes([data[0x1a], data[0x1b]]) / 2;
+    data[0] = 5;
+    data[0x1a..0x1c].copy_from_slice(&length.to_be_bytes());
+
+    let (mut zvm, sink) = common::load_story(data, Options::default());
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsChar);
+
+    // ascii stays, é is in the default unicode table, € isn't
+    for (input, zscii) in [("a", "97"), ("é", "170"), ("€", "63")] {
+        zvm.handle_input(input.to_string());
+        assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsChar);
+        assert!(printed(&mut zvm, &sink).contains(zscii), "{} is zscii {}", input, zscii);
+    }
+}
+
+#[test]
+fn outcomes_serialize_for_hosts() {
+    assert_eq!(
+        serde_json::to_string(&StepOutcome::NeedsRestoreData).unwrap(),
+        r#"{"outcome":"needs_restore_data"}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&StepOutcome::Error("bad".to_string())).unwrap(),
+        r#"{"outcome":"error","message":"bad"}"#
+    );
+}
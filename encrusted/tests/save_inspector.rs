@@ -0,0 +1,116 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use encrusted::quetzal::QuetzalSave;
+use encrusted::save_inspector::Envelope;
+mod common;
+
+use encrusted::{read_save, Game, HostEvent, Options, StepOutcome, VecSink, Zmachine};
+
+fn game() -> (Zmachine, VecSink) {
+    common::load_story(Game::story_data().to_vec(), Options::default())
+}
+
+// The signed save the web app gets at each prompt
+fn run_to_prompt(zvm: &mut Zmachine, sink: &VecSink) -> String {
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    zvm.ui.flush();
+
+    sink.take()
+        .into_iter()
+        .filter_map(|(mtype, msg)| match HostEvent::from_message(&mtype, &msg) {
+            Some(HostEvent::Savestate(_, data)) => Some(data),
+            _ => None,
+        })
+        .last()
+        .unwrap()
+}
+
+#[test]
+fn signed_saves_show_chunks_and_frames() {
+    let (mut zvm, sink) = game();
+    let save = read_save(Game::story_data(), &run_to_prompt(&mut zvm, &sink)).unwrap();
+
+    assert!(matches!(save.envelope, Envelope::Signed { version: 1, .. }));
+    assert_eq!(save.release, 60);
+    assert_eq!(save.serial, "861002");
+
+    let ids: Vec<&str> = save.chunks.iter().map(|chunk| chunk.id.as_str()).collect();
+    assert_eq!(ids, vec!["IFhd", "Stks", "CMem"]);
+    assert!(!save.frames.is_empty());
+    assert_eq!(save.location, "Bedroom");
+
+    let text = save.to_text();
+    assert!(text.contains("signature ok"));
+    assert!(text.contains("IFhd (13 bytes)"));
+}
+
+#[test]
+fn bare_quetzal_saves_are_read_too() {
+    let (mut zvm, sink) = game();
+    run_to_prompt(&mut zvm, &sink);
+
+    let save = read_save(Game::story_data(), &zvm.get_save_state().unwrap()).unwrap();
+    assert_eq!(save.envelope, Envelope::Bare);
+}
+
+#[test]
+fn broken_envelopes_are_reported() {
+    let (mut zvm, sink) = game();
+    let mut data = BASE64.decode(run_to_prompt(&mut zvm, &sink)).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0xFF;
+
+    let err = read_save(Game::story_data(), &BASE64.encode(&data)).unwrap_err();
+    assert!(err.contains("CRC32"));
+
+    assert!(read_save(Game::story_data(), "not base64!").is_err());
+}
+
+#[test]
+fn saves_with_too_much_memory_are_refused() {
+    let (mut zvm, sink) = game();
+    run_to_prompt(&mut zvm, &sink);
+
+    // the same save, with uncompressed memory that runs into static memory
+    let quetzal = BASE64.decode(zvm.get_save_state().unwrap()).unwrap();
+    let mut form = b"IFZS".to_vec();
+    for (id, body) in QuetzalSave::chunks(&quetzal).unwrap() {
+        let body = if id == "CMem" { vec![0; zvm.header().static_memory + 2] } else { body.to_vec() };
+        let chunk_id = if id == "CMem" { "UMem" } else { id.as_str() };
+
+        form.extend(chunk_id.as_bytes());
+        form.extend((body.len() as u32).to_be_bytes());
+        form.extend(&body);
+        if body.len() % 2 != 0 {
+            form.push(0);
+        }
+    }
+
+    let mut save = b"FORM".to_vec();
+    save.extend((form.len() as u32).to_be_bytes());
+    save.extend(form);
+
+    let err = read_save(Game::story_data(), &BASE64.encode(&save)).unwrap_err();
+    assert!(err.contains("dynamic memory"), "{}", err);
+}
+
+#[test]
+fn diffs_show_what_a_turn_changed() {
+    let (mut zvm, sink) = game();
+    let before = read_save(Game::story_data(), &run_to_prompt(&mut zvm, &sink)).unwrap();
+
+    zvm.handle_input(String::from("get up"));
+    let after = read_save(Game::story_data(), &run_to_prompt(&mut zvm, &sink)).unwrap();
+
+    let diff = before.diff(&after);
+    assert_eq!(diff.turns, 1);
+
+    // the player (187) gets out of bed (108), into the bedroom (142)
+    let player = diff.moved.iter().find(|object| object.number == 187).unwrap();
+    assert_eq!((player.from, player.to), (108, 142));
+    assert_eq!(player.from_name, "bed");
+
+    assert!(diff.globals.iter().any(|global| global.index == 2));
+    assert!(diff.to_text().contains("Score +0, turns +1"));
+
+    assert!(before.diff(&before).is_empty());
+}
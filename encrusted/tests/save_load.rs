@@ -82,15 +82,21 @@ fn restore_with_valid_save_data_succeeds() {
 }
 
 #[test]
-#[should_panic(expected = "called `Option::unwrap()` on a `None` value")]
-fn restore_with_invalid_data_panics() {
+fn restore_with_invalid_data_is_ignored() {
     let ui = MockUI::new();
     let opts = Options::default();
     let mut zvm = Game::load_from_ui(ui, opts);
+    let location = zvm.get_current_room();
 
-    // Invalid base64 data will panic - this is expected behavior
-    // Valid data handling is tested in save_and_restore_cycle_preserves_location
+    // Hosts can restore before the game starts (no paused restore to fail),
+    // bad data must leave the machine untouched and playable
     zvm.restore("invalid_base64_data_xyz");
+
+    assert_eq!(zvm.get_current_room(), location);
+    assert!(!zvm.is_awaiting_restore());
+
+    zvm.step();
+    zvm.ui.flush();
 }
 
 #[test]
@@ -158,3 +164,24 @@ fn restore_with_valid_save_restores_state() {
     assert_eq!(location_after_restore, location_at_save,
         "Restore should return to saved location");
 }
+
+#[test]
+fn frame_store_survives_serialization() {
+    use encrusted::frame::Frame;
+
+    let with_store = Frame::new(0x1234, Some(0x10), vec![1, 2], &[7]);
+    let discarded = Frame::new(0x5678, None, vec![3], &[]);
+
+    let restored = Frame::from_bytes(&with_store.to_vec());
+    assert_eq!(restored.store, Some(0x10));
+    assert_eq!(restored.resume, 0x1234);
+    assert_eq!(restored.arg_count, 1);
+
+    // Quetzal sets bit 4 of the flags byte for discarded results
+    assert_eq!(with_store.to_vec()[3] & 0b0001_0000, 0);
+    assert_eq!(discarded.to_vec()[3] & 0b0001_0000, 0b0001_0000);
+
+    let restored = Frame::from_bytes(&discarded.to_vec());
+    assert_eq!(restored.store, None);
+    assert_eq!(restored.resume, 0x5678);
+}
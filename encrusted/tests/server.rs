@@ -0,0 +1,238 @@
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tungstenite::{Message, WebSocket};
+
+use encrusted::protocol::Event;
+use encrusted::server::{self, ServerConfig, ServerError, Sessions};
+use encrusted::Game;
+
+fn config(autosave: &str) -> ServerConfig {
+    let dir = env::temp_dir().join(format!("encrusted-{}-{}", autosave, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    ServerConfig {
+        story: Game::story_data().to_vec(),
+        autosave_dir: Some(dir),
+        idle_timeout: Duration::from_secs(60),
+        seed: Some(1),
+        allowed_origin: None,
+        max_sessions: 4,
+    }
+}
+
+fn autosave_file(config: &ServerConfig, id: &str) -> PathBuf {
+    config.autosave_dir.as_ref().unwrap().join(format!("{}.sav", id))
+}
+
+fn find<'a>(events: &'a [Event], kind: &str) -> Option<&'a Event> {
+    events.iter().find(|event| event.kind == kind)
+}
+
+fn start_server(config: ServerConfig) -> SocketAddr {
+    let http = tiny_http::Server::http("127.0.0.1:0").unwrap();
+    let addr = http.server_addr().to_ip().unwrap();
+
+    thread::spawn(move || server::serve(http, config));
+    addr
+}
+
+/// Sends a raw request, returns the whole response
+fn send(addr: SocketAddr, request: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// Minimal HTTP/1.1 client, returns the status code and the json body
+fn http(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+    let response = send(
+        addr,
+        &format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        ),
+    );
+
+    let status = response[9..12].parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+fn read_event<S: Read + Write>(socket: &mut WebSocket<S>) -> Event {
+    loop {
+        if let Message::Text(text) = socket.read().unwrap() {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+#[test]
+fn sessions_are_independent() {
+    let mut sessions = Sessions::new(config("independent"));
+    let (first, _) = sessions.create().unwrap();
+    let (second, _) = sessions.create().unwrap();
+    assert_ne!(first, second);
+
+    let events = sessions.handle(&first, r#"{"type":"input","text":"turn on light"}"#).unwrap();
+    assert_eq!(find(&events, "header").unwrap().data[1], "0/1");
+
+    let events = sessions.handle(&second, r#"{"type":"input","text":"inventory"}"#).unwrap();
+    assert_eq!(find(&events, "header").unwrap().data[1], "0/1");
+
+    let events = sessions.handle(&first, r#"{"type":"input","text":"get up"}"#).unwrap();
+    assert_eq!(find(&events, "header").unwrap().data[1], "0/2");
+
+    assert_eq!(sessions.list().len(), 2);
+    assert!(sessions.handle("nope", r#"{"type":"tree"}"#).is_err());
+}
+
+#[test]
+fn evicted_sessions_resume_from_autosave() {
+    let config = config("evict");
+    let mut sessions = Sessions::new(config.clone());
+    let (id, _) = sessions.create().unwrap();
+
+    sessions.handle(&id, r#"{"type":"input","text":"turn on light"}"#).unwrap();
+    sessions.handle(&id, r#"{"type":"input","text":"get up"}"#).unwrap();
+    assert!(autosave_file(&config, &id).exists(), "Sessions are autosaved");
+
+    let evicted = sessions.evict_idle(Instant::now() + config.idle_timeout);
+    assert_eq!(evicted, vec![id.clone()]);
+    assert!(sessions.list().is_empty());
+
+    let events = sessions.handle(&id, r#"{"type":"input","text":"look"}"#).unwrap();
+    let print = find(&events, "print").unwrap().data.as_str().unwrap();
+    assert!(!print.contains("in the bed"), "Resumed session should be out of bed");
+
+    assert!(sessions.close(&id));
+    assert!(!autosave_file(&config, &id).exists(), "Closing deletes the autosave");
+    assert!(!sessions.contains(&id), "Closed sessions don't come back");
+    assert!(!sessions.close(&id));
+}
+
+#[test]
+fn new_sessions_are_refused_past_the_limit() {
+    let mut config = config("full");
+    config.max_sessions = 1;
+
+    let mut sessions = Sessions::new(config.clone());
+    let (id, _) = sessions.create().unwrap();
+    assert_eq!(sessions.create().unwrap_err(), ServerError::Full(1));
+
+    // closing one makes room again
+    assert!(sessions.close(&id));
+    assert!(sessions.create().is_ok());
+
+    let addr = start_server(config);
+    let (status, _) = http(addr, "POST", "/sessions", "");
+    assert_eq!(status, 201);
+    let (status, error) = http(addr, "POST", "/sessions", "");
+    assert_eq!(status, 503);
+    assert_eq!(error["type"], "error");
+}
+
+#[test]
+fn http_routes_play_a_session() {
+    let addr = start_server(config("http"));
+
+    let (status, created) = http(addr, "POST", "/sessions", "");
+    assert_eq!(status, 201);
+    let id = created["id"].as_str().unwrap().to_string();
+    assert!(created["events"].as_array().unwrap().iter().any(|e| e["type"] == "print"));
+
+    let path = format!("/sessions/{}", id);
+    let (status, events) = http(addr, "POST", &path, r#"{"type":"input","text":"turn on light"}"#);
+    assert_eq!(status, 200);
+
+    let events: Vec<Event> = serde_json::from_value(events).unwrap();
+    let print = find(&events, "print").unwrap().data.as_str().unwrap();
+    assert!(print.contains("The light is now on."));
+
+    // nobody else gets to see the id
+    let (status, _) = http(addr, "GET", "/sessions", "");
+    assert_eq!(status, 404);
+
+    let (status, _) = http(addr, "DELETE", &path, "");
+    assert_eq!(status, 200);
+
+    let (status, _) = http(addr, "POST", &path, r#"{"type":"tree"}"#);
+    assert_eq!(status, 404);
+}
+
+#[test]
+fn websocket_streams_protocol_events() {
+    let addr = start_server(config("ws"));
+    let (_, created) = http(addr, "POST", "/sessions", "");
+    let id = created["id"].as_str().unwrap();
+
+    let url = format!("ws://{}/sessions/{}/ws", addr, id);
+    let (mut socket, _) = tungstenite::connect(url).unwrap();
+
+    assert_eq!(read_event(&mut socket).kind, "hello");
+
+    socket
+        .send(Message::text(r#"{"type":"input","text":"turn on light"}"#))
+        .unwrap();
+
+    let mut events = vec![read_event(&mut socket)];
+    while events.last().unwrap().kind != "ready" {
+        events.push(read_event(&mut socket));
+    }
+
+    let print = find(&events, "print").unwrap().data.as_str().unwrap();
+    assert!(print.contains("The light is now on."));
+    assert!(find(&events, "header").is_some());
+    assert!(find(&events, "savestate").is_some());
+}
+
+#[test]
+fn oversized_bodies_and_bad_upgrades_are_refused() {
+    let addr = start_server(config("limits"));
+    let (_, created) = http(addr, "POST", "/sessions", "");
+    let path = format!("/sessions/{}", created["id"].as_str().unwrap());
+
+    let text = "x".repeat(100 * 1024);
+    let (status, _) = http(addr, "POST", &path, &format!(r#"{{"type":"input","text":"{}"}}"#, text));
+    assert_eq!(status, 413);
+
+    // a key alone isn't a WebSocket handshake
+    let response = send(
+        addr,
+        &format!(
+            "GET {}/ws HTTP/1.1\r\nHost: localhost\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nConnection: close\r\n\r\n",
+            path
+        ),
+    );
+    assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+
+    // the session is still playable
+    let (status, _) = http(addr, "POST", &path, r#"{"type":"input","text":"turn on light"}"#);
+    assert_eq!(status, 200);
+}
+
+#[test]
+fn cors_is_only_sent_for_the_allowed_origin() {
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+    let response = send(start_server(config("cors-off")), request);
+    assert!(!response.contains("Access-Control-Allow-Origin"));
+
+    let mut config = config("cors-on");
+    config.allowed_origin = Some(String::from("https://play.example.com"));
+
+    let response = send(start_server(config), request);
+    assert!(response.contains("Access-Control-Allow-Origin: https://play.example.com"));
+}
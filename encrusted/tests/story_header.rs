@@ -0,0 +1,121 @@
+mod common;
+
+use encrusted::{Game, HeaderError, Options, StoryHeader, VecSink, WebUI};
+
+fn put_word(data: &mut [u8], addr: usize, value: u16) {
+    data[addr] = (value >> 8) as u8;
+    data[addr + 1] = value as u8;
+}
+
+// A 4k story with just enough header to pass validation
+fn story(version: u8) -> Vec<u8> {
+    let mut data = vec![0; 0x1000];
+    data[0x00] = version;
+    put_word(&mut data, 0x04, 0x900); // high memory
+    put_word(&mut data, 0x06, 0x901); // initial pc
+    put_word(&mut data, 0x08, 0x400); // dictionary
+    put_word(&mut data, 0x0A, 0x100); // object table
+    put_word(&mut data, 0x0C, 0x200); // globals
+    put_word(&mut data, 0x0E, 0x800); // static memory
+    put_word(&mut data, 0x18, 0x040); // abbreviations
+    data
+}
+
+#[test]
+fn h2g2_header_fields() {
+    let header = StoryHeader::parse(Game::story_data()).unwrap();
+
+    assert_eq!(header.version, 3);
+    assert_eq!(header.release, 60);
+    assert_eq!(header.serial_text(), "861002");
+    assert_eq!(header.initial_pc, 0x50fd);
+    assert_eq!(header.static_memory, 0x2608);
+    assert_eq!(header.file_length, Game::story_data().len());
+    assert_eq!(header.extension, None);
+    assert!(!header.is_time_game());
+
+    // the interpreter uses the same header
+    let zvm = common::h2g2(Options::default());
+    assert_eq!(zvm.header(), &header);
+}
+
+#[test]
+fn v5_extension_table() {
+    let mut data = story(5);
+    put_word(&mut data, 0x36, 0x300);
+    put_word(&mut data, 0x300, 3);
+    put_word(&mut data, 0x302, 10);
+    put_word(&mut data, 0x304, 20);
+    put_word(&mut data, 0x306, 0x310);
+
+    let extension = StoryHeader::parse(&data).unwrap().extension.unwrap();
+    assert_eq!(extension.words, vec![10, 20, 0x310]);
+    assert_eq!((extension.mouse_x(), extension.mouse_y()), (10, 20));
+    assert_eq!(extension.unicode_table(), 0x310);
+    assert_eq!(extension.flags3(), 0);
+
+    // v3 stories don't have one, whatever is at 0x36
+    let mut data = story(3);
+    put_word(&mut data, 0x36, 0x300);
+    assert_eq!(StoryHeader::parse(&data).unwrap().extension, None);
+}
+
+#[test]
+fn invalid_headers_are_descriptive_errors() {
+    assert_eq!(StoryHeader::parse(&[3; 10]), Err(HeaderError::TooShort(10)));
+    assert_eq!(StoryHeader::parse(&story(9)), Err(HeaderError::UnsupportedVersion(9)));
+    assert_eq!(StoryHeader::parse(&story(0)), Err(HeaderError::UnsupportedVersion(0)));
+
+    let mut data = story(3);
+    put_word(&mut data, 0x0E, 0x20);
+    assert_eq!(StoryHeader::parse(&data), Err(HeaderError::StaticMemoryTooLow(0x20)));
+
+    let mut data = story(3);
+    put_word(&mut data, 0x08, 0xfff0);
+    let err = StoryHeader::parse(&data).unwrap_err();
+    assert_eq!(
+        err,
+        HeaderError::OutOfRange {
+            field: "dictionary",
+            addr: 0xfff0,
+            len: 0x1000
+        }
+    );
+    assert_eq!(err.to_string(), "The dictionary at 0xfff0 is past the end of the 4096 byte story file");
+
+    // the separators run past the end, so the entry count would too
+    let mut data = story(3);
+    put_word(&mut data, 0x08, 0xff0);
+    data[0xff0] = 20;
+    assert!(matches!(
+        StoryHeader::parse(&data),
+        Err(HeaderError::OutOfRange { field: "dictionary", addr: 0xff0, .. })
+    ));
+
+    let mut data = story(5);
+    put_word(&mut data, 0x36, 0xffe);
+    put_word(&mut data, 0xffe, 4);
+    assert!(matches!(
+        StoryHeader::parse(&data),
+        Err(HeaderError::OutOfRange { field: "header extension table", .. })
+    ));
+
+    // a story cut short of the length its header gives
+    let data = Game::story_data()[..0x10000].to_vec();
+    assert!(matches!(StoryHeader::parse(&data), Err(HeaderError::Truncated { .. })));
+}
+
+#[test]
+fn loading_checks_the_header_first() {
+    let ui = WebUI::with_sink(Box::new(VecSink::new()));
+    let err = Game::try_load_story(story(9), ui, Options::default()).err().unwrap();
+
+    assert_eq!(err.to_string(), "Unsupported game version: 9 (only 1 to 8 are)");
+
+    // the dictionary is read when the story loads, so it's checked with the header
+    let mut data = story(3);
+    put_word(&mut data, 0x08, 0xff0);
+    data[0xff0] = 20;
+    let ui = WebUI::with_sink(Box::new(VecSink::new()));
+    assert!(Game::try_load_story(data, ui, Options::default()).is_err());
+}
@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use encrusted::{Game, Options, TerminalUI, UI};
+
+/// Writer that keeps everything in a shared buffer the test can read back
+#[derive(Clone, Default)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl SharedOutput {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn print_wraps_words_at_width() {
+    let output = SharedOutput::default();
+    let mut ui = TerminalUI::with_output(Box::new(output.clone()), 20, false);
+
+    ui.print("The bedroom is a mess. It is a small bedroom");
+    ui.print(" with a faded carpet and old wallpaper.\n");
+    ui.flush();
+
+    let text = output.text();
+    assert!(text.lines().count() > 1, "Long text should wrap");
+
+    for line in text.lines() {
+        assert!(line.chars().count() <= 20, "Line too long: {:?}", line);
+        assert!(!line.starts_with(' '), "Wrapped line kept a space: {:?}", line);
+    }
+
+    let joined = text.lines().collect::<Vec<_>>().join(" ");
+    assert_eq!(
+        joined,
+        "The bedroom is a mess. It is a small bedroom with a faded carpet and old wallpaper."
+    );
+}
+
+#[test]
+fn flush_keeps_prompt_spacing() {
+    let output = SharedOutput::default();
+    let mut ui = TerminalUI::with_output(Box::new(output.clone()), 80, false);
+
+    ui.print("\n>");
+    ui.print(" ");
+    ui.flush();
+
+    assert_eq!(output.text(), "\n> ");
+}
+
+#[test]
+fn script_commands_are_echoed_and_played() {
+    let output = SharedOutput::default();
+    let mut ui = TerminalUI::with_output(Box::new(output.clone()), 80, false);
+    ui.set_script(vec!["turn on light".to_string()]);
+
+    let mut zvm = Game::load_story(Game::story_data().to_vec(), ui, Options::default());
+
+    zvm.step();
+    zvm.ui.flush();
+
+    let input = zvm.ui.get_user_input();
+    assert_eq!(input, "turn on light");
+    zvm.handle_input(input);
+
+    zvm.step();
+    zvm.ui.flush();
+
+    let text = output.text();
+    let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    assert!(text.contains(">turn on light\n"), "Script command should be echoed");
+    assert!(words.contains("The light is now on."), "Command should be played");
+}
+
+#[test]
+fn status_bar_and_screen_control_go_to_the_output() {
+    let output = SharedOutput::default();
+    let ui = TerminalUI::with_output(Box::new(output.clone()), 40, true);
+
+    ui.clear();
+    ui.set_status_bar("Bedroom", "Score: 0");
+    ui.reset();
+
+    let text = output.text();
+    assert!(text.contains("\x1B[2J"), "Clear should reach the output: {:?}", text);
+    assert!(text.contains(" Bedroom"));
+    assert!(text.contains("Score: 0 "));
+    assert!(text.ends_with("\x1B[r\x1B[0m"), "Reset should reach the output: {:?}", text);
+}
+
+#[test]
+fn transcript_gets_the_status_line_when_it_changes() {
+    let path = env::temp_dir().join(format!("encrusted-transcript-{}.txt", std::process::id()));
+    let output = SharedOutput::default();
+    let mut ui = TerminalUI::with_output(Box::new(output.clone()), 80, false);
+    ui.set_transcript(File::create(&path).unwrap());
+
+    ui.print("You wake up.\n");
+    ui.set_status_bar("Bedroom", "Score: 0");
+    ui.set_status_bar("Bedroom", "Score: 0");
+    ui.print(">");
+    ui.set_status_bar("Front of House", "Score: 0");
+    ui.flush();
+
+    let transcript = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(transcript, "You wake up.\n[Bedroom | Score: 0]\n>\n[Front of House | Score: 0]\n");
+    // the unstyled terminal has no status bar
+    assert_eq!(output.text(), "You wake up.\n>");
+}
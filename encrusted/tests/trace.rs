@@ -0,0 +1,95 @@
+mod common;
+
+use encrusted::{Options, StepOutcome, TraceRecord, Zmachine};
+
+fn game(opts: Options) -> Zmachine {
+    common::h2g2(opts)
+}
+
+#[test]
+fn tracing_is_off_by_default() {
+    let mut zvm = game(Options::default());
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+
+    assert!(!zvm.instr_log.is_enabled());
+    assert!(zvm.instr_log.is_empty());
+    assert_eq!(zvm.instr_log.to_text(), "");
+}
+
+#[test]
+fn keeps_the_last_instructions_with_their_values() {
+    let mut opts = Options::default();
+    opts.trace = 32;
+    let mut zvm = game(opts);
+
+    assert_eq!(zvm.run(usize::MAX), StepOutcome::NeedsLine);
+    assert_eq!(zvm.instr_log.len(), 32);
+
+    // the newest entry is the read the story is paused on, it hasn't run yet
+    let last = zvm.instr_log.last(1).next().unwrap();
+    assert_eq!(last.instr.name, "sread");
+    assert!(last.args.is_empty());
+
+    assert!(zvm.instr_log.entries().any(|entry| !entry.args.is_empty()));
+    assert!(zvm.instr_log.entries().any(|entry| entry.result.is_some()));
+    assert!(zvm
+        .instr_log
+        .entries()
+        .filter(|entry| !entry.args.is_empty())
+        .all(|entry| entry.to_text().contains("  ; [")));
+}
+
+#[test]
+fn exports_text_and_json() {
+    let mut opts = Options::default();
+    opts.trace = 8;
+    let mut zvm = game(opts);
+    zvm.run(usize::MAX);
+
+    let text = zvm.instr_log.to_text();
+    assert_eq!(text.lines().count(), 8);
+
+    let records: Vec<TraceRecord> = serde_json::from_str(&zvm.instr_log.to_json()).unwrap();
+    assert_eq!(records.len(), 8);
+    assert_eq!(records[7].opcode, "sread");
+
+    for (record, line) in records.iter().zip(text.lines()) {
+        assert!(line.starts_with(&record.text));
+    }
+}
+
+#[test]
+fn can_be_switched_at_runtime() {
+    let mut zvm = game(Options::default());
+    zvm.run(usize::MAX);
+    assert!(zvm.instr_log.is_empty());
+
+    zvm.set_trace(16);
+    zvm.handle_input("look".to_string());
+    zvm.run(usize::MAX);
+    assert_eq!(zvm.instr_log.len(), 16);
+
+    // shrinking keeps the newest entries
+    let newest = zvm.instr_log.last(4).map(|e| e.to_text()).collect::<Vec<_>>();
+    zvm.set_trace(4);
+    assert_eq!(zvm.instr_log.entries().map(|e| e.to_text()).collect::<Vec<_>>(), newest);
+
+    zvm.set_trace(0);
+    assert!(zvm.instr_log.is_empty());
+    zvm.handle_input("look".to_string());
+    zvm.run(usize::MAX);
+    assert!(zvm.instr_log.is_empty());
+}
+
+#[test]
+fn guarded_stories_keep_a_few_for_crash_reports() {
+    let mut opts = Options::default();
+    opts.guarded = true;
+    let mut zvm = game(opts);
+
+    // turning tracing off still leaves the crash history
+    zvm.set_trace(0);
+    zvm.run(usize::MAX);
+    assert!(zvm.instr_log.is_enabled());
+    assert!(!zvm.instr_log.is_empty());
+}
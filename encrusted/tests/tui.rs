@@ -0,0 +1,111 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::backend::TestBackend;
+use ratatui::Terminal;
+
+use encrusted::{Game, Options, TuiApp};
+
+fn new_app() -> TuiApp {
+    let mut app = TuiApp::new(Game::story_data().to_vec(), Options::default());
+    app.resume();
+    app
+}
+
+fn press(app: &mut TuiApp, code: KeyCode) -> bool {
+    app.handle_key(KeyEvent::from(code))
+}
+
+fn type_command(app: &mut TuiApp, command: &str) {
+    for c in command.chars() {
+        press(app, KeyCode::Char(c));
+    }
+    press(app, KeyCode::Enter);
+}
+
+/// Draws the app on a test terminal and returns the screen as text
+fn render(app: &mut TuiApp) -> String {
+    render_at(app, 100, 40)
+}
+
+fn render_at(app: &mut TuiApp, width: u16, height: u16) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(|frame| app.draw(frame)).unwrap();
+
+    let buffer = terminal.backend().buffer();
+    let width = buffer.area.width as usize;
+
+    buffer
+        .content()
+        .chunks(width)
+        .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn commands_are_played_in_the_story_pane() {
+    let mut app = new_app();
+    type_command(&mut app, "turn on light");
+
+    let screen = render(&mut app);
+    let words = screen.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    assert!(screen.contains("turn on light"), "Command should be echoed");
+    assert!(words.contains("The light is now on."), "Command should be played");
+    assert!(screen.lines().next().unwrap().contains("Bedroom"), "Status bar shows the room");
+}
+
+#[test]
+fn map_pane_can_be_toggled() {
+    let mut app = new_app();
+    type_command(&mut app, "turn on light");
+
+    assert!(render(&mut app).contains(" Map "));
+
+    press(&mut app, KeyCode::F(2));
+    assert!(!render(&mut app).contains(" Map "));
+
+    press(&mut app, KeyCode::F(2));
+    press(&mut app, KeyCode::F(3));
+    assert!(render(&mut app).contains(" All objects "));
+}
+
+#[test]
+fn hints_are_revealed_one_at_a_time() {
+    let mut app = new_app();
+    type_command(&mut app, "turn on light");
+
+    press(&mut app, KeyCode::F(1));
+    let screen = render(&mut app);
+    assert!(screen.contains("Hints: Bedroom"), "Hint browser should open");
+
+    // the second bedroom question has three answers
+    press(&mut app, KeyCode::Down);
+    assert!(render(&mut app).contains("Enter to reveal hint 1 of 3"));
+
+    press(&mut app, KeyCode::Enter);
+    assert!(render(&mut app).contains("Enter to reveal hint 2 of 3"));
+
+    press(&mut app, KeyCode::Left);
+    assert!(render(&mut app).contains("Enter to reveal hint 1 of 3"));
+
+    press(&mut app, KeyCode::Char('g'));
+    assert!(render(&mut app).contains("Hints: General"));
+
+    press(&mut app, KeyCode::Esc);
+    assert!(!render(&mut app).contains("Hints:"));
+}
+
+#[test]
+fn f10_quits_and_letters_are_typed() {
+    let mut app = new_app();
+    assert!(press(&mut app, KeyCode::Char('x')), "Letters go in the command line");
+    assert!(!press(&mut app, KeyCode::F(10)), "F10 quits");
+}
+
+#[test]
+fn hints_open_on_very_wide_terminals() {
+    let mut app = new_app();
+    press(&mut app, KeyCode::F(1));
+
+    assert!(render_at(&mut app, 1000, 40).contains("Hints:"));
+}
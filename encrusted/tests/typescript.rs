@@ -0,0 +1,25 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use encrusted::events::typescript_definitions;
+
+// The web app imports these types, so engine changes that reshape an event
+// fail here until the checked-in file is regenerated
+#[test]
+fn web_app_event_types_are_up_to_date() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../apps/web/src/types/events.ts");
+    let generated = typescript_definitions();
+
+    if env::var_os("UPDATE_TYPES").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &generated).unwrap();
+    }
+
+    let current = fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        current == generated,
+        "{} is out of date, regenerate it with: UPDATE_TYPES=1 cargo test --test typescript",
+        path.display()
+    );
+}
@@ -0,0 +1,174 @@
+use std::sync::mpsc;
+
+use encrusted::{
+    ChannelSink, Game, Options, OutputFormat, RunKind, RunStyle, TextRun, VecSink, WebUI, UI,
+};
+
+fn web_ui() -> (Box<WebUI>, VecSink) {
+    let sink = VecSink::new();
+    (WebUI::with_sink(Box::new(sink.clone())), sink)
+}
+
+fn flushed_html(ui: &mut WebUI, sink: &VecSink) -> String {
+    ui.flush();
+
+    let messages = sink.take();
+    assert_eq!(messages.len(), 1, "One flush sends one print message");
+    assert_eq!(messages[0].0, "print");
+    messages[0].1.clone()
+}
+
+#[test]
+fn flush_renders_text_and_newlines() {
+    let (mut ui, sink) = web_ui();
+    ui.print("Hello");
+    ui.print(" there");
+    ui.print("\n");
+    ui.print("Second line\n");
+
+    assert_eq!(
+        flushed_html(&mut ui, &sink),
+        "<span>Hello there</span><br><span>Second line</span><br>"
+    );
+}
+
+#[test]
+fn flush_escapes_html() {
+    let (mut ui, sink) = web_ui();
+    ui.print("<b>bold</b> & co\n>");
+
+    assert_eq!(
+        flushed_html(&mut ui, &sink),
+        "<span>&lt;b&gt;bold&lt;/b&gt; &amp; co</span><br><span>&gt;</span>"
+    );
+}
+
+#[test]
+fn objects_on_their_own_line_are_rooms() {
+    let (mut ui, sink) = web_ui();
+    ui.print_object("Bedroom");
+    ui.print("\n");
+    ui.print("There is a ");
+    ui.print_object("toothbrush");
+    ui.print(" here.\n");
+
+    assert_eq!(
+        flushed_html(&mut ui, &sink),
+        concat!(
+            r#"<span class="room">Bedroom</span><br>"#,
+            r#"<span>There is a </span><span class="object">toothbrush</span>"#,
+            r#"<span> here.</span><br>"#
+        )
+    );
+}
+
+#[test]
+fn debug_and_ascii_art_get_their_own_markup() {
+    let (mut ui, sink) = web_ui();
+    ui.debug("pc: 0x1234");
+    ui.print_ascii_art(" /\\\n<__>");
+
+    assert_eq!(
+        flushed_html(&mut ui, &sink),
+        r#"<span class="debug">pc: 0x1234</span><pre class="ascii-art"> /\
+&lt;__&gt;</pre>"#
+    );
+}
+
+#[test]
+fn empty_flush_sends_nothing() {
+    let (mut ui, sink) = web_ui();
+    ui.print("");
+    ui.flush();
+
+    assert!(sink.take().is_empty());
+}
+
+#[test]
+fn clue_book_advert_is_dropped() {
+    let (mut ui, sink) = web_ui();
+    ui.print("Buy the complete map and InvisiClues Hint Booklet today!");
+    ui.flush();
+
+    assert!(sink.take().is_empty());
+}
+
+#[test]
+fn status_bar_is_a_header_message() {
+    let (ui, sink) = web_ui();
+    ui.set_status_bar("Bedroom", "0/1");
+
+    assert_eq!(
+        sink.take(),
+        vec![("header".to_string(), r#"["Bedroom","0/1"]"#.to_string())]
+    );
+}
+
+#[test]
+fn tokens_format_sends_typed_runs() {
+    let (mut ui, sink) = web_ui();
+    ui.set_output_format(OutputFormat::Tokens);
+    ui.print_object("Bedroom");
+    ui.print("\n");
+    ui.print("There is a ");
+    ui.print("<small> ");
+    ui.print_object("toothbrush");
+    ui.print(" here.");
+    ui.debug("pc: 0x1234");
+    ui.print_ascii_art("<__>");
+    ui.flush();
+
+    let messages = sink.take();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].0, "tokens");
+
+    let runs: Vec<TextRun> = serde_json::from_str(&messages[0].1).unwrap();
+    assert_eq!(
+        runs,
+        vec![
+            TextRun::new(RunKind::Object, "Bedroom", RunStyle::Room),
+            TextRun::new(RunKind::Newline, "", RunStyle::Plain),
+            TextRun::new(RunKind::Text, "There is a <small> ", RunStyle::Plain),
+            TextRun::new(RunKind::Object, "toothbrush", RunStyle::Object),
+            TextRun::new(RunKind::Text, " here.", RunStyle::Plain),
+            TextRun::new(RunKind::Debug, "pc: 0x1234", RunStyle::Debug),
+            TextRun::new(RunKind::AsciiArt, "<__>", RunStyle::Preformatted),
+        ]
+    );
+}
+
+#[test]
+fn format_handle_switches_after_handover() {
+    let sink = VecSink::new();
+    let ui = WebUI::with_sink(Box::new(sink.clone()));
+    let format = ui.format_handle();
+    let mut zvm = Game::load_from_ui(ui, Options::default());
+
+    format.set(OutputFormat::Tokens);
+    zvm.step();
+    zvm.ui.flush();
+
+    let types: Vec<String> = sink.take().into_iter().map(|(mtype, _)| mtype).collect();
+    assert!(types.contains(&"tokens".to_string()));
+    assert!(!types.contains(&"print".to_string()));
+}
+
+#[test]
+fn channel_sink_receives_game_messages() {
+    let (tx, rx) = mpsc::channel();
+    let ui = WebUI::with_sink(Box::new(ChannelSink(tx)));
+    let mut zvm = Game::load_from_ui(ui, Options::default());
+
+    zvm.step();
+    zvm.ui.flush();
+
+    let types: Vec<String> = rx.try_iter().map(|(mtype, _)| mtype).collect();
+    assert!(types.contains(&"savestate".to_string()));
+    assert!(types.contains(&"print".to_string()));
+}
+
+#[test]
+#[should_panic(expected = "WebUI::with_sink")]
+fn web_ui_needs_a_sink() {
+    let _ = <WebUI as UI>::new();
+}
@@ -0,0 +1,102 @@
+use encrusted::zstring::{default_alphabet, encoded_length, ZStringDecoder, ZStringError};
+use encrusted::{encode_text, Buffer};
+
+// `words` encoded one after the other from 0x40, with static memory at 0x80
+fn strings(words: &[&[u8]]) -> (Buffer, Vec<usize>) {
+    let mut data = vec![0; 0x40];
+    let mut addrs = Vec::new();
+
+    for word in words {
+        addrs.push(data.len());
+        data.extend(encode_text(word, 3, &default_alphabet()));
+    }
+
+    data.resize(0x100, 0);
+    let mut memory = Buffer::new(data);
+    memory.set_regions(0x80, 0x80);
+
+    (memory, addrs)
+}
+
+#[test]
+fn strings_decode_with_shifts_and_escapes() {
+    let (memory, addrs) = strings(&[b"lamp", b"Ford", b"a,b", b"@"]);
+    let decoder = ZStringDecoder::new(default_alphabet(), 0, 0x80);
+
+    // encode_text pads with 5s, which decode to nothing
+    assert_eq!(&*decoder.decode(&memory, addrs[0]).unwrap(), "lamp");
+    assert_eq!(&*decoder.decode(&memory, addrs[1]).unwrap(), "Ford");
+    assert_eq!(&*decoder.decode(&memory, addrs[2]).unwrap(), "a,b");
+    assert_eq!(&*decoder.decode(&memory, addrs[3]).unwrap(), "@");
+    assert_eq!(encoded_length(&memory, addrs[0]), Ok(4));
+}
+
+#[test]
+fn dynamic_strings_are_decoded_again_when_they_change() {
+    let (mut memory, addrs) = strings(&[b"lamp"]);
+    let decoder = ZStringDecoder::new(default_alphabet(), 0, 0x80);
+
+    let first = decoder.decode(&memory, addrs[0]).unwrap();
+    let again = decoder.decode(&memory, addrs[0]).unwrap();
+    assert!(std::rc::Rc::ptr_eq(&first, &again), "Unchanged strings come from the cache");
+
+    memory.write(addrs[0], &encode_text(b"rope", 3, &default_alphabet()));
+    assert_eq!(&*decoder.decode(&memory, addrs[0]).unwrap(), "rope");
+    assert_eq!(decoder.len(), 1);
+}
+
+#[test]
+fn static_strings_are_decoded_once() {
+    let (mut memory, _) = strings(&[]);
+    memory.write(0x90, &encode_text(b"lamp", 3, &default_alphabet()));
+    let decoder = ZStringDecoder::new(default_alphabet(), 0, 0x80);
+
+    assert_eq!(&*decoder.decode(&memory, 0x90).unwrap(), "lamp");
+
+    // only the interpreter can write here, and it clears the cache when it does
+    memory.write(0x90, &encode_text(b"rope", 3, &default_alphabet()));
+    assert_eq!(&*decoder.decode(&memory, 0x90).unwrap(), "lamp");
+
+    decoder.clear();
+    assert_eq!(&*decoder.decode(&memory, 0x90).unwrap(), "rope");
+}
+
+#[test]
+fn abbreviations_are_expanded() {
+    // abbreviation 0 at word address 0x20 (byte 0x40) is "the"; the string
+    // at 0x50 is abbreviation 0 then " cat"
+    let mut data = vec![0; 0x100];
+    data[0x00..0x02].copy_from_slice(&[0x00, 0x20]);
+    data[0x40..0x44].copy_from_slice(&encode_text(b"the", 3, &default_alphabet()));
+
+    // zchars: 1 0 (abbrev 0), 0 (space), c a t
+    let words: [u16; 2] = [1 << 10, (8 << 10) | (6 << 5) | 25 | 0x8000];
+    for (n, word) in words.iter().enumerate() {
+        data[0x50 + n * 2..0x52 + n * 2].copy_from_slice(&word.to_be_bytes());
+    }
+
+    let memory = Buffer::new(data);
+    let decoder = ZStringDecoder::new(default_alphabet(), 0, 0x100);
+
+    assert_eq!(&*decoder.decode(&memory, 0x50).unwrap(), "the cat");
+    assert_eq!(&*decoder.abbreviation(&memory, 0).unwrap(), "the");
+}
+
+#[test]
+fn broken_strings_are_errors() {
+    // abbreviation 0 (at byte 0x40) uses abbreviation 0 itself
+    let mut data = vec![0; 0x100];
+    data[0x00..0x02].copy_from_slice(&[0x00, 0x20]);
+    data[0x40..0x42].copy_from_slice(&((1 << 10) | 0x8000u16).to_be_bytes());
+
+    // no stop bit before the end of memory
+    data[0xfe..0x100].copy_from_slice(&[0x14, 0xa5]);
+
+    let memory = Buffer::new(data);
+    let decoder = ZStringDecoder::new(default_alphabet(), 0, 0x100);
+
+    assert_eq!(decoder.abbreviation(&memory, 0), Err(ZStringError::NestedAbbreviation(0x40)));
+    assert_eq!(decoder.abbreviation(&memory, 96), Err(ZStringError::BadAbbreviation(96)));
+    assert!(matches!(decoder.decode(&memory, 0xfe), Err(ZStringError::Memory(_))));
+    assert!(encoded_length(&memory, 0xfe).is_err());
+}
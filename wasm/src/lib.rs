@@ -1,169 +1,399 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 
-use encrusted::{Game, Options, UI, Zmachine};
+use encrusted::events::{HostEvent, PROTOCOL_VERSION};
+use encrusted::{EventQueue, Game, Options, OutputFormat, StepOutcome, WebUI, Zmachine};
 
-// Thread-local game instance
-thread_local!(static ZVM: RefCell<Option<Zmachine>> = RefCell::new(None));
+// Game instance behind the module-level functions below
+thread_local!(static DEFAULT: RefCell<Option<GameSession>> = const { RefCell::new(None) });
 
-// Thread-local message store
-thread_local!(static MESSAGE_STORE: RefCell<std::collections::HashMap<String, String>> = RefCell::new(std::collections::HashMap::new()));
+/// Queue game state updates for JavaScript
+fn push_updates(zvm: &mut Zmachine) {
+    let (number, name) = zvm.get_current_room();
+    let tree = zvm.get_object_tree();
 
-/// Store a message from the game UI
-#[wasm_bindgen]
-pub fn store_message(msg_type: String, message: String) {
-    MESSAGE_STORE.with(|store| {
-        store.borrow_mut().insert(msg_type, message);
-    });
+    zvm.update_status_bar();
+    zvm.ui.event(HostEvent::Map(number, name));
+    zvm.ui.event(HostEvent::Tree(tree));
 }
 
-/// Get stored messages as JSON
+/// One game with its own event queue. Any number of these can run side by
+/// side, e.g. two save points next to each other or a sandbox preview.
 #[wasm_bindgen]
-pub fn get_messages() -> String {
-    MESSAGE_STORE.with(|store| {
-        let messages = store.borrow();
-        serde_json::to_string(&*messages).unwrap_or_else(|_| "{}".to_string())
-    })
+pub struct GameSession {
+    zvm: Zmachine,
+    events: Rc<RefCell<EventQueue>>,
+    format: Rc<Cell<OutputFormat>>,
 }
 
-/// Clear all stored messages
 #[wasm_bindgen]
-pub fn clear_messages() {
-    MESSAGE_STORE.with(|store| {
-        store.borrow_mut().clear();
-    });
+impl GameSession {
+    /// Initialize a new game
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> GameSession {
+        // the JS sink: messages are queued for poll_events()
+        let events = Rc::new(RefCell::new(EventQueue::new()));
+        let queue = Rc::clone(&events);
+        let ui = WebUI::with_sink(Box::new(move |mtype: &str, msg: &str| {
+            queue.borrow_mut().push_message(mtype, msg);
+        }));
+        let format = ui.format_handle();
+        // faults roll back to the last prompt instead of ending the game
+        let mut opts = Options::default();
+        opts.guarded = true;
+
+        GameSession {
+            zvm: Game::load_from_ui(ui, opts),
+            events,
+            format,
+        }
+    }
+
+    /// Execute one step of the game, returns true once it's over
+    pub fn step(&mut self) -> bool {
+        // there's no debugger behind step(), so breakpoints don't stop it
+        let outcome = loop {
+            match self.zvm.run(usize::MAX) {
+                StepOutcome::Break(_) => continue,
+                outcome => break outcome,
+            }
+        };
+        self.zvm.ui.flush();
+
+        // a fault with nothing to roll back to already sent its crash event,
+        // and the pc is stuck on the faulty instruction
+        if let StepOutcome::Error(_) = outcome {
+            return true;
+        }
+
+        push_updates(&mut self.zvm);
+        outcome == StepOutcome::Quit
+    }
+
+    /// Execute at most `budget` instructions, returns the StepOutcome as
+    /// JSON: `{"outcome": "yielded"}` means call run again (e.g. next frame)
+    pub fn run(&mut self, budget: u32) -> String {
+        let outcome = self.zvm.run(budget as usize);
+        self.zvm.ui.flush();
+
+        // the room and tree only change meaningfully once the turn is over
+        if outcome != StepOutcome::Yielded {
+            push_updates(&mut self.zvm);
+        }
+
+        serde_json::to_string(&outcome).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Send player input to the game
+    pub fn feed(&mut self, input: String) {
+        self.zvm.handle_input(input);
+    }
+
+    /// Take this session's queued events as a JSON array of `{seq, type, data}`
+    pub fn poll_events(&mut self) -> String {
+        self.events.borrow_mut().drain_json()
+    }
+
+    /// Send output as "html" (`print` events) or "tokens" (`tokens` events
+    /// with typed text runs), returns false for unknown formats
+    pub fn set_output_format(&mut self, format: String) -> bool {
+        match format.as_str() {
+            "html" => self.format.set(OutputFormat::Html),
+            "tokens" => self.format.set(OutputFormat::Tokens),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Save game state
+    pub fn save(&self) -> Option<String> {
+        self.zvm.get_save_state()
+    }
+
+    /// Restore game from base64 save state
+    pub fn restore(&mut self, b64_data: String) {
+        self.zvm.restore(&b64_data);
+    }
+
+    /// Load save state from base64
+    pub fn load_savestate(&mut self, b64_data: String) {
+        self.zvm.load_savestate(&b64_data);
+    }
+
+    /// Get current game updates
+    pub fn get_updates(&mut self) {
+        push_updates(&mut self.zvm);
+    }
+
+    /// Get ASCII art for current room
+    pub fn get_room_ascii_art(&self) -> Option<String> {
+        self.zvm.get_current_room_ascii_art().map(|s| s.to_string())
+    }
+
+    /// Undo last move
+    pub fn undo(&mut self) -> bool {
+        self.zvm.undo()
+    }
+
+    /// Redo last undone move
+    pub fn redo(&mut self) -> bool {
+        self.zvm.redo()
+    }
+
+    /// Get current location name
+    pub fn get_location(&self) -> String {
+        let (_, name) = self.zvm.get_current_room();
+        name
+    }
+
+    /// Get hint questions for a location as JSON
+    pub fn get_hints_for_location(&mut self, location: String) -> String {
+        let questions = self.zvm.get_hint_system().get_questions_for_location(&location);
+        serde_json::to_string(&questions).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Get specific hint answer at a level
+    pub fn get_hint_answer(&mut self, question_idx: usize, level: usize) -> Option<String> {
+        self.zvm.get_hint_system().get_answer_at_level(question_idx, level)
+    }
+
+    /// Trace the last `capacity` instructions, 0 turns tracing off
+    pub fn set_trace(&mut self, capacity: u32) {
+        self.zvm.set_trace(capacity as usize);
+    }
+
+    /// Get the instruction trace as "text" or "json"
+    pub fn get_trace(&self, format: String) -> String {
+        match format.as_str() {
+            "json" => self.zvm.instr_log.to_json(),
+            _ => self.zvm.instr_log.to_text(),
+        }
+    }
+
+    /// Run a debugger command (try "help"), returns what to show. After
+    /// step/next/finish/continue call run() again
+    pub fn debug(&mut self, command: String) -> String {
+        match self.zvm.debug_command(&command) {
+            Ok(output) => output,
+            Err(err) => err,
+        }
+    }
+
+    /// Next instruction and the current frame, for showing debugger stops
+    pub fn debug_location(&self) -> String {
+        self.zvm.debug_location()
+    }
+}
+
+impl Default for GameSession {
+    fn default() -> Self {
+        GameSession::new()
+    }
 }
 
-/// Execute function with mutable access to the game instance
+/// Execute function with mutable access to the default game instance
 fn with<F, R>(func: F) -> R
 where
-    F: FnOnce(&mut Zmachine) -> R,
+    F: FnOnce(&mut GameSession) -> R,
 {
-    ZVM.with(|cell| {
+    DEFAULT.with(|cell| {
         let mut wrapper = cell.borrow_mut();
-        let zvm: &mut Zmachine = wrapper
+        let session: &mut GameSession = wrapper
             .as_mut()
             .expect("Game instance not initialized");
-        func(zvm)
+        func(session)
     })
 }
 
-/// Send game state updates to JavaScript
-fn push_updates(zvm: &mut Zmachine) {
-    let map = serde_json::to_string(&zvm.get_current_room())
-        .unwrap_or_else(|_| "{}".to_string());
-    let tree = serde_json::to_string(&zvm.get_object_tree())
-        .unwrap_or_else(|_| "{}".to_string());
+/// Same as `with`, but falls back to a default value before create()
+fn with_or<F, R>(fallback: R, func: F) -> R
+where
+    F: FnOnce(&mut GameSession) -> R,
+{
+    DEFAULT.with(|cell| match cell.borrow_mut().as_mut() {
+        Some(session) => func(session),
+        None => fallback,
+    })
+}
 
-    zvm.update_status_bar();
-    zvm.ui.message("map", &map);
-    zvm.ui.message("tree", &tree);
+/// Version of the event format, see PROTOCOL_VERSION in apps/web/src/types/events.ts
+#[wasm_bindgen]
+pub fn protocol_version() -> u32 {
+    PROTOCOL_VERSION
 }
 
 /// Initialize the game
 #[wasm_bindgen]
 pub fn create() {
-    ZVM.with(|cell| {
-        let ui = encrusted::ui_web::WebUI::new();
-        let opts = Options::default();
-        let zvm = Game::load_from_ui(ui, opts);
-        *cell.borrow_mut() = Some(zvm);
+    DEFAULT.with(|cell| {
+        *cell.borrow_mut() = Some(GameSession::new());
     });
 }
 
+/// Take all queued events as a JSON array of `{seq, type, data}`
+#[wasm_bindgen]
+pub fn poll_events() -> String {
+    with_or("[]".to_string(), |session| session.poll_events())
+}
+
 /// Execute one step of the game
 #[wasm_bindgen]
 pub fn step() -> bool {
-    with(|zvm| {
-        let done = zvm.step();
-        zvm.ui.flush();
-        push_updates(zvm);
-        done
-    })
+    with(|session| session.step())
+}
+
+/// Execute at most `budget` instructions, returns the StepOutcome as JSON
+#[wasm_bindgen]
+pub fn run(budget: u32) -> String {
+    with(|session| session.run(budget))
 }
 
 /// Send player input to the game
 #[wasm_bindgen]
 pub fn feed(input: String) {
-    with(|zvm| zvm.handle_input(input));
+    with(|session| session.feed(input));
+}
+
+/// Send output as "html" or "tokens"
+#[wasm_bindgen]
+pub fn set_output_format(format: String) -> bool {
+    with(|session| session.set_output_format(format))
 }
 
 /// Save game state
 #[wasm_bindgen]
 pub fn save() -> Option<String> {
-    with(|zvm| zvm.get_save_state())
+    with(|session| session.save())
 }
 
 /// Restore game from base64 save state
 #[wasm_bindgen]
 pub fn restore(b64_data: String) {
-    with(|zvm| zvm.restore(&b64_data));
+    with(|session| session.restore(b64_data));
 }
 
 /// Load save state from base64
 #[wasm_bindgen]
 pub fn load_savestate(b64_data: String) {
-    with(|zvm| zvm.load_savestate(&b64_data));
+    with(|session| session.load_savestate(b64_data));
 }
 
 /// Get current game updates
 #[wasm_bindgen]
 pub fn get_updates() {
-    with(|zvm| push_updates(zvm));
+    with(|session| session.get_updates());
 }
 
 /// Get ASCII art for current room
 #[wasm_bindgen]
 pub fn get_room_ascii_art() -> Option<String> {
-    with(|zvm| zvm.get_current_room_ascii_art().map(|s| s.to_string()))
+    with(|session| session.get_room_ascii_art())
 }
 
 /// Undo last move
 #[wasm_bindgen]
 pub fn undo() -> bool {
-    with(|zvm| zvm.undo())
+    with(|session| session.undo())
 }
 
 /// Redo last undone move
 #[wasm_bindgen]
 pub fn redo() -> bool {
-    with(|zvm| zvm.redo())
+    with(|session| session.redo())
 }
 
 /// Get current location name
 #[wasm_bindgen]
 pub fn get_location() -> String {
-    ZVM.with(|cell| {
-        if let Some(zvm) = cell.borrow_mut().as_mut() {
-            let (_, name) = zvm.get_current_room();
-            name
-        } else {
-            String::new()
-        }
-    })
+    with_or(String::new(), |session| session.get_location())
 }
 
 /// Get hint questions for a location as JSON
 #[wasm_bindgen]
 pub fn get_hints_for_location(location: String) -> String {
-    ZVM.with(|cell| {
-        if let Some(zvm) = cell.borrow_mut().as_mut() {
-            let questions = zvm.get_hint_system().get_questions_for_location(&location);
-            serde_json::to_string(&questions).unwrap_or_else(|_| "[]".to_string())
-        } else {
-            "[]".to_string()
-        }
-    })
+    with_or("[]".to_string(), |session| session.get_hints_for_location(location))
 }
 
 /// Get specific hint answer at a level
 #[wasm_bindgen]
 pub fn get_hint_answer(question_idx: usize, level: usize) -> Option<String> {
-    ZVM.with(|cell| {
-        if let Some(zvm) = cell.borrow_mut().as_mut() {
-            zvm.get_hint_system().get_answer_at_level(question_idx, level)
-        } else {
-            None
+    with_or(None, |session| session.get_hint_answer(question_idx, level))
+}
+
+/// Trace the last `capacity` instructions, 0 turns tracing off
+#[wasm_bindgen]
+pub fn set_trace(capacity: u32) {
+    with(|session| session.set_trace(capacity));
+}
+
+/// Get the instruction trace as "text" or "json"
+#[wasm_bindgen]
+pub fn get_trace(format: String) -> String {
+    with_or(String::new(), |session| session.get_trace(format))
+}
+
+/// Run a debugger command (try "help"), returns what to show
+#[wasm_bindgen]
+pub fn debug(command: String) -> String {
+    with_or(String::new(), |session| session.debug(command))
+}
+
+/// Next instruction and the current frame, for showing debugger stops
+#[wasm_bindgen]
+pub fn debug_location() -> String {
+    with_or(String::new(), |session| session.debug_location())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    // Runs to the next prompt, returns the text printed on the way
+    fn play(session: &mut GameSession, input: Option<&str>) -> String {
+        if let Some(input) = input {
+            session.feed(input.to_string());
         }
-    })
+        session.run(u32::MAX);
+
+        let events: Vec<Value> = serde_json::from_str(&session.poll_events()).unwrap();
+        events
+            .iter()
+            .filter(|event| event["type"] == "print")
+            .map(|event| event["data"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn sessions_keep_their_messages_apart() {
+        let mut first = GameSession::new();
+        let mut second = GameSession::new();
+
+        assert!(play(&mut first, None).contains("You wake up."));
+        assert!(play(&mut second, None).contains("You wake up."));
+
+        assert!(play(&mut first, Some("turn on light")).contains("The light is now on."));
+        assert_eq!(second.poll_events(), "[]", "Nothing was queued for the other session");
+
+        let text = play(&mut second, Some("inventory"));
+        assert!(!text.contains("The light is now on."));
+        assert_eq!(first.poll_events(), "[]");
+
+        assert_eq!(first.get_location(), "Bedroom");
+        assert!(first.save() != second.save());
+    }
+
+    #[test]
+    fn the_default_session_is_separate_too() {
+        create();
+        let mut other = GameSession::new();
+
+        run(u32::MAX);
+        assert!(poll_events().contains("You wake up."));
+        assert_eq!(other.poll_events(), "[]");
+
+        play(&mut other, None);
+        assert_eq!(poll_events(), "[]");
+    }
 }